@@ -4,7 +4,7 @@
 //!
 //! ## Example
 //!
-//! ```
+//! ```rust,no_run
 //! # #[tokio::main(flavor = "multi_thread")]
 //! # async fn main() {
 //! use influxive_core::Metric;
@@ -33,11 +33,25 @@ use std::io::Result;
 #[cfg(feature = "download_binaries")]
 mod download_binaries;
 
+#[cfg(feature = "serde")]
+mod model;
+#[cfg(feature = "serde")]
+pub use model::*;
+
 use influxive_core::*;
 use influxive_writer::*;
 
 pub use influxive_writer::InfluxiveWriterConfig;
 
+pub use influxive_writer::{
+    FluxAggregate, FluxFilter, FluxQuery, FluxTime, FluxValue,
+    InfluxiveQueryClient, QueryColumn, QueryDataType, QueryRecord, QueryStream,
+    QueryTable, QueryValue,
+};
+
+#[cfg(feature = "serde")]
+pub use influxive_writer::InfluxiveWriterConfigModel;
+
 macro_rules! cmd_output {
     ($cmd:expr $(,$arg:expr)*) => {async {
         let mut proc = tokio::process::Command::new($cmd);
@@ -203,6 +217,7 @@ pub struct InfluxiveChildSvc {
     child: std::sync::Mutex<Option<tokio::process::Child>>,
     influx_path: std::path::PathBuf,
     writer: InfluxiveWriter,
+    query_client: InfluxiveQueryClient,
 }
 
 impl InfluxiveChildSvc {
@@ -268,7 +283,8 @@ impl InfluxiveChildSvc {
             &token,
         );
 
-        let bucket = config.bucket.clone();
+        let query_client =
+            InfluxiveQueryClient::new(&host, &config.org, &token);
 
         let this = Self {
             config,
@@ -277,6 +293,7 @@ impl InfluxiveChildSvc {
             child: std::sync::Mutex::new(Some(child)),
             influx_path,
             writer,
+            query_client,
         };
 
         let mut millis = 10;
@@ -288,16 +305,19 @@ impl InfluxiveChildSvc {
                     .with_field("value", true),
             );
 
-            if let Ok(result) = this
-                .query(format!(
-                    r#"from(bucket: "{bucket}")
-|> range(start: -15m, stop: now())
-|> filter(fn: (r) => r["_measurement"] == "influxive.start")
-|> filter(fn: (r) => r["_field"] == "value")"#,
-                ))
+            if let Ok(tables) = this
+                .query_tables(
+                    FluxQuery::from_bucket(&this.config.bucket)
+                        .range_between(
+                            FluxTime::Ago(std::time::Duration::from_secs(900)),
+                            FluxTime::Now,
+                        )
+                        .filter(FluxFilter::measurement("influxive.start"))
+                        .filter(FluxFilter::field("value")),
+                )
                 .await
             {
-                if result.split('\n').count() >= 3 {
+                if tables.iter().any(|t| !t.records().is_empty()) {
                     return Ok(this);
                 }
             }
@@ -335,25 +355,54 @@ impl InfluxiveChildSvc {
         Ok(())
     }
 
-    /// Run a query against the running InfluxDB instance.
+    /// Get the HTTP query client of this running influxd instance.
+    pub fn query_client(&self) -> &InfluxiveQueryClient {
+        &self.query_client
+    }
+
+    /// Run a query against the running InfluxDB instance, returning the
+    /// raw annotated CSV response, see [InfluxiveQueryClient::query_raw].
     /// Note, if you are writing metrics, prefer the 'write_metric' api
     /// as that will be more efficient.
     pub async fn query<Q: Into<StringType>>(
         &self,
         flux_query: Q,
     ) -> Result<String> {
-        cmd_output!(
-            &self.influx_path,
-            "query",
-            "--raw",
-            "--org",
-            &self.config.org,
-            "--host",
-            &self.host,
-            "--token",
-            &self.token,
-            flux_query.into().into_string()
-        )
+        self.query_client.query_raw(flux_query).await
+    }
+
+    /// Run a query against the running InfluxDB instance, returning the
+    /// typed tables of the result, see [InfluxiveQueryClient::query].
+    pub async fn query_tables<Q: Into<StringType>>(
+        &self,
+        flux_query: Q,
+    ) -> Result<Vec<QueryTable>> {
+        self.query_client.query(flux_query).await
+    }
+
+    /// Run a query against the running InfluxDB instance, returning a
+    /// stream of the records of the result as the response arrives,
+    /// see [InfluxiveQueryClient::query_stream]. The stream fails if the
+    /// server stalls for longer than the default
+    /// [InfluxiveQueryClient::with_timeout].
+    pub async fn query_stream<Q: Into<StringType>>(
+        &self,
+        flux_query: Q,
+    ) -> Result<QueryStream> {
+        self.query_client.query_stream(flux_query).await
+    }
+
+    /// Run a query against the running InfluxDB instance, deserializing
+    /// each record of the result, see [InfluxiveQueryClient::query_as].
+    #[cfg(feature = "serde")]
+    pub async fn query_as<
+        T: serde::de::DeserializeOwned,
+        Q: Into<StringType>,
+    >(
+        &self,
+        flux_query: Q,
+    ) -> Result<Vec<T>> {
+        self.query_client.query_as(flux_query).await
     }
 
     /// List the existing dashboard data in the running InfluxDB instance.
@@ -0,0 +1,92 @@
+use super::*;
+
+/// Data-only description of an [InfluxiveChildSvcConfig], e.g. loaded
+/// from a TOML or YAML file. Missing values take the
+/// [InfluxiveChildSvcConfig] defaults.
+///
+/// ```toml
+/// database_path = "/var/lib/influxive"
+/// retention = "168h"
+///
+/// [metric_write]
+/// batch_duration = "1s"
+/// ```
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct InfluxiveChildSvcConfigModel {
+    /// See [InfluxiveChildSvcConfig::download_binaries].
+    #[cfg(feature = "download_binaries")]
+    pub download_binaries: bool,
+
+    /// See [InfluxiveChildSvcConfig::influxd_path].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub influxd_path: Option<std::path::PathBuf>,
+
+    /// See [InfluxiveChildSvcConfig::influx_path].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub influx_path: Option<std::path::PathBuf>,
+
+    /// See [InfluxiveChildSvcConfig::database_path].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub database_path: Option<std::path::PathBuf>,
+
+    /// See [InfluxiveChildSvcConfig::user].
+    pub user: String,
+
+    /// See [InfluxiveChildSvcConfig::pass].
+    pub pass: String,
+
+    /// See [InfluxiveChildSvcConfig::org].
+    pub org: String,
+
+    /// See [InfluxiveChildSvcConfig::bucket].
+    pub bucket: String,
+
+    /// See [InfluxiveChildSvcConfig::retention], an influx duration
+    /// like `72h` or `4w`, or `0` for infinite retention.
+    pub retention: String,
+
+    /// See [InfluxiveChildSvcConfig::metric_write]. The backend defaults
+    /// to writing to the child process.
+    pub metric_write: InfluxiveWriterConfigModel,
+}
+
+impl Default for InfluxiveChildSvcConfigModel {
+    fn default() -> Self {
+        let config = InfluxiveChildSvcConfig::default();
+        Self {
+            #[cfg(feature = "download_binaries")]
+            download_binaries: config.download_binaries,
+            influxd_path: config.influxd_path,
+            influx_path: config.influx_path,
+            database_path: config.database_path,
+            user: config.user,
+            pass: config.pass,
+            org: config.org,
+            bucket: config.bucket,
+            retention: config.retention,
+            metric_write: InfluxiveWriterConfigModel::default(),
+        }
+    }
+}
+
+impl TryFrom<InfluxiveChildSvcConfigModel> for InfluxiveChildSvcConfig {
+    type Error = std::io::Error;
+
+    fn try_from(model: InfluxiveChildSvcConfigModel) -> Result<Self> {
+        Ok(Self {
+            #[cfg(feature = "download_binaries")]
+            download_binaries: model.download_binaries,
+            influxd_path: model.influxd_path,
+            influx_path: model.influx_path,
+            database_path: model.database_path,
+            user: model.user,
+            pass: model.pass,
+            org: model.org,
+            bucket: model.bucket,
+            retention: model.retention,
+            metric_write: model.metric_write.try_into()?,
+        })
+    }
+}
@@ -4,6 +4,10 @@ const DASHBOARD_TEMPLATE: &[u8] =
     include_bytes!("test_dashboard_template.json");
 
 #[tokio::test(flavor = "multi_thread")]
+#[cfg_attr(
+    not(feature = "network-tests"),
+    ignore = "downloads and runs influxd"
+)]
 async fn sanity() {
     let tmp = tempfile::tempdir().unwrap();
 
@@ -64,3 +68,34 @@ async fn sanity() {
     // okay if this fails on windows...
     let _ = tmp.close();
 }
+
+#[cfg(feature = "serde")]
+#[test]
+fn config_model() {
+    let model: InfluxiveChildSvcConfigModel = toml::from_str(
+        r#"
+database_path = "/var/lib/influxive"
+retention = "168h"
+
+[metric_write]
+batch_duration = "1s"
+
+[metric_write.default_tags]
+service = "my.service"
+"#,
+    )
+    .unwrap();
+
+    let config = InfluxiveChildSvcConfig::try_from(model).unwrap();
+    assert_eq!(
+        Some(std::path::PathBuf::from("/var/lib/influxive")),
+        config.database_path
+    );
+    assert_eq!("168h", config.retention);
+    assert_eq!("influxive", config.bucket);
+    assert_eq!(
+        std::time::Duration::from_secs(1),
+        config.metric_write.batch_duration
+    );
+    assert_eq!(1, config.metric_write.default_tags.len());
+}
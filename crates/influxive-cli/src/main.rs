@@ -0,0 +1,400 @@
+#![deny(missing_docs)]
+#![deny(warnings)]
+#![deny(unsafe_code)]
+//! Command-line tool for writing, replaying and querying InfluxDB metrics.
+//!
+//! Installs an `influxive` binary exposing the influxive crates from the
+//! shell:
+//!
+//! - `influxive write` writes a single metric built from arguments.
+//! - `influxive replay` loads a Line Protocol file into a server,
+//!   optionally resuming from a checkpoint.
+//! - `influxive validate` checks a Line Protocol file, reporting each
+//!   invalid line.
+//! - `influxive child` runs influxd as a child process and prints its
+//!   host and token.
+//! - `influxive query` runs a Flux query against a server, e.g. the one
+//!   started by `influxive child`, given its `--host` and `--token`. With
+//!   `--spawn`, it instead starts a child instance just for the query.
+//!
+//! `write` and `replay` take the destination as a `--url`, see
+//! [InfluxiveWriterConfig::from_url](https://docs.rs/influxive-writer/latest/influxive_writer/struct.InfluxiveWriterConfig.html#method.from_url),
+//! or if it is not given, from the environment, see
+//! [InfluxiveWriterConfig::from_env](https://docs.rs/influxive-writer/latest/influxive_writer/struct.InfluxiveWriterConfig.html#method.from_env).
+//!
+//! ## Examples
+//!
+//! ```text
+//! influxive child --database-path ./influxive
+//!
+//! influxive write --url "influx+http://${TOKEN}@127.0.0.1:8086/influxive" \
+//!     my.metric --tag host=a --field value=3.14 --field count=3i
+//!
+//! influxive validate my-metrics.influx
+//! influxive replay --url "influx+http://${TOKEN}@127.0.0.1:8086/influxive" \
+//!     --checkpoint my-metrics.checkpoint my-metrics.influx
+//!
+//! influxive query --host http://127.0.0.1:8086 --token "${TOKEN}" \
+//!     'from(bucket: "influxive") |> range(start: -15m)'
+//! ```
+//!
+//! Field values are parsed like Line Protocol: `3i` is a signed and `3u`
+//! an unsigned integer, `true` and `false` are booleans, other numbers
+//! are floats and anything else, optionally double-quoted, is a string.
+
+use clap::{Args, Parser, Subcommand};
+use influxive_child_svc::{InfluxiveChildSvc, InfluxiveChildSvcConfig};
+use influxive_core::{DataType, Metric};
+use influxive_writer::types::Precision;
+use influxive_writer::*;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+#[derive(Debug, Parser)]
+#[command(name = "influxive", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    cmd: Cmd,
+}
+
+#[derive(Debug, Subcommand)]
+enum Cmd {
+    /// Write a single metric.
+    Write(WriteArgs),
+
+    /// Load a Line Protocol file into a server.
+    Replay(ReplayArgs),
+
+    /// Check a Line Protocol file, reporting each invalid line.
+    Validate(ValidateArgs),
+
+    /// Run influxd as a child process, printing its host and token.
+    Child(ChildArgs),
+
+    /// Run a Flux query against a server.
+    Query(QueryArgs),
+}
+
+#[derive(Debug, Args)]
+struct TargetArgs {
+    /// Destination url, e.g. `influx+http://{token}@{host}:{port}/{bucket}`,
+    /// defaults to the INFLUXIVE_URL or INFLUX_* environment variables.
+    #[arg(long)]
+    url: Option<String>,
+}
+
+impl TargetArgs {
+    fn config(&self) -> std::io::Result<InfluxiveWriterConfig> {
+        match &self.url {
+            Some(url) => InfluxiveWriterConfig::from_url(url),
+            None => InfluxiveWriterConfig::from_env(),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+struct WriteArgs {
+    #[command(flatten)]
+    target: TargetArgs,
+
+    /// Measurement name.
+    name: String,
+
+    /// A `key=value` tag, may be repeated.
+    #[arg(
+        short,
+        long = "tag",
+        value_name = "KEY=VALUE",
+        value_parser = parse_tag
+    )]
+    tags: Vec<(String, String)>,
+
+    /// A `key=value` field, may be repeated.
+    #[arg(
+        short,
+        long = "field",
+        value_name = "KEY=VALUE",
+        required = true,
+        value_parser = parse_field
+    )]
+    fields: Vec<(String, DataType)>,
+
+    /// Unix timestamp in nanoseconds, defaults to now.
+    #[arg(long)]
+    timestamp: Option<u64>,
+}
+
+#[derive(Debug, Args)]
+struct ReplayArgs {
+    #[command(flatten)]
+    target: TargetArgs,
+
+    /// Line Protocol file to replay.
+    path: PathBuf,
+
+    /// Record progress in this file, resuming from it on the next run.
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// Timestamp precision of the file: `ns`, `us`, `ms` or `s`.
+    #[arg(long, default_value = "ns")]
+    precision: Precision,
+
+    /// Skip invalid lines instead of stopping at the first one.
+    #[arg(long)]
+    skip_invalid: bool,
+}
+
+#[derive(Debug, Args)]
+struct ValidateArgs {
+    /// Line Protocol file to check.
+    path: PathBuf,
+
+    /// Timestamp precision of the file: `ns`, `us`, `ms` or `s`.
+    #[arg(long, default_value = "ns")]
+    precision: Precision,
+}
+
+#[derive(Debug, Args)]
+struct ChildArgs {
+    /// Path to influx database files and config directory,
+    /// defaults to `./influxive`.
+    #[arg(long)]
+    database_path: Option<PathBuf>,
+
+    /// Path to the influxd binary, defaults to the one on the path.
+    #[arg(long)]
+    influxd_path: Option<PathBuf>,
+
+    /// Path to the influx cli binary, defaults to the one on the path.
+    #[arg(long)]
+    influx_path: Option<PathBuf>,
+
+    /// Do not download influx release binaries if none are found.
+    #[arg(long)]
+    no_download: bool,
+
+    /// Influx initial username.
+    #[arg(long, default_value = "influxive")]
+    user: String,
+
+    /// Influx initial password.
+    #[arg(long, default_value = "influxive")]
+    pass: String,
+
+    /// Influx initial organization name.
+    #[arg(long, default_value = "influxive")]
+    org: String,
+
+    /// Influx initial bucket name.
+    #[arg(long, default_value = "influxive")]
+    bucket: String,
+
+    /// Retention timespan.
+    #[arg(long, default_value = "72h")]
+    retention: String,
+}
+
+impl ChildArgs {
+    fn config(&self) -> InfluxiveChildSvcConfig {
+        InfluxiveChildSvcConfig::default()
+            .with_database_path(self.database_path.clone())
+            .with_influxd_path(self.influxd_path.clone())
+            .with_influx_path(self.influx_path.clone())
+            .with_download_binaries(!self.no_download)
+            .with_user(self.user.clone())
+            .with_pass(self.pass.clone())
+            .with_org(self.org.clone())
+            .with_bucket(self.bucket.clone())
+            .with_retention(self.retention.clone())
+    }
+}
+
+#[derive(Debug, Args)]
+struct QueryArgs {
+    /// Host of the server to query, e.g. as printed by `influxive child`.
+    #[arg(long, requires = "token", required_unless_present = "spawn")]
+    host: Option<String>,
+
+    /// Token to authenticate with, e.g. as printed by `influxive child`.
+    #[arg(long, requires = "host")]
+    token: Option<String>,
+
+    /// Instead of querying a running server, start a child instance on
+    /// `--database-path` for this query. It can't be opened while another
+    /// instance, e.g. of `influxive child`, holds the database.
+    #[arg(long, conflicts_with = "host")]
+    spawn: bool,
+
+    // options of the child instance, `--org` also applies to `--host`
+    #[command(flatten)]
+    child: ChildArgs,
+
+    /// Flux query to run, or `-` to read it from stdin.
+    flux: String,
+}
+
+fn invalid(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, msg)
+}
+
+fn parse_tag(s: &str) -> std::io::Result<(String, String)> {
+    match s.split_once('=') {
+        Some((k, v)) if !k.is_empty() => Ok((k.to_string(), v.to_string())),
+        _ => Err(invalid(format!("expected key=value, got {s:?}"))),
+    }
+}
+
+fn parse_field(s: &str) -> std::io::Result<(String, DataType)> {
+    let (k, v) = parse_tag(s)?;
+    Ok((k, parse_value(&v)))
+}
+
+fn parse_value(v: &str) -> DataType {
+    if let Some(i) = v.strip_suffix('i').and_then(|i| i.parse().ok()) {
+        return DataType::I64(i);
+    }
+    if let Some(u) = v.strip_suffix('u').and_then(|u| u.parse().ok()) {
+        return DataType::U64(u);
+    }
+    match v {
+        "true" => return DataType::Bool(true),
+        "false" => return DataType::Bool(false),
+        _ => (),
+    }
+    if let Ok(f) = v.parse::<f64>() {
+        if f.is_finite() {
+            return DataType::F64(f);
+        }
+    }
+    let v = v
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(v);
+    DataType::String(v.to_string().into())
+}
+
+async fn write(args: WriteArgs) -> std::io::Result<()> {
+    let timestamp = match args.timestamp {
+        Some(ns) => std::time::UNIX_EPOCH + std::time::Duration::from_nanos(ns),
+        None => std::time::SystemTime::now(),
+    };
+    let mut metric = Metric::new(timestamp, args.name);
+    for (k, v) in args.tags {
+        metric = metric.with_tag(k, v);
+    }
+    for (k, v) in args.fields {
+        metric = metric.with_field(k, v);
+    }
+
+    let writer =
+        InfluxiveWriter::with_token_auth(args.target.config()?, "", "", "");
+    writer.write_metric(metric);
+    writer.flush().await.map_err(std::io::Error::other)?;
+    Ok(())
+}
+
+async fn replay(args: ReplayArgs) -> std::io::Result<()> {
+    let config = args.target.config()?;
+    let mut backend = config.backend.with_token_auth(
+        String::new(),
+        String::new(),
+        String::new(),
+    );
+    let progress = LineProtocolReplay::new(args.path)
+        .with_checkpoint_path(args.checkpoint)
+        .with_precision(args.precision)
+        .with_skip_invalid(args.skip_invalid)
+        .to_backend(&mut *backend)
+        .await
+        .map_err(std::io::Error::other)?;
+    println!(
+        "replayed {} metrics, skipped {} invalid lines, offset {}",
+        progress.metrics, progress.invalid_lines, progress.offset,
+    );
+    Ok(())
+}
+
+/// Returns the count of invalid lines, printing each to stderr.
+async fn validate(args: ValidateArgs) -> std::io::Result<u64> {
+    let file = tokio::fs::File::open(&args.path).await?;
+    let mut reader = LineProtocolReader::new(tokio::io::BufReader::new(file))
+        .with_precision(args.precision);
+    let mut metrics = 0;
+    let mut invalid_lines = 0;
+    loop {
+        match reader.next_metric().await {
+            Ok(Some(_)) => metrics += 1,
+            Ok(None) => break,
+            Err(err) if err.kind() == std::io::ErrorKind::InvalidData => {
+                eprintln!("{}: {err}", args.path.display());
+                invalid_lines += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    println!("{metrics} valid metrics, {invalid_lines} invalid lines");
+    Ok(invalid_lines)
+}
+
+async fn child(args: ChildArgs) -> std::io::Result<()> {
+    let svc = InfluxiveChildSvc::new(args.config()).await?;
+    println!("host: {}", svc.get_host());
+    println!("token: {}", svc.get_token());
+    tokio::signal::ctrl_c().await?;
+    svc.shutdown();
+    Ok(())
+}
+
+/// Returns the raw annotated CSV result.
+async fn query(args: QueryArgs) -> std::io::Result<String> {
+    let flux = if args.flux == "-" {
+        let mut flux = String::new();
+        tokio::io::AsyncReadExt::read_to_string(
+            &mut tokio::io::stdin(),
+            &mut flux,
+        )
+        .await?;
+        flux
+    } else {
+        args.flux
+    };
+    if let (Some(host), Some(token)) = (args.host, args.token) {
+        return InfluxiveQueryClient::new(host, args.child.org, token)
+            .query_raw(flux)
+            .await;
+    }
+    let svc = InfluxiveChildSvc::new(args.child.config()).await?;
+    let result = svc.query(flux).await;
+    svc.shutdown();
+    result
+}
+
+async fn run(cli: Cli) -> std::io::Result<ExitCode> {
+    match cli.cmd {
+        Cmd::Write(args) => write(args).await?,
+        Cmd::Replay(args) => replay(args).await?,
+        Cmd::Validate(args) => {
+            if validate(args).await? > 0 {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Cmd::Child(args) => child(args).await?,
+        Cmd::Query(args) => print!("{}", query(args).await?),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> ExitCode {
+    match run(Cli::parse()).await {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod test;
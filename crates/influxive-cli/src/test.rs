@@ -0,0 +1,170 @@
+use super::*;
+use clap::CommandFactory;
+use influxive_mock::{InfluxiveMock, InfluxiveMockConfig};
+
+fn parse(args: &[&str]) -> Cli {
+    Cli::try_parse_from(
+        std::iter::once("influxive").chain(args.iter().copied()),
+    )
+    .unwrap()
+}
+
+fn mock_url(mock: &InfluxiveMock) -> String {
+    format!("influx+{}/{}", mock.get_host(), mock.get_bucket())
+}
+
+#[test]
+fn cli_definition() {
+    Cli::command().debug_assert();
+
+    assert!(Cli::try_parse_from(["influxive", "write", "m"]).is_err());
+    assert!(
+        Cli::try_parse_from(["influxive", "write", "m", "-f", "v"]).is_err()
+    );
+    assert!(Cli::try_parse_from([
+        "influxive",
+        "validate",
+        "f",
+        "--precision",
+        "h"
+    ])
+    .is_err());
+
+    // queries go to a running server, or explicitly to a new child
+    assert!(Cli::try_parse_from(["influxive", "query", "q"]).is_err());
+    assert!(
+        Cli::try_parse_from(["influxive", "query", "--token", "t", "q"])
+            .is_err()
+    );
+    assert!(Cli::try_parse_from([
+        "influxive",
+        "query",
+        "--spawn",
+        "--host",
+        "h",
+        "--token",
+        "t",
+        "q"
+    ])
+    .is_err());
+    assert!(Cli::try_parse_from(["influxive", "query", "--spawn", "q"]).is_ok());
+}
+
+#[test]
+fn field_values() {
+    let repr = |v: &str| format!("{:?}", parse_value(v));
+    assert_eq!("I64(-3)", repr("-3i"));
+    assert_eq!("U64(3)", repr("3u"));
+    assert_eq!("Bool(true)", repr("true"));
+    assert_eq!("F64(1.5)", repr("1.5"));
+    assert_eq!("F64(3.0)", repr("3"));
+    assert_eq!(format!("{:?}", DataType::from("hello")), repr("hello"));
+    assert_eq!(format!("{:?}", DataType::from("3x")), repr("\"3x\""));
+    assert_eq!(format!("{:?}", DataType::from("inf")), repr("inf"));
+
+    assert_eq!(("a".into(), "b=c".into()), parse_tag("a=b=c").unwrap());
+    assert!(parse_tag("=b").is_err());
+    assert!(parse_field("v").is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn validate_reports_invalid_lines() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("metrics.influx");
+    std::fs::write(&path, "m v=1 1\nm v=1x 2\n\nm,t=a v=2i 3\nm\n").unwrap();
+
+    let Cmd::Validate(args) = parse(&["validate", path.to_str().unwrap()]).cmd
+    else {
+        panic!()
+    };
+    assert_eq!(2, validate(args).await.unwrap());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn write_and_replay() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mock = InfluxiveMock::new(InfluxiveMockConfig::default())
+        .await
+        .unwrap();
+    let url = mock_url(&mock);
+
+    let cli = parse(&[
+        "write",
+        "--url",
+        &url,
+        "my.metric",
+        "--tag",
+        "host=a",
+        "--field",
+        "value=3.5",
+        "-f",
+        "count=2i",
+        "--timestamp",
+        "1000000000",
+    ]);
+    assert_eq!(ExitCode::SUCCESS, run(cli).await.unwrap());
+    let points = mock.points();
+    assert_eq!(1, points.len());
+    assert_eq!(1_000_000_000, points[0].timestamp);
+
+    let path = tmp.path().join("metrics.influx");
+    let checkpoint = tmp.path().join("metrics.checkpoint");
+    std::fs::write(&path, "replayed v=1 1\nreplayed v=2 2\n").unwrap();
+    for _ in 0..2 {
+        let cli = parse(&[
+            "replay",
+            "--url",
+            &url,
+            "--precision",
+            "s",
+            "--checkpoint",
+            checkpoint.to_str().unwrap(),
+            path.to_str().unwrap(),
+        ]);
+        assert_eq!(ExitCode::SUCCESS, run(cli).await.unwrap());
+    }
+    assert_eq!(3, mock.points().len());
+    assert_eq!(2, mock.write_requests());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn query_running_server() {
+    let mock = InfluxiveMock::new(
+        InfluxiveMockConfig::default()
+            .with_org("my.org".into())
+            .with_token(Some("my.token".into())),
+    )
+    .await
+    .unwrap();
+    let url = format!(
+        "influx+http://my.token@{}/{}",
+        mock.local_addr(),
+        mock.get_bucket(),
+    );
+    let cli = parse(&[
+        "write",
+        "--url",
+        &url,
+        "my.metric",
+        "--field",
+        "value=3.5",
+        "--timestamp",
+        "1000000000",
+    ]);
+    assert_eq!(ExitCode::SUCCESS, run(cli).await.unwrap());
+
+    let flux =
+        format!("from(bucket: \"{}\") |> range(start: 0)", mock.get_bucket());
+    let host = mock.get_host();
+    let Cmd::Query(args) = parse(&[
+        "query", "--host", &host, "--token", "my.token", "--org", "my.org",
+        &flux,
+    ])
+    .cmd
+    else {
+        panic!()
+    };
+    let csv = query(args).await.unwrap();
+    assert!(csv.contains("my.metric"), "{csv}");
+    assert!(csv.contains("3.5"), "{csv}");
+}
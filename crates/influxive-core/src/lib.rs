@@ -23,6 +23,9 @@
 use std::borrow::Cow;
 use std::sync::Arc;
 
+mod line_protocol;
+pub use line_protocol::*;
+
 /// Standin until std::io::Error::other is stablized.
 pub fn err_other<E>(error: E) -> std::io::Error
 where
@@ -49,6 +52,20 @@ impl StringType {
             StringType::ArcString(s) => s.to_string(),
         }
     }
+
+    /// Borrow this StringType as a str.
+    pub fn as_str(&self) -> &str {
+        match self {
+            StringType::String(s) => s,
+            StringType::ArcString(s) => s,
+        }
+    }
+}
+
+impl std::fmt::Display for StringType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 macro_rules! stringtype_from_impl {
@@ -86,6 +103,18 @@ pub enum DataType {
     String(StringType),
 }
 
+impl std::fmt::Display for DataType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataType::Bool(b) => b.fmt(f),
+            DataType::F64(v) => v.fmt(f),
+            DataType::I64(v) => v.fmt(f),
+            DataType::U64(v) => v.fmt(f),
+            DataType::String(s) => s.fmt(f),
+        }
+    }
+}
+
 macro_rules! datatype_from_impl {
     ($($f:ty, $i:ident, $b:block,)*) => {$(
         impl From<$f> for DataType {
@@ -114,7 +143,7 @@ datatype_from_impl! {
 }
 
 /// A metric to record in the influxdb instance.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Metric {
     /// The timestamp for this metric report.
     pub timestamp: std::time::SystemTime,
@@ -172,3 +201,165 @@ pub trait MetricWriter: 'static + Send + Sync {
     /// determined by the concrete implementation.
     fn write_metric(&self, metric: Metric);
 }
+
+/// Forwards every metric to each of a list of [MetricWriter]s,
+/// e.g. to feed both InfluxDB and a Prometheus exporter from one source.
+pub struct MultiMetricWriter(
+    Vec<Arc<dyn MetricWriter + 'static + Send + Sync>>,
+);
+
+impl MultiMetricWriter {
+    /// Construct a new MultiMetricWriter from a list of writers.
+    pub fn new(
+        writers: Vec<Arc<dyn MetricWriter + 'static + Send + Sync>>,
+    ) -> Self {
+        Self(writers)
+    }
+
+    /// Add a writer to the list.
+    pub fn with_writer(
+        mut self,
+        writer: Arc<dyn MetricWriter + 'static + Send + Sync>,
+    ) -> Self {
+        self.0.push(writer);
+        self
+    }
+}
+
+impl MetricWriter for MultiMetricWriter {
+    fn write_metric(&self, metric: Metric) {
+        if let Some((last, rest)) = self.0.split_last() {
+            for writer in rest {
+                writer.write_metric(metric.clone());
+            }
+            last.write_metric(metric);
+        }
+    }
+}
+
+/// Predicate selecting the metrics a [RoutingMetricWriter] route accepts.
+pub type MetricRouteFn = Arc<dyn Fn(&Metric) -> bool + 'static + Send + Sync>;
+
+/// Forwards each metric to the first route that matches it, or to
+/// the default route if none do, e.g. to send `app.*` measurements to
+/// one InfluxDB bucket and `infra.*` measurements to another. Metrics
+/// matching no route without a default route are dropped.
+///
+/// Routes are usually separate `InfluxiveWriter` instances, so each
+/// route gets its own batching state.
+///
+/// ```
+/// # use influxive_core::*;
+/// # use std::sync::{Arc, Mutex};
+/// struct Collect(Mutex<Vec<String>>);
+///
+/// impl MetricWriter for Collect {
+///     fn write_metric(&self, metric: Metric) {
+///         self.0.lock().unwrap().push(metric.name.to_string());
+///     }
+/// }
+///
+/// let app = Arc::new(Collect(Mutex::new(Vec::new())));
+/// let debug = Arc::new(Collect(Mutex::new(Vec::new())));
+/// let other = Arc::new(Collect(Mutex::new(Vec::new())));
+///
+/// let router = RoutingMetricWriter::new()
+///     .with_prefix_route("app.", app.clone())
+///     .with_tag_route("level", "debug", debug.clone())
+///     .with_default_route(other.clone());
+///
+/// let now = std::time::SystemTime::now();
+/// router.write_metric(Metric::new(now, "app.requests").with_field("value", 1));
+/// router.write_metric(
+///     Metric::new(now, "cache.size")
+///         .with_field("value", 1)
+///         .with_tag("level", "debug"),
+/// );
+/// router.write_metric(Metric::new(now, "infra.cpu").with_field("value", 1));
+///
+/// assert_eq!(vec!["app.requests"], *app.0.lock().unwrap());
+/// assert_eq!(vec!["cache.size"], *debug.0.lock().unwrap());
+/// assert_eq!(vec!["infra.cpu"], *other.0.lock().unwrap());
+/// ```
+#[derive(Default)]
+pub struct RoutingMetricWriter {
+    routes: Vec<(MetricRouteFn, Arc<dyn MetricWriter + 'static + Send + Sync>)>,
+    default: Option<Arc<dyn MetricWriter + 'static + Send + Sync>>,
+}
+
+impl RoutingMetricWriter {
+    /// Construct a new RoutingMetricWriter with no routes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a route for metrics the predicate returns `true` for.
+    /// Routes are checked in the order they were added.
+    pub fn with_route(
+        mut self,
+        predicate: MetricRouteFn,
+        writer: Arc<dyn MetricWriter + 'static + Send + Sync>,
+    ) -> Self {
+        self.routes.push((predicate, writer));
+        self
+    }
+
+    /// Add a route for metrics whose name starts with `prefix`.
+    pub fn with_prefix_route<P: Into<String>>(
+        self,
+        prefix: P,
+        writer: Arc<dyn MetricWriter + 'static + Send + Sync>,
+    ) -> Self {
+        let prefix = prefix.into();
+        self.with_route(
+            Arc::new(move |metric: &Metric| {
+                metric.name.as_str().starts_with(&prefix)
+            }),
+            writer,
+        )
+    }
+
+    /// Add a route for metrics with the tag `key` set to `value`.
+    pub fn with_tag_route<K: Into<String>, V: Into<String>>(
+        self,
+        key: K,
+        value: V,
+        writer: Arc<dyn MetricWriter + 'static + Send + Sync>,
+    ) -> Self {
+        let key = key.into();
+        let value = value.into();
+        self.with_route(
+            Arc::new(move |metric: &Metric| {
+                metric
+                    .tags
+                    .iter()
+                    .any(|(k, v)| k.as_str() == key && v.to_string() == value)
+            }),
+            writer,
+        )
+    }
+
+    /// Set the route for metrics matching no other route.
+    pub fn with_default_route(
+        mut self,
+        writer: Arc<dyn MetricWriter + 'static + Send + Sync>,
+    ) -> Self {
+        self.default = Some(writer);
+        self
+    }
+}
+
+impl MetricWriter for RoutingMetricWriter {
+    fn write_metric(&self, metric: Metric) {
+        let writer = self
+            .routes
+            .iter()
+            .find(|(predicate, _)| predicate(&metric))
+            .map(|(_, writer)| writer)
+            .or(self.default.as_ref());
+
+        if let Some(writer) = writer {
+            writer.write_metric(metric);
+        }
+    }
+}
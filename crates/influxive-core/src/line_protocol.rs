@@ -0,0 +1,160 @@
+use super::*;
+
+fn invalid(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+/// Split `s` on unescaped `delim` characters. If `quoted`, delimiters
+/// inside double quotes are skipped. Quotes are only special in the
+/// field set, in the measurement and tags they are literal characters.
+fn split_unescaped(s: &str, delim: char, quoted: bool) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    let mut in_quotes = false;
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if quoted && c == '"' {
+            in_quotes = !in_quotes;
+        } else if c == delim && !in_quotes {
+            out.push(&s[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    out.push(&s[start..]);
+    out
+}
+
+/// Remove backslash escapes.
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(c) = chars.next() {
+                out.push(c);
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn parse_field_value(s: &str) -> std::io::Result<DataType> {
+    if let Some(v) = s.strip_prefix('"') {
+        let v = v.strip_suffix('"').ok_or_else(|| {
+            invalid(format!("unterminated string field value: {s}"))
+        })?;
+        return Ok(DataType::String(unescape(v).into()));
+    }
+    match s {
+        "t" | "T" | "true" | "True" | "TRUE" => return Ok(true.into()),
+        "f" | "F" | "false" | "False" | "FALSE" => return Ok(false.into()),
+        _ => (),
+    }
+    if let Some(v) = s.strip_suffix('i') {
+        return v
+            .parse::<i64>()
+            .map(DataType::I64)
+            .map_err(|_| invalid(format!("invalid integer field value: {s}")));
+    }
+    if let Some(v) = s.strip_suffix('u') {
+        return v.parse::<u64>().map(DataType::U64).map_err(|_| {
+            invalid(format!("invalid unsigned field value: {s}"))
+        });
+    }
+    s.parse::<f64>()
+        .map(DataType::F64)
+        .map_err(|_| invalid(format!("invalid field value: {s}")))
+}
+
+/// Parse one line of InfluxDB Line Protocol into a [Metric].
+///
+/// Timestamps are in units of `precision`, e.g. one millisecond for
+/// millisecond timestamps. A line without a timestamp gets
+/// `default_timestamp`. Invalid lines, including lines timestamped
+/// before the unix epoch, are reported as
+/// [std::io::ErrorKind::InvalidData] errors.
+///
+/// ```
+/// let metric = influxive_core::parse_line_protocol(
+///     "my.metric,tag=a value=1i 1000",
+///     std::time::Duration::from_millis(1),
+///     std::time::SystemTime::now(),
+/// )
+/// .unwrap();
+/// assert_eq!("my.metric", metric.name.as_str());
+/// assert_eq!(
+///     std::time::UNIX_EPOCH + std::time::Duration::from_secs(1),
+///     metric.timestamp,
+/// );
+/// ```
+pub fn parse_line_protocol(
+    line: &str,
+    precision: std::time::Duration,
+    default_timestamp: std::time::SystemTime,
+) -> std::io::Result<Metric> {
+    // the series ends at the first unescaped space, quotes included
+    let series = split_unescaped(line, ' ', false)[0];
+    let parts = split_unescaped(&line[series.len()..], ' ', true)
+        .into_iter()
+        .filter(|p| !p.is_empty())
+        .collect::<Vec<_>>();
+    if series.is_empty() || parts.is_empty() || parts.len() > 2 {
+        return Err(invalid(format!("invalid line: {line}")));
+    }
+
+    let mut series = split_unescaped(series, ',', false).into_iter();
+    let name = unescape(series.next().unwrap_or_default());
+    if name.is_empty() {
+        return Err(invalid(format!("missing measurement: {line}")));
+    }
+
+    let timestamp = match parts.get(1) {
+        Some(ts) => {
+            let ts = ts
+                .parse::<i64>()
+                .map_err(|_| invalid(format!("invalid timestamp: {ts}")))?;
+            // metrics are encoded with unsigned timestamps downstream
+            if ts < 0 {
+                return Err(invalid(format!(
+                    "timestamp before the unix epoch: {ts}"
+                )));
+            }
+            let nanos = ts as u128 * precision.as_nanos();
+            std::time::UNIX_EPOCH
+                + std::time::Duration::from_nanos(
+                    u64::try_from(nanos).map_err(|_| {
+                        invalid(format!("timestamp out of range: {ts}"))
+                    })?,
+                )
+        }
+        None => default_timestamp,
+    };
+
+    let mut metric = Metric::new(timestamp, name);
+
+    for tag in series {
+        let kv = split_unescaped(tag, '=', false);
+        if kv.len() != 2 {
+            return Err(invalid(format!("invalid tag: {tag}")));
+        }
+        metric = metric.with_tag(unescape(kv[0]), unescape(kv[1]));
+    }
+
+    for field in split_unescaped(parts[0], ',', true) {
+        // the key ends at the first unescaped `=`, quotes included
+        let key = split_unescaped(field, '=', false)[0];
+        if key.is_empty() || key.len() == field.len() {
+            return Err(invalid(format!("invalid field: {field}")));
+        }
+        let value = parse_field_value(&field[key.len() + 1..])?;
+        metric = metric.with_field(unescape(key), value);
+    }
+
+    Ok(metric)
+}
@@ -274,6 +274,10 @@ mod tests {
     };
 
     #[tokio::test(flavor = "multi_thread")]
+    #[cfg_attr(
+        not(feature = "network-tests"),
+        ignore = "downloads release archives"
+    )]
     async fn tar_gz_sanity() {
         println!("{TEST_TAR:?}");
 
@@ -295,6 +299,10 @@ mod tests {
     }
 
     #[tokio::test(flavor = "multi_thread")]
+    #[cfg_attr(
+        not(feature = "network-tests"),
+        ignore = "downloads release archives"
+    )]
     async fn zip_sanity() {
         println!("{TEST_ZIP:?}");
 
@@ -0,0 +1,679 @@
+use super::*;
+
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+
+/// Days since the unix epoch of a proleptic Gregorian calendar date.
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Proleptic Gregorian calendar date of days since the unix epoch.
+fn civil_from_days(z: i64) -> (i64, i64, i64) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    (
+        if m <= 2 {
+            yoe + era * 400 + 1
+        } else {
+            yoe + era * 400
+        },
+        m,
+        d,
+    )
+}
+
+/// Format nanoseconds since the unix epoch as RFC3339 in UTC,
+/// with trailing zeros of the fraction trimmed.
+pub(crate) fn format_rfc3339(nanos: i64) -> String {
+    let secs = nanos.div_euclid(NANOS_PER_SEC);
+    let frac = nanos.rem_euclid(NANOS_PER_SEC);
+    let (y, m, d) = civil_from_days(secs.div_euclid(86400));
+    let sod = secs.rem_euclid(86400);
+    let mut out = format!(
+        "{y:04}-{m:02}-{d:02}T{:02}:{:02}:{:02}",
+        sod / 3600,
+        sod % 3600 / 60,
+        sod % 60
+    );
+    if frac > 0 {
+        let frac = format!("{frac:09}");
+        out.push('.');
+        out.push_str(frac.trim_end_matches('0'));
+    }
+    out.push('Z');
+    out
+}
+
+/// Parse an RFC3339 timestamp, e.g. `2023-01-02T03:04:05.5Z`,
+/// into nanoseconds since the unix epoch.
+pub(crate) fn parse_rfc3339(s: &str) -> Option<i64> {
+    let num = |s: &str| -> Option<i64> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        s.parse().ok()
+    };
+
+    let (date, time) = s.split_once('T')?;
+    let mut date = date.split('-');
+    let (y, m, d) =
+        (num(date.next()?)?, num(date.next()?)?, num(date.next()?)?);
+
+    let (time, offset) = if let Some(time) = time.strip_suffix('Z') {
+        (time, 0)
+    } else {
+        let at = time.rfind(['+', '-'])?;
+        let (time, offset) = time.split_at(at);
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let (oh, om) = offset[1..].split_once(':')?;
+        (time, sign * (num(oh)? * 3600 + num(om)? * 60))
+    };
+
+    let (time, frac) = match time.split_once('.') {
+        Some((time, frac)) if frac.len() <= 9 => {
+            (time, num(frac)? * 10_i64.pow(9 - frac.len() as u32))
+        }
+        Some(_) => return None,
+        None => (time, 0),
+    };
+    let mut time = time.split(':');
+    let (hh, mm, ss) =
+        (num(time.next()?)?, num(time.next()?)?, num(time.next()?)?);
+
+    let secs =
+        days_from_civil(y, m, d) * 86400 + hh * 3600 + mm * 60 + ss - offset;
+    Some(secs * NANOS_PER_SEC + frac)
+}
+
+/// Parse a Flux duration literal, e.g. `-15m` or `1h30m`, into nanoseconds.
+pub(crate) fn parse_duration(s: &str) -> Option<i64> {
+    let (sign, mut rest) = match s.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, s),
+    };
+    if rest.is_empty() {
+        return None;
+    }
+
+    let mut total: i64 = 0;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        if digits == 0 {
+            return None;
+        }
+        let n: i64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let unit = match &rest[..unit_len] {
+            "ns" => 1,
+            "us" | "µs" => 1_000,
+            "ms" => 1_000_000,
+            "s" => NANOS_PER_SEC,
+            "m" => 60 * NANOS_PER_SEC,
+            "h" => 3600 * NANOS_PER_SEC,
+            "d" => 86400 * NANOS_PER_SEC,
+            "w" => 7 * 86400 * NANOS_PER_SEC,
+            _ => return None,
+        };
+        rest = &rest[unit_len..];
+        total = total.checked_add(n.checked_mul(unit)?)?;
+    }
+    Some(sign * total)
+}
+
+/// A literal or record value a filter compares.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Null,
+    Str(String),
+    Num(f64),
+    Bool(bool),
+}
+
+impl From<&FieldValue> for Value {
+    fn from(v: &FieldValue) -> Self {
+        match v {
+            FieldValue::Boolean(b) => Value::Bool(*b),
+            FieldValue::String(s) => Value::Str(s.clone()),
+            v => Value::Num(v.as_f64().unwrap_or_default()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A filter predicate.
+#[derive(Debug, Clone)]
+enum Expr {
+    Cmp(Operand, CmpOp, Operand),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Const(bool),
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Column(String),
+    Literal(Value),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(f64),
+    Op(&'static str),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut out = Vec::new();
+    let chars = s.chars().collect::<Vec<_>>();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '"' {
+            let mut v = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err("unterminated string".to_string()),
+                    Some('"') => break,
+                    Some('\\') => {
+                        i += 1;
+                        if let Some(c) = chars.get(i) {
+                            v.push(*c);
+                        }
+                    }
+                    Some(c) => v.push(*c),
+                }
+                i += 1;
+            }
+            i += 1;
+            out.push(Token::Str(v));
+        } else if c.is_ascii_digit()
+            || (c == '-'
+                && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()))
+        {
+            let start = i;
+            i += 1;
+            while chars
+                .get(i)
+                .is_some_and(|c| c.is_ascii_digit() || *c == '.')
+            {
+                i += 1;
+            }
+            let v = chars[start..i].iter().collect::<String>();
+            out.push(Token::Num(
+                v.parse().map_err(|_| format!("invalid number: {v}"))?,
+            ));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while chars
+                .get(i)
+                .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+            {
+                i += 1;
+            }
+            out.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            let two =
+                chars[i..chars.len().min(i + 2)].iter().collect::<String>();
+            let op = ["==", "!=", "<=", ">=", "=>"]
+                .into_iter()
+                .find(|op| *op == two)
+                .or_else(|| {
+                    ["(", ")", "[", "]", "<", ">", ".", ":", ","]
+                        .into_iter()
+                        .find(|op| op.starts_with(c))
+                })
+                .ok_or_else(|| format!("unexpected character: {c}"))?;
+            i += op.len();
+            out.push(Token::Op(op));
+        }
+    }
+    Ok(out)
+}
+
+/// Recursive descent parser of filter predicates,
+/// `or` binding weaker than `and`, binding weaker than comparisons.
+struct ExprParser {
+    tokens: Vec<Token>,
+    pos: usize,
+    record: String,
+}
+
+impl ExprParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Op(o)) if o == op => Ok(()),
+            t => Err(format!("expected {op}, got {t:?}")),
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(i)) if i == keyword)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.and()?;
+        while self.is_keyword("or") {
+            self.pos += 1;
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while self.is_keyword("and") {
+            self.pos += 1;
+            lhs = Expr::And(Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.is_keyword("not") {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.peek() == Some(&Token::Op("(")) {
+            self.pos += 1;
+            let expr = self.or()?;
+            self.expect(")")?;
+            return Ok(expr);
+        }
+        let lhs = self.operand()?;
+        let op = match self.peek() {
+            Some(Token::Op("==")) => CmpOp::Eq,
+            Some(Token::Op("!=")) => CmpOp::Ne,
+            Some(Token::Op("<")) => CmpOp::Lt,
+            Some(Token::Op("<=")) => CmpOp::Le,
+            Some(Token::Op(">")) => CmpOp::Gt,
+            Some(Token::Op(">=")) => CmpOp::Ge,
+            _ => {
+                return match lhs {
+                    Operand::Literal(Value::Bool(b)) => Ok(Expr::Const(b)),
+                    lhs => Err(format!("expected a comparison: {lhs:?}")),
+                }
+            }
+        };
+        self.pos += 1;
+        Ok(Expr::Cmp(lhs, op, self.operand()?))
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(Operand::Literal(Value::Str(s))),
+            Some(Token::Num(n)) => Ok(Operand::Literal(Value::Num(n))),
+            Some(Token::Ident(i)) if i == "true" => {
+                Ok(Operand::Literal(Value::Bool(true)))
+            }
+            Some(Token::Ident(i)) if i == "false" => {
+                Ok(Operand::Literal(Value::Bool(false)))
+            }
+            Some(Token::Ident(i)) if i == self.record => match self.next() {
+                Some(Token::Op(".")) => match self.next() {
+                    Some(Token::Ident(column)) => Ok(Operand::Column(column)),
+                    t => Err(format!("expected a column name, got {t:?}")),
+                },
+                Some(Token::Op("[")) => match self.next() {
+                    Some(Token::Str(column)) => {
+                        self.expect("]")?;
+                        Ok(Operand::Column(column))
+                    }
+                    t => Err(format!("expected a column name, got {t:?}")),
+                },
+                t => Err(format!("expected . or [, got {t:?}")),
+            },
+            t => Err(format!("unexpected {t:?}")),
+        }
+    }
+}
+
+/// A row of a query result: one field of one point.
+struct Row<'a> {
+    point: &'a Point,
+    field: &'a str,
+    value: &'a FieldValue,
+}
+
+impl Row<'_> {
+    fn column(&self, column: &str) -> Value {
+        match column {
+            "_measurement" => Value::Str(self.point.measurement.clone()),
+            "_field" => Value::Str(self.field.to_string()),
+            "_value" => self.value.into(),
+            column => self
+                .point
+                .tags
+                .get(column)
+                .map(|v| Value::Str(v.clone()))
+                .unwrap_or(Value::Null),
+        }
+    }
+}
+
+impl Expr {
+    fn eval(&self, row: &Row<'_>) -> bool {
+        match self {
+            Expr::Const(b) => *b,
+            Expr::Not(e) => !e.eval(row),
+            Expr::And(a, b) => a.eval(row) && b.eval(row),
+            Expr::Or(a, b) => a.eval(row) || b.eval(row),
+            Expr::Cmp(lhs, op, rhs) => {
+                let get = |o: &Operand| match o {
+                    Operand::Column(c) => row.column(c),
+                    Operand::Literal(v) => v.clone(),
+                };
+                let ordering = match (get(lhs), get(rhs)) {
+                    (Value::Str(a), Value::Str(b)) => a.partial_cmp(&b),
+                    (Value::Num(a), Value::Num(b)) => a.partial_cmp(&b),
+                    (Value::Bool(a), Value::Bool(b)) => a.partial_cmp(&b),
+                    // null and mismatched types never compare
+                    _ => None,
+                };
+                let Some(ordering) = ordering else {
+                    return false;
+                };
+                match op {
+                    CmpOp::Eq => ordering.is_eq(),
+                    CmpOp::Ne => ordering.is_ne(),
+                    CmpOp::Lt => ordering.is_lt(),
+                    CmpOp::Le => ordering.is_le(),
+                    CmpOp::Gt => ordering.is_gt(),
+                    CmpOp::Ge => ordering.is_ge(),
+                }
+            }
+        }
+    }
+}
+
+/// Measurement, tags and field of a result table.
+type TableKey = (String, Vec<(String, String)>, String);
+
+/// A parsed query of the supported Flux subset.
+pub(crate) struct Query {
+    bucket: String,
+    start: i64,
+    stop: i64,
+    filters: Vec<Expr>,
+}
+
+/// Split `s` on a top-level separator, outside of strings and parens.
+fn split_top_level<'a>(s: &'a str, sep: &str) -> Vec<&'a str> {
+    let mut out = Vec::new();
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut start = 0;
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if quoted => i += 1,
+            b'"' => quoted = !quoted,
+            b'(' | b'[' if !quoted => depth += 1,
+            b')' | b']' if !quoted => depth -= 1,
+            _ if !quoted && depth == 0 && s[i..].starts_with(sep) => {
+                out.push(&s[start..i]);
+                i += sep.len();
+                start = i;
+                continue;
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+    out.push(&s[start..]);
+    out
+}
+
+/// Split `name(args)` into its name and arguments.
+fn call(stage: &str) -> Result<(&str, &str), String> {
+    let stage = stage.trim();
+    let open = stage
+        .find('(')
+        .ok_or_else(|| format!("expected a function call: {stage}"))?;
+    let args = stage[open + 1..]
+        .strip_suffix(')')
+        .ok_or_else(|| format!("expected a function call: {stage}"))?;
+    Ok((stage[..open].trim(), args))
+}
+
+/// Named arguments of a call, e.g. `bucket: "b"`.
+fn named_args(args: &str) -> Result<Vec<(&str, &str)>, String> {
+    split_top_level(args, ",")
+        .into_iter()
+        .filter(|a| !a.trim().is_empty())
+        .map(|arg| {
+            arg.split_once(':')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| format!("expected a named argument: {arg}"))
+        })
+        .collect()
+}
+
+fn parse_time(s: &str, now: i64) -> Result<i64, String> {
+    if s == "now()" {
+        return Ok(now);
+    }
+    if let Some(d) = parse_duration(s) {
+        return Ok(now + d);
+    }
+    if let Ok(n) = s.parse::<i64>() {
+        // integers are seconds since the epoch
+        return Ok(n * NANOS_PER_SEC);
+    }
+    parse_rfc3339(s).ok_or_else(|| format!("invalid time: {s}"))
+}
+
+impl Query {
+    /// Parse a query made of `from`, `range` and `filter` calls.
+    pub fn parse(flux: &str, now: i64) -> Result<Self, String> {
+        let mut stages = split_top_level(flux.trim(), "|>").into_iter();
+
+        let (name, args) = call(stages.next().unwrap_or_default())?;
+        if name != "from" {
+            return Err(format!("expected from(), got {name}()"));
+        }
+        let bucket = named_args(args)?
+            .into_iter()
+            .find(|(k, _)| *k == "bucket")
+            .and_then(|(_, v)| v.strip_prefix('"')?.strip_suffix('"'))
+            .ok_or_else(|| "from() requires a bucket".to_string())?
+            .to_string();
+
+        let mut query = Query {
+            bucket,
+            start: i64::MIN,
+            stop: now,
+            filters: Vec::new(),
+        };
+
+        for stage in stages {
+            let (name, args) = call(stage)?;
+            match name {
+                "range" => {
+                    for (k, v) in named_args(args)? {
+                        match k {
+                            "start" => query.start = parse_time(v, now)?,
+                            "stop" => query.stop = parse_time(v, now)?,
+                            k => {
+                                return Err(format!(
+                                    "unsupported range() argument: {k}"
+                                ))
+                            }
+                        }
+                    }
+                }
+                "filter" => {
+                    let (_, f) = named_args(args)?
+                        .into_iter()
+                        .find(|(k, _)| *k == "fn")
+                        .ok_or_else(|| "filter() requires fn".to_string())?;
+                    let (params, body) = f
+                        .split_once("=>")
+                        .ok_or_else(|| format!("invalid function: {f}"))?;
+                    let record = params
+                        .trim()
+                        .trim_start_matches('(')
+                        .trim_end_matches(')')
+                        .trim()
+                        .to_string();
+                    let mut parser = ExprParser {
+                        tokens: tokenize(body)?,
+                        pos: 0,
+                        record,
+                    };
+                    let expr = parser.or()?;
+                    if parser.pos != parser.tokens.len() {
+                        return Err(format!("unexpected input in: {body}"));
+                    }
+                    query.filters.push(expr);
+                }
+                name => return Err(format!("unsupported function: {name}()")),
+            }
+        }
+
+        Ok(query)
+    }
+
+    /// Render the matching points as InfluxDB annotated CSV,
+    /// one table per series and field.
+    pub fn execute<'a>(
+        &self,
+        points: impl IntoIterator<Item = &'a Point>,
+    ) -> String {
+        let mut tables: BTreeMap<TableKey, Vec<Row<'a>>> = BTreeMap::new();
+
+        for point in points {
+            if point.bucket != self.bucket
+                || point.timestamp < self.start
+                || point.timestamp >= self.stop
+            {
+                continue;
+            }
+            for (field, value) in point.fields.iter() {
+                let row = Row {
+                    point,
+                    field,
+                    value,
+                };
+                if self.filters.iter().all(|f| f.eval(&row)) {
+                    tables
+                        .entry((
+                            point.measurement.clone(),
+                            point
+                                .tags
+                                .iter()
+                                .map(|(k, v)| (k.clone(), v.clone()))
+                                .collect(),
+                            field.clone(),
+                        ))
+                        .or_default()
+                        .push(row);
+                }
+            }
+        }
+
+        let start = format_rfc3339(self.start.max(0));
+        let stop = format_rfc3339(self.stop);
+        let mut out = String::new();
+        let mut schema = None;
+        for (table, ((_, tags, _), mut rows)) in tables.into_iter().enumerate()
+        {
+            rows.sort_by_key(|r| r.point.timestamp);
+
+            // a new annotation block starts whenever the columns change
+            let table_schema = (
+                rows[0].value.datatype(),
+                tags.iter().map(|(k, _)| k.clone()).collect::<Vec<_>>(),
+            );
+            if schema.as_ref() != Some(&table_schema) {
+                if schema.is_some() {
+                    out.push('\n');
+                }
+                render_annotations(&mut out, table_schema.0, &table_schema.1);
+                schema = Some(table_schema);
+            }
+
+            for row in rows {
+                out.push_str(&format!(
+                    ",,{table},{start},{stop},{},{},{},{}",
+                    format_rfc3339(row.point.timestamp),
+                    csv_escape(&row.value.to_string()),
+                    csv_escape(row.field),
+                    csv_escape(&row.point.measurement),
+                ));
+                for (_, v) in tags.iter() {
+                    out.push(',');
+                    out.push_str(&csv_escape(v));
+                }
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+fn render_annotations(out: &mut String, datatype: &str, tag_keys: &[String]) {
+    let tags = tag_keys.len();
+    out.push_str(&format!(
+        "#datatype,string,long,dateTime:RFC3339,dateTime:RFC3339,\
+         dateTime:RFC3339,{datatype},string,string{}\n",
+        ",string".repeat(tags)
+    ));
+    out.push_str(&format!(
+        "#group,false,false,true,true,false,false,true,true{}\n",
+        ",true".repeat(tags)
+    ));
+    out.push_str(&format!("#default,_result,,,,,,,{}\n", ",".repeat(tags)));
+    out.push_str(",result,table,_start,_stop,_time,_value,_field,_measurement");
+    for k in tag_keys {
+        out.push(',');
+        out.push_str(&csv_escape(k));
+    }
+    out.push('\n');
+}
+
+fn csv_escape(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
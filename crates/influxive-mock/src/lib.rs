@@ -0,0 +1,713 @@
+#![deny(missing_docs)]
+#![deny(warnings)]
+#![deny(unsafe_code)]
+//! An in-memory InfluxDB server for tests.
+//!
+//! [InfluxiveMock] listens on a local port and serves enough of the
+//! InfluxDB HTTP API for the influxive crates to be tested offline,
+//! without downloading and running influxd:
+//!
+//! - `POST /api/v2/write` and the v1 compatibility `POST /write`
+//!   store Line Protocol in memory.
+//! - `POST /api/v2/query` answers a useful subset of Flux in annotated
+//!   CSV: `from(bucket:)`, `range(start:, stop:)` and
+//!   `filter(fn: (r) => ...)` with comparisons combined by `and`, `or`
+//!   and `not`.
+//! - `GET /health`, `GET /ping` and `/api/v2/setup` answer like a
+//!   healthy server.
+//!
+//! Writes to any bucket are accepted. Points with the same series and
+//! timestamp are merged, as InfluxDB does.
+//!
+//! Faults can be injected to test retries and error handling, see
+//! [InfluxiveMock::set_latency] and [InfluxiveMock::fail_next_writes].
+//!
+//! ## Example
+//!
+//! ```
+//! # #[tokio::main(flavor = "multi_thread")]
+//! # async fn main() {
+//! use influxive_mock::*;
+//!
+//! let mock = InfluxiveMock::new(InfluxiveMockConfig::default())
+//!     .await
+//!     .unwrap();
+//!
+//! let client = reqwest::Client::new();
+//! client
+//!     .post(format!("{}/api/v2/write?bucket=influxive", mock.get_host()))
+//!     .body("my.metric,tag=test-tag value=3.14 1700000000000000000")
+//!     .send()
+//!     .await
+//!     .unwrap()
+//!     .error_for_status()
+//!     .unwrap();
+//!
+//! let result = mock
+//!     .query(
+//!         r#"from(bucket: "influxive")
+//!     |> range(start: 0)
+//!     |> filter(fn: (r) => r._measurement == "my.metric")"#,
+//!     )
+//!     .unwrap();
+//!
+//! assert!(result.contains(",3.14,value,my.metric,test-tag"));
+//! # }
+//! ```
+
+use influxive_core::*;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+mod flux;
+
+mod line_protocol;
+pub use line_protocol::*;
+
+/// Influxive mock server configuration.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct InfluxiveMockConfig {
+    /// Address the server listens on.
+    /// Defaults to `127.0.0.1:0`, an ephemeral port.
+    pub bind_addr: std::net::SocketAddr,
+
+    /// The organization reported by the server.
+    /// Defaults to `influxive`.
+    pub org: String,
+
+    /// The bucket reported by the server.
+    /// Defaults to `influxive`.
+    pub bucket: String,
+
+    /// If set, writes and queries must present this token,
+    /// otherwise any token (or none) is accepted.
+    /// Defaults to `None`.
+    pub token: Option<String>,
+
+    /// If true, `/api/v2/setup` allows an initial setup,
+    /// as a freshly started influxd would.
+    /// Defaults to `false`.
+    pub setup_allowed: bool,
+}
+
+impl Default for InfluxiveMockConfig {
+    fn default() -> Self {
+        Self {
+            bind_addr: ([127, 0, 0, 1], 0).into(),
+            org: "influxive".to_string(),
+            bucket: "influxive".to_string(),
+            token: None,
+            setup_allowed: false,
+        }
+    }
+}
+
+impl InfluxiveMockConfig {
+    /// Apply [InfluxiveMockConfig::bind_addr].
+    pub fn with_bind_addr(mut self, bind_addr: std::net::SocketAddr) -> Self {
+        self.bind_addr = bind_addr;
+        self
+    }
+
+    /// Apply [InfluxiveMockConfig::org].
+    pub fn with_org(mut self, org: String) -> Self {
+        self.org = org;
+        self
+    }
+
+    /// Apply [InfluxiveMockConfig::bucket].
+    pub fn with_bucket(mut self, bucket: String) -> Self {
+        self.bucket = bucket;
+        self
+    }
+
+    /// Apply [InfluxiveMockConfig::token].
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// Apply [InfluxiveMockConfig::setup_allowed].
+    pub fn with_setup_allowed(mut self, setup_allowed: bool) -> Self {
+        self.setup_allowed = setup_allowed;
+        self
+    }
+}
+
+/// A fault returned instead of a successful write,
+/// see [InfluxiveMock::fail_next_writes].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InfluxiveMockFault {
+    /// Nothing is stored, the write answers `500 Internal Server Error`.
+    ServerError,
+
+    /// Nothing is stored, the write answers `429 Too Many Requests`.
+    TooManyRequests,
+
+    /// The first `accepted` lines are stored,
+    /// the write answers `400 Bad Request` with a partial write error.
+    PartialWrite {
+        /// The number of lines stored before failing.
+        accepted: usize,
+    },
+}
+
+type SeriesKey = (String, String, Vec<(String, String)>, i64);
+
+struct State {
+    org: String,
+    bucket: String,
+    token: Option<String>,
+    setup_allowed: bool,
+    latency: std::time::Duration,
+    faults: VecDeque<InfluxiveMockFault>,
+    write_requests: u64,
+    points: BTreeMap<SeriesKey, Point>,
+}
+
+impl State {
+    fn store(&mut self, point: Point) {
+        let key = (
+            point.bucket.clone(),
+            point.measurement.clone(),
+            point
+                .tags
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            point.timestamp,
+        );
+        match self.points.get_mut(&key) {
+            Some(existing) => existing.fields.extend(point.fields),
+            None => {
+                self.points.insert(key, point);
+            }
+        }
+    }
+}
+
+/// An in-memory InfluxDB server listening on a local port.
+/// The server stops when this is dropped.
+pub struct InfluxiveMock {
+    state: Arc<Mutex<State>>,
+    local_addr: std::net::SocketAddr,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for InfluxiveMock {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl InfluxiveMock {
+    /// Bind the server and start serving.
+    pub async fn new(config: InfluxiveMockConfig) -> std::io::Result<Self> {
+        let listener = tokio::net::TcpListener::bind(config.bind_addr).await?;
+        let local_addr = listener.local_addr()?;
+
+        let state = Arc::new(Mutex::new(State {
+            org: config.org,
+            bucket: config.bucket,
+            token: config.token,
+            setup_allowed: config.setup_allowed,
+            latency: std::time::Duration::ZERO,
+            faults: VecDeque::new(),
+            write_requests: 0,
+            points: BTreeMap::new(),
+        }));
+
+        let weak = Arc::downgrade(&state);
+        let task = tokio::task::spawn(async move {
+            loop {
+                let socket = match listener.accept().await {
+                    Ok((socket, _)) => socket,
+                    Err(err) => {
+                        tracing::warn!(?err, "mock accept error");
+                        continue;
+                    }
+                };
+                let state = match weak.upgrade() {
+                    Some(state) => state,
+                    None => break,
+                };
+                tokio::task::spawn(async move {
+                    if let Err(err) = serve(socket, &state).await {
+                        tracing::debug!(?err, "mock request error");
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            state,
+            local_addr,
+            task,
+        })
+    }
+
+    /// The address the server is bound to.
+    pub fn local_addr(&self) -> std::net::SocketAddr {
+        self.local_addr
+    }
+
+    /// The host url of the server, e.g. `http://127.0.0.1:37429`.
+    pub fn get_host(&self) -> String {
+        format!("http://{}", self.local_addr)
+    }
+
+    /// The organization of the server.
+    pub fn get_org(&self) -> String {
+        self.state.lock().unwrap().org.clone()
+    }
+
+    /// The bucket of the server.
+    pub fn get_bucket(&self) -> String {
+        self.state.lock().unwrap().bucket.clone()
+    }
+
+    /// The token writes and queries must present, if any.
+    pub fn get_token(&self) -> Option<String> {
+        self.state.lock().unwrap().token.clone()
+    }
+
+    /// All stored points, ordered by bucket, series and timestamp.
+    pub fn points(&self) -> Vec<Point> {
+        self.state
+            .lock()
+            .unwrap()
+            .points
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// The number of write requests received, including failed ones.
+    pub fn write_requests(&self) -> u64 {
+        self.state.lock().unwrap().write_requests
+    }
+
+    /// Remove all stored points.
+    pub fn clear(&self) {
+        self.state.lock().unwrap().points.clear();
+    }
+
+    /// Run a Flux query against the stored points,
+    /// returning the result as annotated CSV.
+    pub fn query<Q: AsRef<str>>(&self, flux: Q) -> std::io::Result<String> {
+        let query = flux::Query::parse(flux.as_ref(), now_nanos())
+            .map_err(err_other)?;
+        Ok(query.execute(self.state.lock().unwrap().points.values()))
+    }
+
+    /// Delay every response by `latency`.
+    pub fn set_latency(&self, latency: std::time::Duration) {
+        self.state.lock().unwrap().latency = latency;
+    }
+
+    /// Answer the next `count` write requests with `fault`,
+    /// after any faults already queued.
+    pub fn fail_next_writes(&self, fault: InfluxiveMockFault, count: usize) {
+        self.state
+            .lock()
+            .unwrap()
+            .faults
+            .extend(std::iter::repeat_n(fault, count));
+    }
+}
+
+fn now_nanos() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as i64)
+        .unwrap_or_default()
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: BTreeMap<String, String>,
+    headers: BTreeMap<String, String>,
+    body: String,
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn new(status: &'static str) -> Self {
+        Self {
+            status,
+            content_type: "application/json; charset=utf-8",
+            body: String::new(),
+        }
+    }
+
+    fn json(status: &'static str, body: serde_json::Value) -> Self {
+        Self {
+            body: body.to_string(),
+            ..Self::new(status)
+        }
+    }
+
+    fn error(status: &'static str, code: &str, message: String) -> Self {
+        Self::json(
+            status,
+            serde_json::json!({ "code": code, "message": message }),
+        )
+    }
+}
+
+/// Decode `%XX` escapes and `+` of a url query component.
+fn url_decode(s: &str) -> String {
+    let mut out = Vec::with_capacity(s.len());
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match hex {
+                    Some(b) => {
+                        out.push(b);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+async fn read_request(
+    socket: &mut tokio::net::TcpStream,
+) -> std::io::Result<Request> {
+    use tokio::io::AsyncReadExt;
+
+    const MAX_HEADER: usize = 8192;
+
+    let mut buf = Vec::new();
+    let head_len = loop {
+        if let Some(at) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break at + 4;
+        }
+        if buf.len() > MAX_HEADER {
+            return Err(err_other("request header too large"));
+        }
+        let mut chunk = [0; 4096];
+        let read = socket.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(err_other("connection closed before request"));
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    };
+
+    let head = String::from_utf8_lossy(&buf[..head_len]).into_owned();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or("").split(' ');
+    let method = request_line.next().unwrap_or("").to_string();
+    let target = request_line.next().unwrap_or("");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let query = query
+        .split('&')
+        .filter(|kv| !kv.is_empty())
+        .map(|kv| {
+            let (k, v) = kv.split_once('=').unwrap_or((kv, ""));
+            (url_decode(k), url_decode(v))
+        })
+        .collect();
+
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.trim().to_ascii_lowercase(), v.trim().to_string()))
+        .collect::<BTreeMap<_, _>>();
+
+    let content_length = headers
+        .get("content-length")
+        .and_then(|l| l.parse::<usize>().ok())
+        .unwrap_or_default();
+
+    let mut body = buf.split_off(head_len);
+    while body.len() < content_length {
+        let mut chunk = [0; 4096];
+        let read = socket.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(err_other("connection closed before request body"));
+        }
+        body.extend_from_slice(&chunk[..read]);
+    }
+
+    Ok(Request {
+        method,
+        path: path.to_string(),
+        query,
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
+
+async fn serve(
+    mut socket: tokio::net::TcpStream,
+    state: &Mutex<State>,
+) -> std::io::Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let req = read_request(&mut socket).await?;
+
+    let latency = state.lock().unwrap().latency;
+    if !latency.is_zero() {
+        tokio::time::sleep(latency).await;
+    }
+
+    let res = route(&req, &mut state.lock().unwrap());
+
+    let mut response = format!(
+        "HTTP/1.1 {}\r\n\
+        Content-Type: {}\r\n\
+        Content-Length: {}\r\n\
+        X-Influxdb-Version: mock\r\n\
+        Connection: close\r\n\r\n",
+        res.status,
+        res.content_type,
+        res.body.len(),
+    );
+    if req.method != "HEAD" {
+        response.push_str(&res.body);
+    }
+
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
+
+fn route(req: &Request, state: &mut State) -> Response {
+    match (req.method.as_str(), req.path.as_str()) {
+        ("GET" | "HEAD", "/health") => Response::json(
+            "200 OK",
+            serde_json::json!({
+                "name": "influxdb",
+                "message": "ready for queries and writes",
+                "status": "pass",
+                "checks": [],
+                "version": "mock",
+            }),
+        ),
+        ("GET" | "HEAD", "/ping") => Response::new("204 No Content"),
+        ("GET", "/api/v2/setup") => Response::json(
+            "200 OK",
+            serde_json::json!({ "allowed": state.setup_allowed }),
+        ),
+        ("POST", "/api/v2/setup") => setup(req, state),
+        ("POST", "/api/v2/write") => match req.query.get("bucket") {
+            Some(bucket) => write(req, state, bucket),
+            None => Response::error(
+                "400 Bad Request",
+                "invalid",
+                "bucket is required".to_string(),
+            ),
+        },
+        ("POST", "/write") => match req.query.get("db") {
+            Some(db) => write(req, state, db),
+            None => Response::error(
+                "400 Bad Request",
+                "invalid",
+                "database is required".to_string(),
+            ),
+        },
+        ("POST", "/api/v2/query") => query(req, state),
+        (_, "/health" | "/ping" | "/api/v2/setup" | "/api/v2/write")
+        | (_, "/write" | "/api/v2/query") => Response::error(
+            "405 Method Not Allowed",
+            "method not allowed",
+            format!("method not allowed: {}", req.method),
+        ),
+        (_, path) => Response::error(
+            "404 Not Found",
+            "not found",
+            format!("path not found: {path}"),
+        ),
+    }
+}
+
+fn authorized(req: &Request, state: &State) -> bool {
+    let Some(token) = &state.token else {
+        return true;
+    };
+    req.headers
+        .get("authorization")
+        .and_then(|auth| {
+            auth.strip_prefix("Token ")
+                .or_else(|| auth.strip_prefix("Bearer "))
+        })
+        .is_some_and(|t| t == token)
+}
+
+fn unauthorized() -> Response {
+    Response::error(
+        "401 Unauthorized",
+        "unauthorized",
+        "unauthorized access".to_string(),
+    )
+}
+
+fn setup(req: &Request, state: &mut State) -> Response {
+    if !state.setup_allowed {
+        return Response::error(
+            "422 Unprocessable Entity",
+            "conflict",
+            "onboarding has already been completed".to_string(),
+        );
+    }
+
+    let body: serde_json::Value = match serde_json::from_str(&req.body) {
+        Ok(body) => body,
+        Err(err) => {
+            return Response::error(
+                "400 Bad Request",
+                "invalid",
+                err.to_string(),
+            )
+        }
+    };
+    let get = |k: &str| body.get(k).and_then(|v| v.as_str()).map(String::from);
+
+    let (Some(username), Some(org), Some(bucket)) =
+        (get("username"), get("org"), get("bucket"))
+    else {
+        return Response::error(
+            "400 Bad Request",
+            "invalid",
+            "username, org and bucket are required".to_string(),
+        );
+    };
+
+    state.setup_allowed = false;
+    state.org = org;
+    state.bucket = bucket;
+    state.token = Some(get("token").unwrap_or_else(|| "mock-token".into()));
+
+    Response::json(
+        "201 Created",
+        serde_json::json!({
+            "user": { "name": username },
+            "org": { "name": state.org },
+            "bucket": { "name": state.bucket },
+            "auth": { "token": state.token },
+        }),
+    )
+}
+
+fn write(req: &Request, state: &mut State, bucket: &str) -> Response {
+    if !authorized(req, state) {
+        return unauthorized();
+    }
+
+    state.write_requests += 1;
+
+    let fault = state.faults.pop_front();
+    match fault {
+        Some(InfluxiveMockFault::ServerError) => {
+            return Response::error(
+                "500 Internal Server Error",
+                "internal error",
+                "injected server error".to_string(),
+            )
+        }
+        Some(InfluxiveMockFault::TooManyRequests) => {
+            return Response::error(
+                "429 Too Many Requests",
+                "too many requests",
+                "injected rate limit".to_string(),
+            )
+        }
+        _ => (),
+    }
+
+    let precision = match line_protocol::precision_nanos(
+        req.query.get("precision").map(String::as_str),
+    ) {
+        Ok(precision) => precision,
+        Err(err) => return Response::error("400 Bad Request", "invalid", err),
+    };
+
+    let accepted = match fault {
+        Some(InfluxiveMockFault::PartialWrite { accepted }) => accepted,
+        _ => usize::MAX,
+    };
+
+    let now = now_nanos();
+    let mut errors = Vec::new();
+    let lines = req
+        .body
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'));
+    for (n, line) in lines.enumerate() {
+        if n >= accepted {
+            errors.push(format!("injected partial write at line {}", n + 1));
+            break;
+        }
+        match line_protocol::parse_line(bucket, line, precision, now) {
+            Ok(point) => state.store(point),
+            Err(err) => errors.push(format!("line {}: {err}", n + 1)),
+        }
+    }
+
+    if errors.is_empty() {
+        Response::new("204 No Content")
+    } else {
+        Response::error(
+            "400 Bad Request",
+            "invalid",
+            format!("partial write: {}", errors.join("; ")),
+        )
+    }
+}
+
+fn query(req: &Request, state: &State) -> Response {
+    if !authorized(req, state) {
+        return unauthorized();
+    }
+
+    let flux = if req.body.trim_start().starts_with('{') {
+        match serde_json::from_str::<serde_json::Value>(&req.body) {
+            Ok(body) => body
+                .get("query")
+                .and_then(|q| q.as_str())
+                .unwrap_or_default()
+                .to_string(),
+            Err(err) => {
+                return Response::error(
+                    "400 Bad Request",
+                    "invalid",
+                    err.to_string(),
+                )
+            }
+        }
+    } else {
+        req.body.clone()
+    };
+
+    match flux::Query::parse(&flux, now_nanos()) {
+        Ok(query) => Response {
+            status: "200 OK",
+            content_type: "text/csv; charset=utf-8",
+            body: query.execute(state.points.values()),
+        },
+        Err(err) => Response::error("400 Bad Request", "invalid", err),
+    }
+}
+
+#[cfg(test)]
+mod test;
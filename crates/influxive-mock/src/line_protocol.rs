@@ -0,0 +1,143 @@
+use super::*;
+
+/// The value of a field of a [Point].
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    /// Float value, e.g. `1.5`.
+    Float(f64),
+
+    /// Signed integer value, e.g. `3i`.
+    Integer(i64),
+
+    /// Unsigned integer value, e.g. `3u`.
+    UInteger(u64),
+
+    /// Bool value, e.g. `true` or `t`.
+    Boolean(bool),
+
+    /// String value, e.g. `"text"`.
+    String(String),
+}
+
+impl FieldValue {
+    /// The annotated CSV datatype of this value.
+    pub(crate) fn datatype(&self) -> &'static str {
+        match self {
+            FieldValue::Float(_) => "double",
+            FieldValue::Integer(_) => "long",
+            FieldValue::UInteger(_) => "unsignedLong",
+            FieldValue::Boolean(_) => "boolean",
+            FieldValue::String(_) => "string",
+        }
+    }
+
+    /// The value as a float, if it is numeric.
+    pub(crate) fn as_f64(&self) -> Option<f64> {
+        match self {
+            FieldValue::Float(v) => Some(*v),
+            FieldValue::Integer(v) => Some(*v as f64),
+            FieldValue::UInteger(v) => Some(*v as f64),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldValue::Float(v) => v.fmt(f),
+            FieldValue::Integer(v) => v.fmt(f),
+            FieldValue::UInteger(v) => v.fmt(f),
+            FieldValue::Boolean(v) => v.fmt(f),
+            FieldValue::String(v) => v.fmt(f),
+        }
+    }
+}
+
+/// A point written to the mock server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Point {
+    /// The bucket the point was written to.
+    pub bucket: String,
+
+    /// The measurement name.
+    pub measurement: String,
+
+    /// The tag set, sorted by key.
+    pub tags: BTreeMap<String, String>,
+
+    /// The fields, sorted by key.
+    pub fields: BTreeMap<String, FieldValue>,
+
+    /// Nanoseconds since the unix epoch.
+    pub timestamp: i64,
+}
+
+/// Nanoseconds per unit of a write `precision` query parameter.
+pub(crate) fn precision_nanos(precision: Option<&str>) -> Result<i64, String> {
+    match precision {
+        None | Some("ns") | Some("n") => Ok(1),
+        Some("us") | Some("u") => Ok(1_000),
+        Some("ms") => Ok(1_000_000),
+        Some("s") => Ok(1_000_000_000),
+        Some(p) => Err(format!("invalid precision: {p}")),
+    }
+}
+
+/// Nanoseconds since the unix epoch of `t`, if they fit an i64.
+fn unix_nanos(t: std::time::SystemTime) -> Option<i64> {
+    match t.duration_since(std::time::UNIX_EPOCH) {
+        Ok(d) => i64::try_from(d.as_nanos()).ok(),
+        Err(err) => i64::try_from(err.duration().as_nanos()).ok().map(|n| -n),
+    }
+}
+
+/// Parse one line of Line Protocol. Timestamps are multiplied by
+/// `precision`, missing timestamps are set to `now`.
+pub(crate) fn parse_line(
+    bucket: &str,
+    line: &str,
+    precision: i64,
+    now: i64,
+) -> Result<Point, String> {
+    let now_negative = now < 0;
+    let now = std::time::Duration::from_nanos(now.unsigned_abs());
+    let now = if now_negative {
+        std::time::UNIX_EPOCH - now
+    } else {
+        std::time::UNIX_EPOCH + now
+    };
+    let metric = parse_line_protocol(
+        line,
+        std::time::Duration::from_nanos(precision as u64),
+        now,
+    )
+    .map_err(|err| err.to_string())?;
+    let timestamp = unix_nanos(metric.timestamp)
+        .ok_or_else(|| format!("timestamp out of range: {line}"))?;
+
+    Ok(Point {
+        bucket: bucket.to_string(),
+        measurement: metric.name.into_string(),
+        tags: metric
+            .tags
+            .into_iter()
+            .map(|(k, v)| (k.into_string(), v.to_string()))
+            .collect(),
+        fields: metric
+            .fields
+            .into_iter()
+            .map(|(k, v)| {
+                let v = match v {
+                    DataType::Bool(v) => FieldValue::Boolean(v),
+                    DataType::F64(v) => FieldValue::Float(v),
+                    DataType::I64(v) => FieldValue::Integer(v),
+                    DataType::U64(v) => FieldValue::UInteger(v),
+                    DataType::String(v) => FieldValue::String(v.into_string()),
+                };
+                (k.into_string(), v)
+            })
+            .collect(),
+        timestamp,
+    })
+}
@@ -0,0 +1,236 @@
+use super::*;
+use influxive_writer::*;
+
+async fn local_mock(config: InfluxiveMockConfig) -> InfluxiveMock {
+    InfluxiveMock::new(config).await.unwrap()
+}
+
+fn mock_writer(mock: &InfluxiveMock) -> InfluxiveWriter {
+    InfluxiveWriter::with_token_auth(
+        InfluxiveWriterConfig::default()
+            .with_batch_duration(std::time::Duration::from_millis(5))
+            .with_retry_backoff(std::time::Duration::from_millis(1)),
+        mock.get_host(),
+        mock.get_bucket(),
+        mock.get_token().unwrap_or_default(),
+    )
+}
+
+async fn post(url: String, token: Option<&str>, body: &str) -> (u16, String) {
+    let mut req = reqwest::Client::new().post(url).body(body.to_string());
+    if let Some(token) = token {
+        req = req.header("Authorization", format!("Token {token}"));
+    }
+    let res = req.send().await.unwrap();
+    (res.status().as_u16(), res.text().await.unwrap())
+}
+
+#[test]
+fn line_protocol_parsing() {
+    let p = line_protocol::parse_line(
+        "b",
+        r#"my\ metric,host=a\,b,zone=z f=1.5,i=-3i,u=3u,b=t,s="say \"hi\", ok" 1700"#,
+        1_000_000_000,
+        0,
+    )
+    .unwrap();
+    assert_eq!("my metric", p.measurement);
+    assert_eq!("a,b", p.tags["host"]);
+    assert_eq!("z", p.tags["zone"]);
+    assert_eq!(FieldValue::Float(1.5), p.fields["f"]);
+    assert_eq!(FieldValue::Integer(-3), p.fields["i"]);
+    assert_eq!(FieldValue::UInteger(3), p.fields["u"]);
+    assert_eq!(FieldValue::Boolean(true), p.fields["b"]);
+    assert_eq!(FieldValue::String("say \"hi\", ok".into()), p.fields["s"]);
+    assert_eq!(1_700_000_000_000, p.timestamp);
+
+    assert_eq!(
+        42,
+        line_protocol::parse_line("b", "m v=1", 1, 42)
+            .unwrap()
+            .timestamp
+    );
+    let p =
+        line_protocol::parse_line("b", r#"m"q,t=a"b s="x y""#, 1, 0).unwrap();
+    assert_eq!("m\"q", p.measurement);
+    assert_eq!("a\"b", p.tags["t"]);
+    assert_eq!(FieldValue::String("x y".into()), p.fields["s"]);
+    assert!(line_protocol::parse_line("b", "m", 1, 0).is_err());
+    assert!(line_protocol::parse_line("b", "m v=1x", 1, 0).is_err());
+    assert!(line_protocol::precision_nanos(Some("h")).is_err());
+}
+
+#[test]
+fn flux_time_literals() {
+    let t = flux::parse_rfc3339("2023-03-04T05:06:07.25Z").unwrap();
+    assert_eq!("2023-03-04T05:06:07.25Z", flux::format_rfc3339(t));
+    assert_eq!(Some(t), flux::parse_rfc3339("2023-03-04T07:06:07.25+02:00"));
+    assert_eq!("1970-01-01T00:00:00Z", flux::format_rfc3339(0));
+    assert_eq!(
+        Some(-90 * 60 * 1_000_000_000),
+        flux::parse_duration("-1h30m")
+    );
+    assert_eq!(None, flux::parse_duration("5x"));
+}
+
+#[test]
+fn flux_query_filters() {
+    let mut points = Vec::new();
+    for (n, line) in [
+        "cpu,host=a usage=1.5,idle=90i 1000000000",
+        "cpu,host=b usage=2.5,idle=80i 2000000000",
+        "cpu,host=a usage=3.5,idle=70i 3000000000",
+        "mem,host=a used=10i 1000000000",
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        points.push(line_protocol::parse_line("b", line, 1, n as i64).unwrap());
+    }
+    let run = |flux: &str| {
+        flux::Query::parse(flux, 10_000_000_000)
+            .unwrap()
+            .execute(points.iter())
+    };
+
+    let all = run(r#"from(bucket: "b") |> range(start: -1h)"#);
+    assert_eq!(7, all.lines().filter(|l| l.starts_with(",,")).count());
+
+    let result = run(r#"from(bucket: "b")
+        |> range(start: 1970-01-01T00:00:01.5Z, stop: now())
+        |> filter(fn: (r) => r["_measurement"] == "cpu" and r._field == "usage")
+        |> filter(fn: (r) => not (r.host == "b" or r._value < 2.0))"#);
+    assert_eq!(
+        "#datatype,string,long,dateTime:RFC3339,dateTime:RFC3339,\
+         dateTime:RFC3339,double,string,string,string
+#group,false,false,true,true,false,false,true,true,true
+#default,_result,,,,,,,,
+,result,table,_start,_stop,_time,_value,_field,_measurement,host
+,,0,1970-01-01T00:00:01.5Z,1970-01-01T00:00:10Z,1970-01-01T00:00:03Z,3.5,usage,cpu,a
+",
+        result
+    );
+
+    assert!(run(r#"from(bucket: "other")"#).is_empty());
+    assert!(flux::Query::parse(r#"from(bucket: "b") |> sum()"#, 0).is_err());
+    assert!(flux::Query::parse(r#"buckets()"#, 0).is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_round_trip() {
+    let mock = local_mock(InfluxiveMockConfig::default()).await;
+    let writer = mock_writer(&mock);
+
+    for n in 0..3 {
+        writer.write_metric(
+            Metric::new(
+                std::time::UNIX_EPOCH + std::time::Duration::from_secs(n + 1),
+                "my.metric",
+            )
+            .with_field("value", n as f64)
+            .with_tag("tag", "test-tag"),
+        );
+    }
+    writer.flush().await.unwrap();
+
+    assert_eq!(3, mock.points().len());
+    let result = mock
+        .query(
+            r#"from(bucket: "influxive")
+|> range(start: 0)
+|> filter(fn: (r) => r.tag == "test-tag")"#,
+        )
+        .unwrap();
+    assert_eq!(3, result.matches("my.metric").count());
+
+    mock.clear();
+    assert!(mock.points().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fault_injection() {
+    let mock = local_mock(InfluxiveMockConfig::default()).await;
+    let writer = mock_writer(&mock);
+
+    mock.fail_next_writes(InfluxiveMockFault::ServerError, 1);
+    mock.fail_next_writes(InfluxiveMockFault::TooManyRequests, 1);
+    writer.write_metric(
+        Metric::new(std::time::SystemTime::now(), "retried")
+            .with_field("value", 1.0),
+    );
+    writer.flush().await.unwrap();
+    assert_eq!(3, mock.write_requests());
+    assert_eq!(1, mock.points().len());
+
+    mock.fail_next_writes(InfluxiveMockFault::PartialWrite { accepted: 1 }, 1);
+    let (status, body) = post(
+        format!("{}/api/v2/write?bucket=influxive", mock.get_host()),
+        None,
+        "partial a=1 1\npartial a=2 2",
+    )
+    .await;
+    assert_eq!(400, status);
+    assert!(body.contains("partial write"), "{body}");
+    assert_eq!(2, mock.points().len());
+
+    mock.set_latency(std::time::Duration::from_millis(100));
+    let start = std::time::Instant::now();
+    let health = writer.health().await;
+    assert!(health.reachable);
+    assert_eq!(Some("mock"), health.version.as_deref());
+    assert!(start.elapsed() >= std::time::Duration::from_millis(100));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn token_and_setup() {
+    let mock = local_mock(
+        InfluxiveMockConfig::default()
+            .with_token(Some("secret".into()))
+            .with_setup_allowed(true),
+    )
+    .await;
+    let host = mock.get_host();
+
+    let (status, _) =
+        post(format!("{host}/write?db=influxive"), Some("wrong"), "m v=1")
+            .await;
+    assert_eq!(401, status);
+    let (status, _) = post(
+        format!("{host}/write?db=influxive"),
+        Some("secret"),
+        "m v=1",
+    )
+    .await;
+    assert_eq!(204, status);
+
+    let health = reqwest::get(format!("{host}/health"))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(health.contains(r#""status":"pass""#), "{health}");
+
+    let (status, body) = post(
+        format!("{host}/api/v2/setup"),
+        None,
+        r#"{"username":"u","org":"o","bucket":"b","token":"t"}"#,
+    )
+    .await;
+    assert_eq!(201, status, "{body}");
+    assert_eq!("o", mock.get_org());
+    assert_eq!("b", mock.get_bucket());
+    assert_eq!(Some("t".to_string()), mock.get_token());
+
+    let (status, _) = post(format!("{host}/api/v2/setup"), None, "{}").await;
+    assert_eq!(422, status);
+
+    let (status, body) = post(
+        format!("{host}/api/v2/query?org=o"),
+        Some("t"),
+        r#"{"query":"from(bucket: \"influxive\") |> range(start: 0)"}"#,
+    )
+    .await;
+    assert_eq!(200, status);
+    assert_eq!(1, body.matches(",v,m").count(), "{body}");
+}
@@ -43,6 +43,11 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::Mutex;
 
+#[cfg(feature = "serde")]
+mod model;
+#[cfg(feature = "serde")]
+pub use model::*;
+
 type Erased = Box<dyn Fn() + 'static + Send + Sync>;
 struct ErasedMap(Mutex<HashMap<u64, Erased>>);
 
@@ -572,6 +577,9 @@ pub struct InfluxiveMeterProvider(
 impl InfluxiveMeterProvider {
     /// Construct a new InfluxiveMeterProvider instance with a given
     /// "Influxive" InfluxiveDB child process connector.
+    ///
+    /// Observable metrics are reported from a task on the current Tokio
+    /// runtime, or from a background thread if there is none.
     pub fn new(
         config: InfluxiveMeterProviderConfig,
         influxive: Arc<dyn MetricWriter + 'static + Send + Sync>,
@@ -580,17 +588,32 @@ impl InfluxiveMeterProvider {
 
         if let Some(interval) = config.observable_report_interval {
             let weak = Arc::downgrade(&strong);
-            tokio::task::spawn(async move {
-                let mut interval = tokio::time::interval(interval);
-                loop {
-                    interval.tick().await;
-                    if let Some(strong) = weak.upgrade() {
-                        strong.invoke();
-                    } else {
-                        break;
+            if tokio::runtime::Handle::try_current().is_ok() {
+                tokio::task::spawn(async move {
+                    let mut interval = tokio::time::interval(interval);
+                    loop {
+                        interval.tick().await;
+                        if let Some(strong) = weak.upgrade() {
+                            strong.invoke();
+                        } else {
+                            break;
+                        }
                     }
-                }
-            });
+                });
+            } else {
+                // no tokio runtime, report from a plain thread instead
+                std::thread::Builder::new()
+                    .name("influxive-otel-report".to_string())
+                    .spawn(move || loop {
+                        std::thread::sleep(interval);
+                        if let Some(strong) = weak.upgrade() {
+                            strong.invoke();
+                        } else {
+                            break;
+                        }
+                    })
+                    .expect("failed to spawn influxive report thread");
+            }
         }
 
         Self(influxive, strong)
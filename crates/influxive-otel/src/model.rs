@@ -0,0 +1,45 @@
+use super::*;
+
+/// Data-only description of an [InfluxiveMeterProviderConfig], e.g.
+/// loaded from a TOML or YAML file. Missing values take the
+/// [InfluxiveMeterProviderConfig] defaults.
+///
+/// ```toml
+/// observable_report_interval = "10s"
+/// ```
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct InfluxiveMeterProviderConfigModel {
+    /// See [InfluxiveMeterProviderConfig::observable_report_interval].
+    /// Set to `null` to disable periodic reporting.
+    #[serde(with = "humantime_serde")]
+    pub observable_report_interval: Option<std::time::Duration>,
+
+    /// Disable periodic reporting of observable metrics, for formats
+    /// like TOML that cannot express a `null` interval.
+    /// Defaults to `false`.
+    pub disable_observable_report: bool,
+}
+
+impl Default for InfluxiveMeterProviderConfigModel {
+    fn default() -> Self {
+        Self {
+            observable_report_interval: InfluxiveMeterProviderConfig::default()
+                .observable_report_interval,
+            disable_observable_report: false,
+        }
+    }
+}
+
+impl From<InfluxiveMeterProviderConfigModel> for InfluxiveMeterProviderConfig {
+    fn from(model: InfluxiveMeterProviderConfigModel) -> Self {
+        Self {
+            observable_report_interval: if model.disable_observable_report {
+                None
+            } else {
+                model.observable_report_interval
+            },
+        }
+    }
+}
@@ -1,25 +1,27 @@
 use super::*;
-use influxive_child_svc::*;
+use influxive_mock::*;
+use influxive_writer::*;
+
+/// A writer sending to an in-memory InfluxDB, so tests can run offline.
+async fn mock_writer() -> (InfluxiveMock, Arc<InfluxiveWriter>) {
+    let mock = InfluxiveMock::new(InfluxiveMockConfig::default())
+        .await
+        .unwrap();
+    let writer = Arc::new(InfluxiveWriter::with_token_auth(
+        InfluxiveWriterConfig::default()
+            .with_batch_duration(std::time::Duration::from_millis(5)),
+        mock.get_host(),
+        mock.get_bucket(),
+        "",
+    ));
+    (mock, writer)
+}
 
 #[tokio::test(flavor = "multi_thread")]
 async fn observable_report_interval() {
     use influxive_otel_atomic_obs::MeterExt;
 
-    let tmp = tempfile::tempdir().unwrap();
-
-    let i = Arc::new(
-        InfluxiveChildSvc::new(
-            InfluxiveChildSvcConfig::default()
-                .with_database_path(Some(tmp.path().into()))
-                .with_metric_write(
-                    InfluxiveWriterConfig::default().with_batch_duration(
-                        std::time::Duration::from_millis(5),
-                    ),
-                ),
-        )
-        .await
-        .unwrap(),
-    );
+    let (mock, i) = mock_writer().await;
 
     let meter_provider = InfluxiveMeterProvider::new(
         InfluxiveMeterProviderConfig::default()
@@ -45,13 +47,12 @@ async fn observable_report_interval() {
 
     tokio::time::sleep(std::time::Duration::from_millis(200)).await;
 
-    let result = i
+    let result = mock
         .query(
             r#"from(bucket: "influxive")
 |> range(start: -15m, stop: now())
 "#,
         )
-        .await
         .unwrap();
 
     println!("{result}");
@@ -62,7 +63,6 @@ async fn observable_report_interval() {
         "expected result_count >= 5, got: {result_count}"
     );
 
-    i.shutdown();
     drop(i);
 }
 
@@ -71,25 +71,11 @@ async fn sanity() {
     use influxive_otel_atomic_obs::MeterExt;
     use opentelemetry_api::metrics::MeterProvider;
 
-    let tmp = tempfile::tempdir().unwrap();
-
-    let i = Arc::new(
-        InfluxiveChildSvc::new(
-            InfluxiveChildSvcConfig::default()
-                .with_database_path(Some(tmp.path().into()))
-                .with_metric_write(
-                    InfluxiveWriterConfig::default().with_batch_duration(
-                        std::time::Duration::from_millis(5),
-                    ),
-                ),
-        )
-        .await
-        .unwrap(),
-    );
+    let (mock, i) = mock_writer().await;
 
-    println!("{}", i.get_host());
+    println!("{}", mock.get_host());
 
-    i.ping().await.unwrap();
+    assert!(i.health().await.reachable);
 
     let meter_provider = InfluxiveMeterProvider::new(
         InfluxiveMeterProviderConfig {
@@ -235,13 +221,12 @@ async fn sanity() {
 
     tokio::time::sleep(std::time::Duration::from_secs(2)).await;
 
-    let result = i
+    let result = mock
         .query(
             r#"from(bucket: "influxive")
 |> range(start: -15m, stop: now())
 "#,
         )
-        .await
         .unwrap();
 
     println!("{result}");
@@ -270,15 +255,68 @@ async fn sanity() {
     assert_eq!(12, result.matches("m_obs_g_u64_a").count());
     assert_eq!(12, result.matches("m_obs_g_u64_r").count());
 
-    println!("about to shutdown influxive-child-svc");
-    i.shutdown();
-
-    println!("about to drop influxive-child-svc");
     drop(i);
 
-    println!("about to close tempfile::tempdir");
-    // okay if this fails on windows...
-    let _ = tmp.close();
-
     println!("test complete");
 }
+
+#[test]
+fn meter_provider_without_runtime() {
+    use influxive_otel_atomic_obs::MeterExt;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("metrics.influx");
+
+    // no tokio runtime, both the writer and the observable
+    // reporting run on background threads
+    let writer = Arc::new(influxive_writer::InfluxiveWriter::with_token_auth(
+        InfluxiveWriterConfig::create_with_influx_file(path.clone())
+            .with_batch_duration(std::time::Duration::from_millis(5)),
+        "",
+        "",
+        "",
+    ));
+    let meter_provider = InfluxiveMeterProvider::new(
+        InfluxiveMeterProviderConfig::default()
+            .with_observable_report_interval(Some(
+                std::time::Duration::from_millis(5),
+            )),
+        writer,
+    );
+
+    let (metric, _) = opentelemetry_api::metrics::MeterProvider::meter(
+        &meter_provider,
+        "test",
+    )
+    .u64_observable_counter_atomic("m_obs_sync", 0)
+    .init();
+    metric.add(1);
+
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert!(content.contains("m_obs_sync value=1u "), "{content}");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn config_model() {
+    let config: InfluxiveMeterProviderConfig =
+        toml::from_str::<InfluxiveMeterProviderConfigModel>(
+            "observable_report_interval = \"10s\"",
+        )
+        .unwrap()
+        .into();
+    assert_eq!(
+        Some(std::time::Duration::from_secs(10)),
+        config.observable_report_interval
+    );
+
+    let config: InfluxiveMeterProviderConfig =
+        toml::from_str::<InfluxiveMeterProviderConfigModel>(
+            "disable_observable_report = true",
+        )
+        .unwrap()
+        .into();
+    assert_eq!(None, config.observable_report_interval);
+}
@@ -0,0 +1,527 @@
+#![deny(missing_docs)]
+#![deny(warnings)]
+#![deny(unsafe_code)]
+//! Serve influxive metrics on a Prometheus scrape endpoint.
+//!
+//! [InfluxivePrometheus] is a [MetricWriter] that keeps the latest value
+//! of every series in memory and serves them on a local HTTP listener in
+//! the Prometheus text exposition format. Combine it with an
+//! `InfluxiveWriter` through [influxive_core::MultiMetricWriter] to feed
+//! both InfluxDB and Prometheus from a single `InfluxiveMeterProvider`.
+//!
+//! Names are mapped to valid Prometheus names: a metric `my.metric` with
+//! field `value` becomes `my_metric`, any other field `f` becomes
+//! `my_metric_f`, and tags become labels. Tags that map to the same label
+//! name get a numeric suffix, e.g. `host_name` and `host_name_1`, and so
+//! do metrics, e.g. `my_metric` for `my.metric` and `my_metric_1` for a
+//! `my-metric` written later, for as long as the earlier one has series.
+//! Label names starting with `__` are reserved by Prometheus, such tags
+//! start with a single underscore instead, e.g. `__name__` becomes
+//! `_name__`. String fields are not exported.
+//!
+//! ## Example
+//!
+//! ```
+//! # #[tokio::main(flavor = "multi_thread")]
+//! # async fn main() {
+//! use influxive_core::*;
+//! use influxive_prometheus::*;
+//!
+//! let prometheus = InfluxivePrometheus::new(
+//!     InfluxivePrometheusConfig::default()
+//!         .with_bind_addr(([127, 0, 0, 1], 0).into()),
+//! )
+//! .await
+//! .unwrap();
+//!
+//! println!("scrape http://{}/metrics", prometheus.local_addr());
+//!
+//! prometheus.write_metric(
+//!     Metric::new(std::time::SystemTime::now(), "my.metric")
+//!         .with_field("value", 3.14)
+//!         .with_tag("tag", "test-tag"),
+//! );
+//!
+//! assert!(prometheus.render().contains(r#"my_metric{tag="test-tag"} 3.14"#));
+//! # }
+//! ```
+
+use influxive_core::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Influxive Prometheus exporter configuration.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct InfluxivePrometheusConfig {
+    /// Address the scrape endpoint listens on.
+    /// Defaults to `127.0.0.1:9464`.
+    pub bind_addr: std::net::SocketAddr,
+
+    /// HTTP path serving the metrics.
+    /// Defaults to `/metrics`.
+    pub path: String,
+
+    /// Series that have not been written within this timespan are
+    /// dropped from the output.
+    /// Defaults to `5m`.
+    pub stale_after: std::time::Duration,
+
+    /// Scrape connections that have not sent a complete request within
+    /// this timespan are closed.
+    /// Defaults to `10s`.
+    pub read_timeout: std::time::Duration,
+}
+
+impl Default for InfluxivePrometheusConfig {
+    fn default() -> Self {
+        Self {
+            bind_addr: ([127, 0, 0, 1], 9464).into(),
+            path: "/metrics".to_string(),
+            stale_after: std::time::Duration::from_secs(60 * 5),
+            read_timeout: std::time::Duration::from_secs(10),
+        }
+    }
+}
+
+impl InfluxivePrometheusConfig {
+    /// Apply [InfluxivePrometheusConfig::bind_addr].
+    pub fn with_bind_addr(mut self, bind_addr: std::net::SocketAddr) -> Self {
+        self.bind_addr = bind_addr;
+        self
+    }
+
+    /// Apply [InfluxivePrometheusConfig::path].
+    pub fn with_path(mut self, path: String) -> Self {
+        self.path = path;
+        self
+    }
+
+    /// Apply [InfluxivePrometheusConfig::stale_after].
+    pub fn with_stale_after(
+        mut self,
+        stale_after: std::time::Duration,
+    ) -> Self {
+        self.stale_after = stale_after;
+        self
+    }
+
+    /// Apply [InfluxivePrometheusConfig::read_timeout].
+    pub fn with_read_timeout(
+        mut self,
+        read_timeout: std::time::Duration,
+    ) -> Self {
+        self.read_timeout = read_timeout;
+        self
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct SeriesKey {
+    name: String,
+    labels: Vec<(String, String)>,
+}
+
+struct Sample {
+    value: f64,
+    updated: std::time::Instant,
+}
+
+struct Series {
+    samples: HashMap<SeriesKey, Sample>,
+    last_expired: std::time::Instant,
+
+    /// Exported name by metric name and field.
+    names: HashMap<String, HashMap<String, String>>,
+
+    /// Exported names in use, so different metrics mapping to the same
+    /// name don't merge.
+    exported: std::collections::HashSet<String>,
+}
+
+impl Series {
+    fn expire(&mut self, stale_after: std::time::Duration) {
+        self.samples
+            .retain(|_, s| s.updated.elapsed() < stale_after);
+        self.last_expired = std::time::Instant::now();
+
+        // free the names of metrics without series
+        let live = self
+            .samples
+            .keys()
+            .map(|k| k.name.as_str())
+            .collect::<std::collections::HashSet<_>>();
+        self.exported.retain(|n| live.contains(n.as_str()));
+        for fields in self.names.values_mut() {
+            fields.retain(|_, n| live.contains(n.as_str()));
+        }
+        self.names.retain(|_, fields| !fields.is_empty());
+    }
+
+    /// The exported name of a field of a metric, suffixed `_1`, `_2`, ...
+    /// if another metric already maps to it.
+    fn name(&mut self, name: &str, field: &str) -> String {
+        if let Some(exported) =
+            self.names.get(name).and_then(|fields| fields.get(field))
+        {
+            return exported.clone();
+        }
+
+        let base = if field == "value" {
+            metric_name(name)
+        } else {
+            metric_name(&format!("{name}_{field}"))
+        };
+        let exported = std::iter::once(base.clone())
+            .chain((1..).map(|n| format!("{base}_{n}")))
+            .find(|n| !self.exported.contains(n))
+            .unwrap();
+        if exported != base {
+            tracing::warn!(
+                name,
+                field,
+                exported,
+                "prometheus name already taken by another metric"
+            );
+        }
+
+        self.exported.insert(exported.clone());
+        self.names
+            .entry(name.to_string())
+            .or_default()
+            .insert(field.to_string(), exported.clone());
+        exported
+    }
+}
+
+struct Store {
+    stale_after: std::time::Duration,
+    series: Mutex<Series>,
+}
+
+impl Store {
+    fn write_metric(&self, metric: Metric) {
+        let Metric {
+            name, fields, tags, ..
+        } = metric;
+
+        let labels = labels(
+            tags.into_iter()
+                .map(|(k, v)| (label_name(k.as_str()), v.to_string()))
+                .collect(),
+        );
+
+        let now = std::time::Instant::now();
+        let mut lock = self.series.lock().unwrap();
+
+        // without scrapes, stale series would otherwise never be dropped
+        if lock.last_expired.elapsed() >= self.stale_after {
+            lock.expire(self.stale_after);
+        }
+
+        for (field, value) in fields {
+            let value = match value {
+                DataType::Bool(b) => {
+                    if b {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                }
+                DataType::F64(f) => f,
+                DataType::I64(i) => i as f64,
+                DataType::U64(u) => u as f64,
+                // prometheus samples are numeric
+                DataType::String(_) => continue,
+            };
+
+            let name = lock.name(name.as_str(), field.as_str());
+
+            lock.samples.insert(
+                SeriesKey {
+                    name,
+                    labels: labels.clone(),
+                },
+                Sample {
+                    value,
+                    updated: now,
+                },
+            );
+        }
+    }
+
+    fn render(&self) -> String {
+        use std::fmt::Write;
+
+        let mut series = {
+            let mut lock = self.series.lock().unwrap();
+            lock.expire(self.stale_after);
+            lock.samples
+                .iter()
+                .map(|(k, s)| (k.name.clone(), k.labels.clone(), s.value))
+                .collect::<Vec<_>>()
+        };
+        series.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+
+        let mut out = String::new();
+        let mut cur_name = None;
+
+        for (name, labels, value) in series {
+            if cur_name.as_ref() != Some(&name) {
+                let _ = writeln!(out, "# TYPE {name} untyped");
+                cur_name = Some(name.clone());
+            }
+
+            out.push_str(&name);
+            if !labels.is_empty() {
+                out.push('{');
+                for (i, (k, v)) in labels.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    let _ = write!(out, "{k}=\"{}\"", label_value(v));
+                }
+                out.push('}');
+            }
+            let _ = writeln!(out, " {}", sample_value(value));
+        }
+
+        out
+    }
+}
+
+/// Map to a valid metric name, i.e. `[a-zA-Z_:][a-zA-Z0-9_:]*`.
+fn metric_name(name: &str) -> String {
+    sanitize(name, true)
+}
+
+/// Map to a valid label name, i.e. `[a-zA-Z_][a-zA-Z0-9_]*`,
+/// not starting with the reserved `__`.
+fn label_name(name: &str) -> String {
+    let name = sanitize(name, false);
+    match name.strip_prefix("__") {
+        Some(rest) => format!("_{}", rest.trim_start_matches('_')),
+        None => name,
+    }
+}
+
+/// Sort labels, renaming duplicate label names by appending
+/// `_1`, `_2`, ... so the exposition stays valid.
+fn labels(mut labels: Vec<(String, String)>) -> Vec<(String, String)> {
+    labels.sort();
+
+    if labels.windows(2).all(|w| w[0].0 != w[1].0) {
+        return labels;
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    let mut out = Vec::with_capacity(labels.len());
+    let mut dups = Vec::new();
+    for (k, v) in labels {
+        if seen.insert(k.clone()) {
+            out.push((k, v));
+        } else {
+            dups.push((k, v));
+        }
+    }
+    for (k, v) in dups {
+        let k = (1..)
+            .map(|n| format!("{k}_{n}"))
+            .find(|k| !seen.contains(k))
+            .unwrap();
+        seen.insert(k.clone());
+        out.push((k, v));
+    }
+    out.sort();
+    out
+}
+
+fn sanitize(name: &str, allow_colon: bool) -> String {
+    let mut out = String::with_capacity(name.len() + 1);
+    for (i, c) in name.chars().enumerate() {
+        if i == 0 && c.is_ascii_digit() {
+            out.push('_');
+        }
+        if c.is_ascii_alphanumeric() || c == '_' || (allow_colon && c == ':') {
+            out.push(c);
+        } else {
+            out.push('_');
+        }
+    }
+    if out.is_empty() {
+        out.push('_');
+    }
+    out
+}
+
+fn label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn sample_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value == f64::INFINITY {
+        "+Inf".to_string()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// A [MetricWriter] serving the latest value of each series on a
+/// Prometheus scrape endpoint. The listener is shut down on drop.
+pub struct InfluxivePrometheus {
+    store: Arc<Store>,
+    local_addr: std::net::SocketAddr,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for InfluxivePrometheus {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl InfluxivePrometheus {
+    /// Bind the scrape endpoint and start serving.
+    pub async fn new(
+        config: InfluxivePrometheusConfig,
+    ) -> std::io::Result<Self> {
+        let listener = tokio::net::TcpListener::bind(config.bind_addr).await?;
+        let local_addr = listener.local_addr()?;
+
+        let store = Arc::new(Store {
+            stale_after: config.stale_after,
+            series: Mutex::new(Series {
+                samples: HashMap::new(),
+                last_expired: std::time::Instant::now(),
+                names: HashMap::new(),
+                exported: std::collections::HashSet::new(),
+            }),
+        });
+
+        let weak = Arc::downgrade(&store);
+        let path: Arc<str> = config.path.into();
+        let read_timeout = config.read_timeout;
+        let task = tokio::task::spawn(async move {
+            loop {
+                let socket = match listener.accept().await {
+                    Ok((socket, _)) => socket,
+                    Err(err) => {
+                        tracing::warn!(?err, "prometheus accept error");
+                        continue;
+                    }
+                };
+                let store = match weak.upgrade() {
+                    Some(store) => store,
+                    None => break,
+                };
+                let path = path.clone();
+                tokio::task::spawn(async move {
+                    if let Err(err) =
+                        serve(socket, &store, &path, read_timeout).await
+                    {
+                        tracing::debug!(?err, "prometheus scrape error");
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            store,
+            local_addr,
+            task,
+        })
+    }
+
+    /// The address the scrape endpoint is bound to.
+    pub fn local_addr(&self) -> std::net::SocketAddr {
+        self.local_addr
+    }
+
+    /// Render the current series in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        self.store.render()
+    }
+
+    /// Record the latest values of a metric.
+    pub fn write_metric(&self, metric: Metric) {
+        self.store.write_metric(metric);
+    }
+}
+
+impl MetricWriter for InfluxivePrometheus {
+    fn write_metric(&self, metric: Metric) {
+        InfluxivePrometheus::write_metric(self, metric);
+    }
+}
+
+async fn serve(
+    mut socket: tokio::net::TcpStream,
+    store: &Store,
+    path: &str,
+    read_timeout: std::time::Duration,
+) -> std::io::Result<()> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const MAX_HEADER: usize = 8192;
+
+    let mut buf = Vec::new();
+    tokio::time::timeout(read_timeout, async {
+        while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+            if buf.len() > MAX_HEADER {
+                return Err(err_other("request header too large"));
+            }
+            let mut chunk = [0; 1024];
+            let read = socket.read(&mut chunk).await?;
+            if read == 0 {
+                return Err(err_other("connection closed before request"));
+            }
+            buf.extend_from_slice(&chunk[..read]);
+        }
+        Ok(())
+    })
+    .await
+    .map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            "timed out reading request",
+        )
+    })??;
+
+    let head = String::from_utf8_lossy(&buf);
+    let mut request_line = head.lines().next().unwrap_or("").split(' ');
+    let method = request_line.next().unwrap_or("");
+    let target = request_line.next().unwrap_or("");
+    let target = target.split('?').next().unwrap_or("");
+
+    let (status, body) = if method != "GET" && method != "HEAD" {
+        ("405 Method Not Allowed", String::new())
+    } else if target != path {
+        ("404 Not Found", String::new())
+    } else {
+        ("200 OK", store.render())
+    };
+
+    let mut response = format!(
+        "HTTP/1.1 {status}\r\n\
+        Content-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+        Content-Length: {}\r\n\
+        Connection: close\r\n\r\n",
+        body.len(),
+    );
+    if method != "HEAD" {
+        response.push_str(&body);
+    }
+
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,260 @@
+use super::*;
+
+async fn scrape(addr: std::net::SocketAddr, path: &str) -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut socket = tokio::net::TcpStream::connect(addr).await.unwrap();
+    socket
+        .write_all(
+            format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .as_bytes(),
+        )
+        .await
+        .unwrap();
+    let mut response = String::new();
+    socket.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+async fn local_prometheus(
+    config: InfluxivePrometheusConfig,
+) -> InfluxivePrometheus {
+    InfluxivePrometheus::new(config.with_bind_addr(([127, 0, 0, 1], 0).into()))
+        .await
+        .unwrap()
+}
+
+#[test]
+fn name_mapping() {
+    assert_eq!("my_metric_ms", metric_name("my.metric-ms"));
+    assert_eq!("ns:my_metric", metric_name("ns:my metric"));
+    assert_eq!("_9lives", metric_name("9lives"));
+    assert_eq!("host_name", label_name("host:name"));
+    // `__` is reserved for prometheus' own labels
+    assert_eq!("_name__", label_name("__name__"));
+    assert_eq!("_x", label_name(".:x"));
+    assert_eq!("_", label_name("__"));
+    assert_eq!(r#"a \"b\"\\c\nd"#, label_value("a \"b\"\\c\nd"));
+    assert_eq!("+Inf", sample_value(f64::INFINITY));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn render_latest_value_per_series() {
+    let p = local_prometheus(InfluxivePrometheusConfig::default()).await;
+
+    for n in 0..3 {
+        p.write_metric(
+            Metric::new(std::time::SystemTime::now(), "my.metric")
+                .with_field("value", n)
+                .with_field("count", n * 2)
+                .with_field("ok", true)
+                .with_field("note", "strings are skipped")
+                .with_tag("zone", "b")
+                .with_tag("host", "h1"),
+        );
+    }
+    p.write_metric(
+        Metric::new(std::time::SystemTime::now(), "my.metric")
+            .with_field("value", 1.5)
+            .with_tag("host", "h2"),
+    );
+
+    assert_eq!(
+        r#"# TYPE my_metric untyped
+my_metric{host="h1",zone="b"} 2
+my_metric{host="h2"} 1.5
+# TYPE my_metric_count untyped
+my_metric_count{host="h1",zone="b"} 4
+# TYPE my_metric_ok untyped
+my_metric_ok{host="h1",zone="b"} 1
+"#,
+        p.render()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stale_series_expire() {
+    let p = local_prometheus(
+        InfluxivePrometheusConfig::default()
+            .with_stale_after(std::time::Duration::from_millis(50)),
+    )
+    .await;
+
+    p.write_metric(
+        Metric::new(std::time::SystemTime::now(), "old").with_field("value", 1),
+    );
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    p.write_metric(
+        Metric::new(std::time::SystemTime::now(), "new").with_field("value", 2),
+    );
+
+    assert_eq!("# TYPE new untyped\nnew 2\n", p.render());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stale_series_expire_without_scrapes() {
+    let p = local_prometheus(
+        InfluxivePrometheusConfig::default()
+            .with_stale_after(std::time::Duration::from_millis(50)),
+    )
+    .await;
+
+    for n in 0..10 {
+        p.write_metric(
+            Metric::new(std::time::SystemTime::now(), format!("old{n}"))
+                .with_field("value", n),
+        );
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    p.write_metric(
+        Metric::new(std::time::SystemTime::now(), "new").with_field("value", 2),
+    );
+
+    assert_eq!(1, p.store.series.lock().unwrap().samples.len());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn duplicate_label_names_are_suffixed() {
+    let p = local_prometheus(InfluxivePrometheusConfig::default()).await;
+
+    p.write_metric(
+        Metric::new(std::time::SystemTime::now(), "m")
+            .with_field("value", 1)
+            .with_tag("host.name", "a")
+            .with_tag("host:name", "b")
+            .with_tag("host_name_1", "c"),
+    );
+
+    assert_eq!(
+        "# TYPE m untyped\n\
+        m{host_name=\"a\",host_name_1=\"c\",host_name_2=\"b\"} 1\n",
+        p.render()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn colliding_metric_names_are_suffixed() {
+    let p = local_prometheus(
+        InfluxivePrometheusConfig::default()
+            .with_stale_after(std::time::Duration::from_millis(50)),
+    )
+    .await;
+    let write = |name: &'static str, value: i64| {
+        p.write_metric(
+            Metric::new(std::time::SystemTime::now(), name)
+                .with_field("value", value)
+                .with_tag("__name__", "x"),
+        );
+    };
+
+    write("my.metric", 1);
+    write("my-metric", 2);
+    write("my.metric", 3);
+
+    assert_eq!(
+        "# TYPE my_metric untyped\n\
+        my_metric{_name__=\"x\"} 3\n\
+        # TYPE my_metric_1 untyped\n\
+        my_metric_1{_name__=\"x\"} 2\n",
+        p.render()
+    );
+
+    // once its series are gone, the name is free again
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert_eq!("", p.render());
+    write("my-metric", 4);
+    assert_eq!(
+        "# TYPE my_metric untyped\nmy_metric{_name__=\"x\"} 4\n",
+        p.render()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scrape_http() {
+    let p = local_prometheus(InfluxivePrometheusConfig::default()).await;
+
+    p.write_metric(
+        Metric::new(std::time::SystemTime::now(), "my.metric")
+            .with_field("value", 42),
+    );
+
+    let response = scrape(p.local_addr(), "/metrics").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+    assert!(
+        response.ends_with("\r\n\r\n# TYPE my_metric untyped\nmy_metric 42\n")
+    );
+
+    let response = scrape(p.local_addr(), "/other").await;
+    assert!(response.starts_with("HTTP/1.1 404"), "{response}");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn slow_client_times_out() {
+    use tokio::io::AsyncReadExt;
+
+    let p = local_prometheus(
+        InfluxivePrometheusConfig::default()
+            .with_read_timeout(std::time::Duration::from_millis(50)),
+    )
+    .await;
+
+    let mut socket = tokio::net::TcpStream::connect(p.local_addr())
+        .await
+        .unwrap();
+    let mut buf = Vec::new();
+    let read = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        socket.read_to_end(&mut buf),
+    )
+    .await
+    .unwrap();
+    assert!(read.map(|n| n == 0).unwrap_or(true));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn meter_provider_feeds_influx_and_prometheus() {
+    use influxive_writer::*;
+    use std::io::BufRead;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("metrics.influx");
+
+    let writer = InfluxiveWriter::with_token_auth(
+        InfluxiveWriterConfig::create_with_influx_file(path.clone())
+            .with_batch_duration(std::time::Duration::from_millis(5)),
+        "",
+        "",
+        "",
+    );
+    let p =
+        Arc::new(local_prometheus(InfluxivePrometheusConfig::default()).await);
+
+    let meter_provider = influxive_otel::InfluxiveMeterProvider::new(
+        Default::default(),
+        Arc::new(MultiMetricWriter::new(vec![Arc::new(writer), p.clone()])),
+    );
+
+    let m = opentelemetry_api::metrics::MeterProvider::meter(
+        &meter_provider,
+        "test",
+    )
+    .f64_histogram("my.histogram")
+    .init();
+    m.record(2.5, &[opentelemetry_api::KeyValue::new("k", "v")]);
+
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let file = std::fs::File::open(&path).unwrap();
+    let line = std::io::BufReader::new(file)
+        .lines()
+        .next()
+        .unwrap()
+        .unwrap();
+    assert!(line.starts_with("my.histogram,k=v value=2.5 "), "{line}");
+
+    let response = scrape(p.local_addr(), "/metrics").await;
+    assert!(
+        response.contains("my_histogram{k=\"v\"} 2.5\n"),
+        "{response}"
+    );
+}
@@ -0,0 +1,272 @@
+//! Batching loop overhead, measured against a backend that discards
+//! metrics, so only the writer itself is measured.
+//!
+//! Run with `cargo bench -p influxive-writer`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use influxive_core::Metric;
+use influxive_writer::types::*;
+use influxive_writer::*;
+use std::sync::Arc;
+
+struct NullBackend(usize);
+
+impl Backend for NullBackend {
+    fn buffer_metric(&mut self, _metric: Metric) {
+        self.0 += 1;
+    }
+
+    fn buffer_count(&self) -> usize {
+        self.0
+    }
+
+    fn send(&mut self) -> BackendFuture<'_, ()> {
+        Box::pin(async move {
+            self.0 = 0;
+            Ok(())
+        })
+    }
+
+    fn clear(&mut self) {
+        self.0 = 0;
+    }
+}
+
+#[derive(Debug)]
+struct NullBackendFactory;
+
+impl BackendFactory for NullBackendFactory {
+    fn with_token_auth(
+        &self,
+        _host: String,
+        _bucket: String,
+        _token: String,
+    ) -> Box<dyn Backend + 'static + Send + Sync> {
+        Box::new(NullBackend(0))
+    }
+}
+
+fn writer(batch_duration: std::time::Duration) -> InfluxiveWriter {
+    InfluxiveWriter::with_token_auth(
+        InfluxiveWriterConfig::default()
+            .with_batch_duration(batch_duration)
+            .with_backend(Arc::new(NullBackendFactory)),
+        "",
+        "",
+        "",
+    )
+}
+
+/// Throughput of writing a batch of metrics and flushing them.
+fn write_and_flush(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("write_and_flush");
+
+    for count in [1, 100, 1000] {
+        let writer = runtime
+            .block_on(async { writer(std::time::Duration::from_millis(10)) });
+
+        group.throughput(criterion::Throughput::Elements(count));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(count),
+            &count,
+            |b, &count| {
+                b.to_async(&runtime).iter(|| async {
+                    for _ in 0..count {
+                        writer.write_metric(
+                            Metric::new(
+                                std::time::SystemTime::UNIX_EPOCH,
+                                "bench",
+                            )
+                            .with_field("value", 1),
+                        );
+                    }
+                    writer.flush().await.unwrap();
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+/// Round trip through the batching loop of an otherwise idle writer.
+/// With a short batch duration, a timer driven loop would be kept busy
+/// waking up even though nothing is buffered. That the loop doesn't is
+/// asserted by the `idle_writer_does_not_wake_up` test, which counts
+/// the wakeups.
+fn idle_flush(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let writer = runtime
+        .block_on(async { writer(std::time::Duration::from_micros(100)) });
+
+    c.bench_function("idle_flush", |b| {
+        b.to_async(&runtime).iter(|| writer.flush());
+    });
+}
+
+/// Commands of the single ordered queue the writer used before
+/// sharding its ingest path, kept as the baseline for [producers].
+enum MpscCmd {
+    Metric(Metric),
+    Flush(tokio::sync::oneshot::Sender<()>),
+}
+
+/// The pre-sharding ingest path: one bounded channel carrying metrics
+/// to a batching task, which buffers them in a backend.
+struct MpscWriter {
+    send: tokio::sync::mpsc::Sender<MpscCmd>,
+    dropped: std::sync::atomic::AtomicU64,
+}
+
+impl MpscWriter {
+    fn new(runtime: &tokio::runtime::Runtime, capacity: usize) -> Self {
+        let (send, mut recv) = tokio::sync::mpsc::channel(capacity);
+        runtime.spawn(async move {
+            let mut backend = NullBackend(0);
+            while let Some(cmd) = recv.recv().await {
+                match cmd {
+                    MpscCmd::Metric(metric) => backend.buffer_metric(metric),
+                    MpscCmd::Flush(respond) => {
+                        backend.send().await.unwrap();
+                        let _ = respond.send(());
+                    }
+                }
+            }
+        });
+        Self {
+            send,
+            dropped: std::sync::atomic::AtomicU64::new(0),
+        }
+    }
+
+    fn write_metric(&self, metric: Metric) {
+        if self.send.try_send(MpscCmd::Metric(metric)).is_err() {
+            self.dropped
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    async fn flush(&self) {
+        let (respond, done) = tokio::sync::oneshot::channel();
+        self.send.send(MpscCmd::Flush(respond)).await.ok().unwrap();
+        done.await.unwrap();
+    }
+}
+
+/// Time `iters` rounds of `producers` threads concurrently writing
+/// `per_producer` metrics each, until `flush` has picked them all up.
+fn time_producers<W, F>(
+    runtime: &tokio::runtime::Runtime,
+    iters: u64,
+    producers: u64,
+    per_producer: u64,
+    write: W,
+    flush: F,
+) -> std::time::Duration
+where
+    W: Fn(Metric) + Sync,
+    F: Fn(),
+{
+    let _guard = runtime.enter();
+    let barrier = std::sync::Barrier::new(producers as usize + 1);
+    std::thread::scope(|s| {
+        for _ in 0..producers {
+            s.spawn(|| {
+                for _ in 0..iters {
+                    barrier.wait();
+                    for _ in 0..per_producer {
+                        write(
+                            Metric::new(
+                                std::time::SystemTime::UNIX_EPOCH,
+                                "bench",
+                            )
+                            .with_field("value", 1),
+                        );
+                    }
+                    barrier.wait();
+                }
+            });
+        }
+
+        let mut elapsed = std::time::Duration::ZERO;
+        for _ in 0..iters {
+            barrier.wait();
+            let start = std::time::Instant::now();
+            barrier.wait();
+            flush();
+            elapsed += start.elapsed();
+        }
+        elapsed
+    })
+}
+
+/// Throughput of 1, 8 and 64 threads concurrently writing `PER_PRODUCER`
+/// metrics each, until they have all been picked up by the batching loop.
+/// `sharded` is the writer, `mpsc` the single channel it replaced.
+fn producers(c: &mut Criterion) {
+    const PER_PRODUCER: u64 = 1000;
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("producers");
+
+    for producers in [1, 8, 64] {
+        // large enough that no metric of an iteration is dropped
+        let capacity = (producers * PER_PRODUCER) as usize;
+        let writer = runtime.block_on(async {
+            InfluxiveWriter::with_token_auth(
+                InfluxiveWriterConfig::default()
+                    .with_batch_buffer_size(capacity)
+                    .with_backend(Arc::new(NullBackendFactory)),
+                "",
+                "",
+                "",
+            )
+        });
+        let mpsc = MpscWriter::new(&runtime, capacity);
+
+        group.throughput(criterion::Throughput::Elements(
+            producers * PER_PRODUCER,
+        ));
+        group.bench_with_input(
+            BenchmarkId::new("sharded", producers),
+            &producers,
+            |b, &producers| {
+                b.iter_custom(|iters| {
+                    time_producers(
+                        &runtime,
+                        iters,
+                        producers,
+                        PER_PRODUCER,
+                        |metric| writer.write_metric(metric),
+                        || runtime.block_on(writer.flush()).unwrap(),
+                    )
+                });
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("mpsc", producers),
+            &producers,
+            |b, &producers| {
+                b.iter_custom(|iters| {
+                    time_producers(
+                        &runtime,
+                        iters,
+                        producers,
+                        PER_PRODUCER,
+                        |metric| mpsc.write_metric(metric),
+                        || runtime.block_on(mpsc.flush()),
+                    )
+                });
+            },
+        );
+
+        assert_eq!(0, writer.stats().metrics_dropped);
+        assert_eq!(0, mpsc.dropped.load(std::sync::atomic::Ordering::Relaxed));
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, write_and_flush, idle_flush, producers);
+criterion_main!(benches);
@@ -0,0 +1,484 @@
+use crate::*;
+use std::time::SystemTime;
+
+/// Write `s` as a Flux string literal, escaping quotes, backslashes,
+/// control characters and `${` interpolation.
+fn flux_str(out: &mut String, s: &str) {
+    out.push('"');
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '$' if chars.peek() == Some(&'{') => out.push_str("\\$"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn flux_str_list(out: &mut String, list: &[&str]) {
+    out.push('[');
+    for (i, s) in list.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        flux_str(out, s);
+    }
+    out.push(']');
+}
+
+/// Write nanoseconds as a Flux duration literal, e.g. `-1h30m`.
+fn flux_duration(out: &mut String, nanos: i64) {
+    use std::fmt::Write;
+
+    if nanos == 0 {
+        out.push_str("0s");
+        return;
+    }
+    if nanos < 0 {
+        out.push('-');
+    }
+    let mut rest = nanos.unsigned_abs();
+    for (unit, per) in [
+        ("h", 3_600_000_000_000),
+        ("m", 60_000_000_000),
+        ("s", 1_000_000_000),
+        ("ms", 1_000_000),
+        ("us", 1_000),
+        ("ns", 1),
+    ] {
+        if rest >= per {
+            let _ = write!(out, "{}{unit}", rest / per);
+            rest %= per;
+        }
+    }
+}
+
+fn duration_nanos(d: std::time::Duration) -> i64 {
+    d.as_nanos().try_into().unwrap_or(i64::MAX)
+}
+
+/// A literal value compared against in a [FluxFilter].
+#[derive(Debug, Clone, PartialEq)]
+pub enum FluxValue {
+    /// A string.
+    String(String),
+
+    /// A float.
+    Float(f64),
+
+    /// A signed integer.
+    Int(i64),
+
+    /// An unsigned integer.
+    UInt(u64),
+
+    /// A boolean.
+    Bool(bool),
+
+    /// A point in time.
+    Time(SystemTime),
+
+    /// A duration in nanoseconds, which may be negative.
+    Duration(i64),
+}
+
+impl FluxValue {
+    fn render(&self, out: &mut String) {
+        match self {
+            FluxValue::String(s) => flux_str(out, s),
+            FluxValue::Float(f) if f.is_nan() => {
+                out.push_str("float(v: \"NaN\")")
+            }
+            FluxValue::Float(f) if f.is_infinite() => {
+                out.push_str(if *f > 0.0 {
+                    "float(v: \"+Inf\")"
+                } else {
+                    "float(v: \"-Inf\")"
+                })
+            }
+            FluxValue::Float(f) => {
+                // Display never uses an exponent, which Flux can't parse
+                let f = f.to_string();
+                out.push_str(&f);
+                if !f.contains('.') {
+                    out.push_str(".0");
+                }
+            }
+            FluxValue::Int(i) => out.push_str(&i.to_string()),
+            FluxValue::UInt(u) => out.push_str(&format!("uint(v: {u})")),
+            FluxValue::Bool(b) => out.push_str(&b.to_string()),
+            FluxValue::Time(t) => {
+                out.push_str(&format_rfc3339(time_to_nanos(*t)))
+            }
+            FluxValue::Duration(d) => flux_duration(out, *d),
+        }
+    }
+}
+
+macro_rules! fluxvalue_from_impl {
+    ($($f:ty, $i:ident, $b:block,)*) => {$(
+        impl From<$f> for FluxValue {
+            fn from($i: $f) -> Self $b
+        }
+    )*};
+}
+
+fluxvalue_from_impl! {
+    &str, f, { FluxValue::String(f.to_string()) },
+    String, f, { FluxValue::String(f) },
+    &String, f, { FluxValue::String(f.clone()) },
+    f64, f, { FluxValue::Float(f) },
+    f32, f, { FluxValue::Float(f as f64) },
+    i64, f, { FluxValue::Int(f) },
+    i32, f, { FluxValue::Int(f as i64) },
+    u64, f, { FluxValue::UInt(f) },
+    u32, f, { FluxValue::UInt(f as u64) },
+    bool, f, { FluxValue::Bool(f) },
+    SystemTime, f, { FluxValue::Time(f) },
+    std::time::Duration, f, { FluxValue::Duration(duration_nanos(f)) },
+}
+
+impl From<DataType> for FluxValue {
+    fn from(d: DataType) -> Self {
+        match d {
+            DataType::Bool(b) => FluxValue::Bool(b),
+            DataType::F64(f) => FluxValue::Float(f),
+            DataType::I64(i) => FluxValue::Int(i),
+            DataType::U64(u) => FluxValue::UInt(u),
+            DataType::String(s) => FluxValue::String(s.into_string()),
+        }
+    }
+}
+
+/// A bound of a [FluxQuery::range].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FluxTime {
+    /// `now()`.
+    Now,
+
+    /// A point in time.
+    At(SystemTime),
+
+    /// A duration before now, rendered as a negative duration.
+    Ago(std::time::Duration),
+}
+
+impl FluxTime {
+    fn render(&self, out: &mut String) {
+        match self {
+            FluxTime::Now => out.push_str("now()"),
+            FluxTime::At(t) => FluxValue::Time(*t).render(out),
+            FluxTime::Ago(d) => flux_duration(out, -duration_nanos(*d)),
+        }
+    }
+}
+
+impl From<SystemTime> for FluxTime {
+    fn from(t: SystemTime) -> Self {
+        FluxTime::At(t)
+    }
+}
+
+/// A predicate function body of a [FluxQuery::filter], comparing columns
+/// of the row `r` against literal values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FluxFilter(String);
+
+impl FluxFilter {
+    fn compare(column: &str, op: &str, value: FluxValue) -> Self {
+        let mut out = String::from("r[");
+        flux_str(&mut out, column);
+        out.push_str("] ");
+        out.push_str(op);
+        out.push(' ');
+        value.render(&mut out);
+        Self(out)
+    }
+
+    /// `r[column] == value`.
+    pub fn eq<V: Into<FluxValue>>(column: &str, value: V) -> Self {
+        Self::compare(column, "==", value.into())
+    }
+
+    /// `r[column] != value`.
+    pub fn ne<V: Into<FluxValue>>(column: &str, value: V) -> Self {
+        Self::compare(column, "!=", value.into())
+    }
+
+    /// `r[column] < value`.
+    pub fn lt<V: Into<FluxValue>>(column: &str, value: V) -> Self {
+        Self::compare(column, "<", value.into())
+    }
+
+    /// `r[column] <= value`.
+    pub fn le<V: Into<FluxValue>>(column: &str, value: V) -> Self {
+        Self::compare(column, "<=", value.into())
+    }
+
+    /// `r[column] > value`.
+    pub fn gt<V: Into<FluxValue>>(column: &str, value: V) -> Self {
+        Self::compare(column, ">", value.into())
+    }
+
+    /// `r[column] >= value`.
+    pub fn ge<V: Into<FluxValue>>(column: &str, value: V) -> Self {
+        Self::compare(column, ">=", value.into())
+    }
+
+    /// `exists r[column]`, the column has a non-null value.
+    pub fn exists(column: &str) -> Self {
+        let mut out = String::from("exists r[");
+        flux_str(&mut out, column);
+        out.push(']');
+        Self(out)
+    }
+
+    /// `r["_measurement"] == measurement`.
+    pub fn measurement(measurement: &str) -> Self {
+        Self::eq("_measurement", measurement)
+    }
+
+    /// `r["_field"] == field`.
+    pub fn field(field: &str) -> Self {
+        Self::eq("_field", field)
+    }
+
+    /// Both this and the other predicate hold.
+    pub fn and(self, other: FluxFilter) -> Self {
+        Self(format!("({}) and ({})", self.0, other.0))
+    }
+
+    /// This or the other predicate holds.
+    pub fn or(self, other: FluxFilter) -> Self {
+        Self(format!("({}) or ({})", self.0, other.0))
+    }
+}
+
+impl std::ops::Not for FluxFilter {
+    type Output = Self;
+
+    /// `not (predicate)`, the predicate does not hold.
+    fn not(self) -> Self {
+        Self(format!("not ({})", self.0))
+    }
+}
+
+impl std::fmt::Display for FluxFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The aggregate function of a [FluxQuery::aggregate_window].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FluxAggregate {
+    /// `mean`.
+    Mean,
+
+    /// `median`.
+    Median,
+
+    /// `sum`.
+    Sum,
+
+    /// `count`.
+    Count,
+
+    /// `min`.
+    Min,
+
+    /// `max`.
+    Max,
+
+    /// `first`.
+    First,
+
+    /// `last`.
+    Last,
+
+    /// `spread`.
+    Spread,
+
+    /// `stddev`.
+    Stddev,
+}
+
+impl FluxAggregate {
+    fn as_str(&self) -> &'static str {
+        match self {
+            FluxAggregate::Mean => "mean",
+            FluxAggregate::Median => "median",
+            FluxAggregate::Sum => "sum",
+            FluxAggregate::Count => "count",
+            FluxAggregate::Min => "min",
+            FluxAggregate::Max => "max",
+            FluxAggregate::First => "first",
+            FluxAggregate::Last => "last",
+            FluxAggregate::Spread => "spread",
+            FluxAggregate::Stddev => "stddev",
+        }
+    }
+}
+
+/// A Flux query built from typed pipeline stages, starting with
+/// `from(bucket:)`. Strings are always escaped, so values from
+/// untrusted input can't change the query.
+///
+/// Renders to Flux with `to_string()`, and can be passed directly to
+/// [InfluxiveQueryClient::query] or the query functions of
+/// `InfluxiveChildSvc`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FluxQuery {
+    flux: String,
+}
+
+impl FluxQuery {
+    /// `from(bucket: bucket)`.
+    pub fn from_bucket(bucket: &str) -> Self {
+        let mut flux = String::from("from(bucket: ");
+        flux_str(&mut flux, bucket);
+        flux.push(')');
+        Self { flux }
+    }
+
+    fn pipe(mut self, f: impl FnOnce(&mut String)) -> Self {
+        self.flux.push_str("\n|> ");
+        f(&mut self.flux);
+        self
+    }
+
+    /// `range(start: start)`, up to now.
+    pub fn range<S: Into<FluxTime>>(self, start: S) -> Self {
+        self.pipe(|out| {
+            out.push_str("range(start: ");
+            start.into().render(out);
+            out.push(')');
+        })
+    }
+
+    /// `range(start: start, stop: stop)`, the stop is exclusive.
+    pub fn range_between<S: Into<FluxTime>, T: Into<FluxTime>>(
+        self,
+        start: S,
+        stop: T,
+    ) -> Self {
+        self.pipe(|out| {
+            out.push_str("range(start: ");
+            start.into().render(out);
+            out.push_str(", stop: ");
+            stop.into().render(out);
+            out.push(')');
+        })
+    }
+
+    /// `filter(fn: (r) => predicate)`.
+    pub fn filter(self, predicate: FluxFilter) -> Self {
+        self.pipe(|out| {
+            out.push_str("filter(fn: (r) => ");
+            out.push_str(&predicate.0);
+            out.push(')');
+        })
+    }
+
+    /// `aggregateWindow(every: every, fn: aggregate,
+    /// createEmpty: create_empty)`. With `create_empty`, windows without
+    /// data produce a null value.
+    pub fn aggregate_window(
+        self,
+        every: std::time::Duration,
+        aggregate: FluxAggregate,
+        create_empty: bool,
+    ) -> Self {
+        self.pipe(|out| {
+            out.push_str("aggregateWindow(every: ");
+            flux_duration(out, duration_nanos(every));
+            out.push_str(", fn: ");
+            out.push_str(aggregate.as_str());
+            out.push_str(", createEmpty: ");
+            out.push_str(if create_empty { "true" } else { "false" });
+            out.push(')');
+        })
+    }
+
+    /// `group(columns: columns)`, regrouping the tables by these columns.
+    pub fn group(self, columns: &[&str]) -> Self {
+        self.pipe(|out| {
+            out.push_str("group(columns: ");
+            flux_str_list(out, columns);
+            out.push(')');
+        })
+    }
+
+    /// `group(columns: columns, mode: "except")`, regrouping the tables
+    /// by all but these columns.
+    pub fn group_except(self, columns: &[&str]) -> Self {
+        self.pipe(|out| {
+            out.push_str("group(columns: ");
+            flux_str_list(out, columns);
+            out.push_str(", mode: \"except\")");
+        })
+    }
+
+    /// `group()`, merging all tables into one.
+    pub fn ungroup(self) -> Self {
+        self.pipe(|out| out.push_str("group()"))
+    }
+
+    /// `pivot(rowKey: row_key, columnKey: column_key,
+    /// valueColumn: value_column)`, e.g. `&["_time"], &["_field"], "_value"`
+    /// to turn fields into columns.
+    pub fn pivot(
+        self,
+        row_key: &[&str],
+        column_key: &[&str],
+        value_column: &str,
+    ) -> Self {
+        self.pipe(|out| {
+            out.push_str("pivot(rowKey: ");
+            flux_str_list(out, row_key);
+            out.push_str(", columnKey: ");
+            flux_str_list(out, column_key);
+            out.push_str(", valueColumn: ");
+            flux_str(out, value_column);
+            out.push(')');
+        })
+    }
+
+    /// `limit(n: n)`, the first `n` records of each table.
+    pub fn limit(self, n: u64) -> Self {
+        self.pipe(|out| out.push_str(&format!("limit(n: {n})")))
+    }
+
+    /// `yield(name: name)`, naming the result of this query.
+    pub fn yield_as(self, name: &str) -> Self {
+        self.pipe(|out| {
+            out.push_str("yield(name: ");
+            flux_str(out, name);
+            out.push(')');
+        })
+    }
+}
+
+impl std::fmt::Display for FluxQuery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.flux)
+    }
+}
+
+impl From<FluxQuery> for StringType {
+    fn from(q: FluxQuery) -> Self {
+        q.flux.into()
+    }
+}
+
+impl From<&FluxQuery> for StringType {
+    fn from(q: &FluxQuery) -> Self {
+        q.flux.clone().into()
+    }
+}
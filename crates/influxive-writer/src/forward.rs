@@ -0,0 +1,466 @@
+use super::*;
+use std::path::PathBuf;
+
+/// Configuration of a [LineProtocolForwarder].
+#[derive(Clone)]
+#[non_exhaustive]
+pub struct LineProtocolForwarderConfig {
+    /// The Line Protocol file to follow, e.g. one written by
+    /// [types::LineProtocolFileBackendFactory].
+    pub path: PathBuf,
+
+    /// Sidecar file the forwarded byte offset is saved to.
+    /// Defaults to `None`, which uses the followed path with
+    /// `.checkpoint` appended.
+    pub checkpoint_path: Option<PathBuf>,
+
+    /// Timestamp precision of the file.
+    /// Defaults to [types::Precision::Nanoseconds].
+    pub precision: types::Precision,
+
+    /// How often to check the file for new lines and rotation once
+    /// all lines have been forwarded.
+    /// Defaults to `1s`.
+    pub poll_interval: std::time::Duration,
+
+    /// Maximum metrics sent in one batch.
+    /// Defaults to `5000`.
+    pub batch_size: usize,
+
+    /// Delay before retrying a failed send. Retryable failures are
+    /// retried until they succeed, other failures stop the forwarder,
+    /// see [LineProtocolForwarder::is_stopped].
+    /// Defaults to `1s`.
+    pub retry_backoff: std::time::Duration,
+
+    /// The backend metrics are forwarded to.
+    /// Defaults to [types::InfluxHttpBackendFactory].
+    pub backend: Arc<dyn types::BackendFactory + 'static + Send + Sync>,
+}
+
+impl std::fmt::Debug for LineProtocolForwarderConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LineProtocolForwarderConfig")
+            .field("path", &self.path)
+            .field("checkpoint_path", &self.checkpoint_path)
+            .field("precision", &self.precision)
+            .field("poll_interval", &self.poll_interval)
+            .field("batch_size", &self.batch_size)
+            .field("retry_backoff", &self.retry_backoff)
+            .field("backend", &self.backend)
+            .finish()
+    }
+}
+
+impl LineProtocolForwarderConfig {
+    /// Follow the Line Protocol file at `path`.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            checkpoint_path: None,
+            precision: types::Precision::Nanoseconds,
+            poll_interval: std::time::Duration::from_secs(1),
+            batch_size: 5000,
+            retry_backoff: std::time::Duration::from_secs(1),
+            backend: Arc::new(types::InfluxHttpBackendFactory::new()),
+        }
+    }
+
+    /// Apply [LineProtocolForwarderConfig::checkpoint_path].
+    pub fn with_checkpoint_path(
+        mut self,
+        checkpoint_path: Option<PathBuf>,
+    ) -> Self {
+        self.checkpoint_path = checkpoint_path;
+        self
+    }
+
+    /// Apply [LineProtocolForwarderConfig::precision].
+    pub fn with_precision(mut self, precision: types::Precision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Apply [LineProtocolForwarderConfig::poll_interval].
+    pub fn with_poll_interval(
+        mut self,
+        poll_interval: std::time::Duration,
+    ) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Apply [LineProtocolForwarderConfig::batch_size].
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Apply [LineProtocolForwarderConfig::retry_backoff].
+    pub fn with_retry_backoff(
+        mut self,
+        retry_backoff: std::time::Duration,
+    ) -> Self {
+        self.retry_backoff = retry_backoff;
+        self
+    }
+
+    /// Apply [LineProtocolForwarderConfig::backend].
+    pub fn with_backend(
+        mut self,
+        backend: Arc<dyn types::BackendFactory + 'static + Send + Sync>,
+    ) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    fn resolved_checkpoint_path(&self) -> PathBuf {
+        match &self.checkpoint_path {
+            Some(path) => path.clone(),
+            None => {
+                let mut path = self.path.as_os_str().to_owned();
+                path.push(".checkpoint");
+                path.into()
+            }
+        }
+    }
+}
+
+/// Statistics of a [LineProtocolForwarder].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LineProtocolForwarderStats {
+    /// The byte offset in the current file forwarded and checkpointed.
+    pub offset: u64,
+
+    /// Metrics the backend reported as sent.
+    pub metrics_sent: u64,
+
+    /// Invalid lines skipped.
+    pub invalid_lines: u64,
+
+    /// Times the followed file was rotated or truncated.
+    pub rotations: u64,
+}
+
+type SharedForwarderStats = Arc<std::sync::Mutex<LineProtocolForwarderStats>>;
+
+/// Follows an actively written Line Protocol file, like `tail -F`, and
+/// forwards new lines to a backend, by default InfluxDB over HTTP.
+///
+/// Together with [types::LineProtocolFileBackendFactory] in another
+/// process this makes a crash-safe pipeline: the byte offset forwarded
+/// up to is saved in a sidecar checkpoint file after every delivered
+/// batch, so a restarted forwarder neither duplicates nor skips lines.
+///
+/// A line is only forwarded once its trailing newline was written.
+/// If the file is replaced (e.g. renamed away by logrotate) the rest of
+/// the old file is forwarded before following the new file from its
+/// start. If the file is truncated, it is followed from its start.
+/// Rotations are detected through the file's inode on unix, and only
+/// through truncation elsewhere. A file rotated while the forwarder is
+/// not running is not forwarded.
+///
+/// A send failing with an error retrying won't fix, e.g. an expired
+/// token, stops the forwarder without checkpointing the batch, so no
+/// lines are lost. Once fixed, a new forwarder resumes from the batch.
+pub struct LineProtocolForwarder {
+    stats: SharedForwarderStats,
+    shutdown: Arc<tokio::sync::Notify>,
+    task: tokio::task::JoinHandle<types::BackendResult<()>>,
+}
+
+impl Drop for LineProtocolForwarder {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl LineProtocolForwarder {
+    /// Start following the file, forwarding to a backend created with
+    /// the given host, bucket and token.
+    /// Must be called within a Tokio runtime.
+    pub fn with_token_auth<H: AsRef<str>, B: AsRef<str>, T: AsRef<str>>(
+        config: LineProtocolForwarderConfig,
+        host: H,
+        bucket: B,
+        token: T,
+    ) -> Self {
+        let backend = config.backend.with_token_auth(
+            host.as_ref().to_string(),
+            bucket.as_ref().to_string(),
+            token.as_ref().to_string(),
+        );
+
+        let stats = SharedForwarderStats::default();
+        let shutdown = Arc::new(tokio::sync::Notify::new());
+
+        let task = tokio::task::spawn(
+            Forward {
+                checkpoint_path: config.resolved_checkpoint_path(),
+                config,
+                backend,
+                saved: Checkpoint::default(),
+                stats: stats.clone(),
+                shutdown: shutdown.clone(),
+            }
+            .run(),
+        );
+
+        Self {
+            stats,
+            shutdown,
+            task,
+        }
+    }
+
+    /// Get a snapshot of the forwarder statistics.
+    pub fn stats(&self) -> LineProtocolForwarderStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// The forwarder stopped on its own, because a send failed with an
+    /// error retrying won't fix. [LineProtocolForwarder::shutdown]
+    /// returns the error.
+    pub fn is_stopped(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    /// Stop following the file. Lines read but not yet delivered are
+    /// not checkpointed, and are forwarded again after a restart.
+    /// Returns the error the forwarder stopped on, if any.
+    pub async fn shutdown(mut self) -> types::BackendResult<()> {
+        self.shutdown.notify_one();
+        match (&mut self.task).await {
+            Ok(result) => result,
+            Err(err) => Err(types::BackendError::Other(err.into())),
+        }
+    }
+}
+
+/// Identifies a file, to detect it was replaced.
+fn file_id(meta: &std::fs::Metadata) -> Option<u64> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        Some(meta.ino())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = meta;
+        None
+    }
+}
+
+type FileReader = LineProtocolReader<tokio::io::BufReader<tokio::fs::File>>;
+
+struct Forward {
+    config: LineProtocolForwarderConfig,
+    checkpoint_path: PathBuf,
+    backend: Box<dyn types::Backend + 'static + Send + Sync>,
+    saved: Checkpoint,
+    stats: SharedForwarderStats,
+    shutdown: Arc<tokio::sync::Notify>,
+}
+
+impl Forward {
+    /// Sleep for `duration`, returns false if shut down meanwhile.
+    async fn sleep(&self, duration: std::time::Duration) -> bool {
+        tokio::select! {
+            _ = self.shutdown.notified() => false,
+            _ = tokio::time::sleep(duration) => true,
+        }
+    }
+
+    async fn run(mut self) -> types::BackendResult<()> {
+        let mut checkpoint = match Checkpoint::read(&self.checkpoint_path) {
+            Ok(checkpoint) => checkpoint,
+            Err(err) => {
+                tracing::warn!(
+                    ?err,
+                    path = ?self.checkpoint_path,
+                    "invalid forwarder checkpoint, forwarding from start"
+                );
+                Checkpoint::default()
+            }
+        };
+        self.saved = checkpoint;
+        self.stats.lock().unwrap().offset = checkpoint.offset;
+
+        loop {
+            let reader = match self.open(&mut checkpoint).await {
+                Ok(reader) => {
+                    self.save(&checkpoint);
+                    reader
+                }
+                Err(err) => {
+                    tracing::debug!(?err, path = ?self.config.path, "open");
+                    if !self.sleep(self.config.poll_interval).await {
+                        return Ok(());
+                    }
+                    continue;
+                }
+            };
+
+            match self.follow(reader, &mut checkpoint).await? {
+                Some(next) => checkpoint = next,
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Open the followed file at the checkpoint, unless the checkpoint
+    /// belongs to a different file or is past the end of the file.
+    async fn open(
+        &self,
+        checkpoint: &mut Checkpoint,
+    ) -> std::io::Result<FileReader> {
+        use tokio::io::AsyncSeekExt;
+
+        let mut file = tokio::fs::File::open(&self.config.path).await?;
+        let meta = file.metadata().await?;
+        let id = file_id(&meta);
+
+        if checkpoint.file_id.is_some() && checkpoint.file_id != id
+            || checkpoint.offset > meta.len()
+        {
+            tracing::info!(path = ?self.config.path, "following new file");
+            checkpoint.offset = 0;
+        }
+        checkpoint.file_id = id;
+        file.seek(std::io::SeekFrom::Start(checkpoint.offset))
+            .await?;
+
+        Ok(LineProtocolReader::new(tokio::io::BufReader::new(file))
+            .with_precision(self.config.precision)
+            .with_offset(checkpoint.offset)
+            .with_follow(true))
+    }
+
+    /// Returns true if the followed path no longer is the open file.
+    fn rotated(&self, checkpoint: &Checkpoint, offset: u64) -> bool {
+        match std::fs::metadata(&self.config.path) {
+            Ok(meta) => {
+                (checkpoint.file_id.is_some()
+                    && file_id(&meta) != checkpoint.file_id)
+                    || meta.len() < offset
+            }
+            // renamed away and not yet recreated
+            Err(_) => true,
+        }
+    }
+
+    /// Forward lines until the file is rotated, returning the
+    /// checkpoint to open the new file with, or `None` on shutdown.
+    async fn follow(
+        &mut self,
+        mut reader: FileReader,
+        checkpoint: &mut Checkpoint,
+    ) -> types::BackendResult<Option<Checkpoint>> {
+        let mut rotated = false;
+
+        loop {
+            match reader.next_metric().await {
+                Ok(Some(metric)) => {
+                    self.backend.buffer_metric(metric);
+                    if self.backend.buffer_count() >= self.config.batch_size
+                        && !self.send(reader.offset(), checkpoint).await?
+                    {
+                        return Ok(None);
+                    }
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::InvalidData => {
+                    tracing::warn!(?err, path = ?self.config.path, "skip invalid line");
+                    self.stats.lock().unwrap().invalid_lines += 1;
+                }
+                Err(err) => {
+                    tracing::warn!(?err, path = ?self.config.path, "read error");
+                    // reopen at the checkpoint, which re-reads the
+                    // buffered metrics
+                    self.backend.clear();
+                    if !self.sleep(self.config.poll_interval).await {
+                        return Ok(None);
+                    }
+                    return Ok(Some(*checkpoint));
+                }
+                Ok(None) => {
+                    // caught up with the writer
+                    if !self.send(reader.offset(), checkpoint).await? {
+                        return Ok(None);
+                    }
+
+                    if rotated {
+                        // the old file was drained after its rotation
+                        self.stats.lock().unwrap().rotations += 1;
+                        return Ok(Some(Checkpoint::default()));
+                    }
+
+                    if self.rotated(checkpoint, reader.offset()) {
+                        // read what was written before the rotation
+                        rotated = true;
+                        continue;
+                    }
+
+                    if !self.sleep(self.config.poll_interval).await {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Send the buffered metrics, retrying until delivered, then save
+    /// `offset` as the checkpoint. Returns false on shutdown, and errors
+    /// retrying won't fix without saving the checkpoint.
+    async fn send(
+        &mut self,
+        offset: u64,
+        checkpoint: &mut Checkpoint,
+    ) -> types::BackendResult<bool> {
+        let count = self.backend.buffer_count() as u64;
+
+        while self.backend.buffer_count() > 0 {
+            match self.backend.send().await {
+                Ok(()) => {
+                    if let Err(err) = self.backend.flush().await {
+                        tracing::warn!(?err, "forwarder flush error");
+                    }
+                    self.stats.lock().unwrap().metrics_sent += count;
+                    break;
+                }
+                Err(err) if err.is_retryable() => {
+                    tracing::warn!(?err, "forwarder send error, retrying");
+                    if !self.sleep(self.config.retry_backoff).await {
+                        return Ok(false);
+                    }
+                }
+                Err(err) => {
+                    tracing::error!(
+                        ?err,
+                        count,
+                        "forwarder send error, stopping"
+                    );
+                    return Err(err);
+                }
+            }
+        }
+
+        checkpoint.offset = offset;
+        self.save(checkpoint);
+
+        Ok(true)
+    }
+
+    /// Save the checkpoint, if it changed.
+    fn save(&mut self, checkpoint: &Checkpoint) {
+        if *checkpoint == self.saved {
+            return;
+        }
+        if let Err(err) = checkpoint.write(&self.checkpoint_path) {
+            tracing::error!(?err, "forwarder checkpoint error");
+            return;
+        }
+        self.saved = *checkpoint;
+        self.stats.lock().unwrap().offset = checkpoint.offset;
+    }
+}
@@ -0,0 +1,181 @@
+use super::*;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+static NEXT_PRODUCER: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Each thread writes to its own shard (modulo the shard count).
+    static PRODUCER: usize = NEXT_PRODUCER.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Metrics queued by one shard, with their sequence numbers, and the
+/// share of the queue capacity the shard has reserved for them.
+#[derive(Default)]
+struct Shard {
+    metrics: Vec<(u64, Metric)>,
+    reserved: usize,
+}
+
+/// Sharded queue of metrics written, but not yet picked up by the
+/// batching loop. Producers on different threads lock different shards.
+/// A shard reserves queue capacity in chunks, and only wakes the
+/// batching loop when it was empty, so producers rarely touch state
+/// shared with other shards.
+///
+/// The one exception is the sequence number each metric is stamped with
+/// when pushed. [InfluxiveWriter::write_metric] promises that a metric
+/// whose write returned before another write started is sent first, no
+/// matter which threads wrote them, e.g. when a task moves between
+/// worker threads. Per shard order can't keep that promise, so draining
+/// merges the shards by sequence number.
+pub(crate) struct Ingest {
+    shards: Box<[Mutex<Shard>]>,
+    seq: AtomicU64,
+    queued: AtomicUsize,
+    capacity: AtomicUsize,
+    notify: Arc<tokio::sync::Notify>,
+}
+
+impl Ingest {
+    /// Construct an ingest queue holding at most `capacity` metrics.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_notify(capacity, Arc::new(tokio::sync::Notify::new()))
+    }
+
+    /// Construct an ingest queue waking the batching loop through
+    /// `notify`, which may be shared by many queues.
+    pub fn with_notify(
+        capacity: usize,
+        notify: Arc<tokio::sync::Notify>,
+    ) -> Self {
+        let shards = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .next_power_of_two()
+            .min(64);
+        Self::with_shards(capacity, notify, shards)
+    }
+
+    /// Construct an ingest queue with a fixed number of shards.
+    pub fn with_shards(
+        capacity: usize,
+        notify: Arc<tokio::sync::Notify>,
+        shards: usize,
+    ) -> Self {
+        Self {
+            shards: (0..shards).map(|_| Mutex::default()).collect(),
+            seq: AtomicU64::new(0),
+            queued: AtomicUsize::new(0),
+            capacity: AtomicUsize::new(capacity),
+            notify,
+        }
+    }
+
+    /// Change the number of metrics the queue holds at most.
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+    }
+
+    /// Reserve capacity for up to a chunk of metrics, returning how many.
+    /// Reserved capacity a shard didn't use is released when drained.
+    /// Chunks are sized so that until then, the shards together hold
+    /// back at most an eighth of the capacity.
+    fn reserve(&self) -> usize {
+        let capacity = self.capacity.load(Ordering::Relaxed);
+        let chunk = (capacity / (self.shards.len() * 8)).clamp(1, 64);
+        match self.queued.fetch_update(
+            Ordering::AcqRel,
+            Ordering::Acquire,
+            |queued| {
+                (queued < capacity)
+                    .then(|| queued + chunk.min(capacity - queued))
+            },
+        ) {
+            Ok(queued) => chunk.min(capacity - queued),
+            Err(_) => 0,
+        }
+    }
+
+    /// Queue a metric without blocking on the batching loop.
+    /// Returns `false` if the queue is full and the metric was dropped.
+    pub fn push(&self, metric: Metric) -> bool {
+        let shard = PRODUCER.with(|p| *p) % self.shards.len();
+        let mut shard = self.shards[shard].lock().unwrap();
+
+        if shard.metrics.len() == shard.reserved {
+            match self.reserve() {
+                0 => return false,
+                n => shard.reserved += n,
+            }
+        }
+
+        // taken under the shard lock, so each shard stays sorted
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        let was_empty = shard.metrics.is_empty();
+        shard.metrics.push((seq, metric));
+        drop(shard);
+
+        // a non-empty shard already woke the batching loop, which will
+        // find this metric when it drains the shard
+        if was_empty {
+            self.notify.notify_one();
+        }
+
+        true
+    }
+
+    /// Resolves once metrics have been queued since the last drain.
+    pub async fn notified(&self) {
+        self.notify.notified().await
+    }
+
+    /// Take all queued metrics, in the order they were pushed.
+    pub fn drain(&self) -> Vec<Metric> {
+        // only take metrics pushed before this cut: a later push may
+        // land in a shard already drained, and the ones it has to
+        // follow must then wait for the next drain as well
+        let cut = self.seq.load(Ordering::Acquire);
+
+        let mut out = Vec::new();
+        let mut runs = 0;
+        let mut released = 0;
+        let mut rest = false;
+        for shard in self.shards.iter() {
+            let mut shard = shard.lock().unwrap();
+            if shard.metrics.is_empty() {
+                continue;
+            }
+            let at = shard.metrics.partition_point(|(seq, _)| *seq < cut);
+            let left = shard.metrics.split_off(at);
+            let taken = std::mem::replace(&mut shard.metrics, left);
+            rest |= !shard.metrics.is_empty();
+            released += shard.reserved - shard.metrics.len();
+            shard.reserved = shard.metrics.len();
+            drop(shard);
+
+            if taken.is_empty() {
+                continue;
+            }
+            runs += 1;
+            if out.is_empty() {
+                out = taken;
+            } else {
+                out.extend(taken);
+            }
+        }
+        self.queued.fetch_sub(released, Ordering::AcqRel);
+
+        // metrics left behind may not have woken the batching loop,
+        // their shard wasn't empty when they were pushed
+        if rest {
+            self.notify.notify_one();
+        }
+
+        // each shard is a sorted run, which the stable sort merges
+        if runs > 1 {
+            out.sort_by_key(|(seq, _)| *seq);
+        }
+        out.into_iter().map(|(_, metric)| metric).collect()
+    }
+}
@@ -0,0 +1,447 @@
+use super::*;
+use std::time::Duration;
+
+/// File format of a [BackendConfigModel::File] backend,
+/// see [types::FileFormat].
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Default,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileFormatModel {
+    /// See [types::FileFormat::LineProtocol].
+    #[default]
+    LineProtocol,
+
+    /// See [types::FileFormat::JsonLines].
+    JsonLines,
+
+    /// See [types::FileFormat::Csv].
+    Csv,
+
+    /// See [types::FileFormat::Parquet].
+    /// Requires the `parquet` feature.
+    Parquet,
+}
+
+/// Data-only description of the backend of a writer, tagged by `type`.
+/// Unset optional values keep the defaults of the backend factory.
+///
+/// For example, in TOML:
+///
+/// ```toml
+/// type = "influx"
+/// org = "my.org"
+/// precision = "ms"
+/// ```
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+#[non_exhaustive]
+pub enum BackendConfigModel {
+    /// InfluxDB over HTTP, see [types::InfluxHttpBackendFactory].
+    /// The host, bucket and token the writer is created with are used,
+    /// unless `host`, `bucket` and `token` are all set.
+    Influx {
+        /// InfluxDB url, e.g. `http://127.0.0.1:8086`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        host: Option<String>,
+
+        /// Bucket to write to.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bucket: Option<String>,
+
+        /// Authentication token.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<String>,
+
+        /// Write to the v2 API of this organization.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        org: Option<String>,
+
+        /// Timestamp precision, `ns`, `us`, `ms` or `s`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        precision: Option<types::Precision>,
+    },
+
+    /// A backend given by url, see [InfluxiveWriterConfig::from_url].
+    Url {
+        /// The backend url.
+        url: String,
+    },
+
+    /// Files on disk, see [types::LineProtocolFileBackendFactory].
+    File {
+        /// Path of the file, or directory, depending on the format.
+        path: std::path::PathBuf,
+
+        /// The file format.
+        #[serde(default)]
+        format: FileFormatModel,
+
+        /// Time window of each parquet file.
+        /// Defaults to `1h`.
+        #[serde(
+            default,
+            with = "humantime_serde",
+            skip_serializing_if = "Option::is_none"
+        )]
+        window: Option<Duration>,
+    },
+
+    /// Line Protocol over TCP,
+    /// see [types::LineProtocolSocketBackendFactory].
+    Tcp {
+        /// Address, e.g. `127.0.0.1:8094`.
+        addr: String,
+    },
+
+    /// Line Protocol over a unix domain socket,
+    /// see [types::LineProtocolSocketBackendFactory].
+    #[cfg(unix)]
+    Unix {
+        /// Path of the socket.
+        path: std::path::PathBuf,
+    },
+
+    /// Line Protocol over UDP, see [types::LineProtocolUdpBackendFactory].
+    Udp {
+        /// Address, e.g. `127.0.0.1:8089`.
+        addr: String,
+    },
+
+    /// Graphite plaintext, see [types::GraphiteBackendFactory].
+    Graphite {
+        /// Address, e.g. `127.0.0.1:2003`.
+        addr: String,
+
+        /// Prefix prepended to every path.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        prefix: Option<String>,
+
+        /// How tags are represented, `tagged`, `path` or `drop`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tag_mode: Option<types::GraphiteTagMode>,
+    },
+
+    /// StatsD over UDP, see [types::StatsdBackendFactory].
+    Statsd {
+        /// Address, e.g. `127.0.0.1:8125`.
+        addr: String,
+
+        /// Prefix prepended to every metric name.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        prefix: Option<String>,
+
+        /// StatsD dialect, `statsd` or `dog-statsd`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        flavor: Option<types::StatsdFlavor>,
+    },
+
+    /// OTLP protobuf over HTTP, see [types::OtlpHttpBackendFactory].
+    #[cfg(feature = "otlp")]
+    OtlpHttp {
+        /// The metrics endpoint url,
+        /// e.g. `http://127.0.0.1:4318/v1/metrics`.
+        endpoint: String,
+
+        /// HTTP headers added to every export request.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        headers: Vec<(String, String)>,
+    },
+
+    /// InfluxDB over HTTP, failing over to a secondary backend,
+    /// see [types::FailoverBackendFactory]. The primary is the host,
+    /// bucket and token the writer is created with.
+    Failover {
+        /// The backend written to while the primary is down.
+        secondary: Box<BackendConfigModel>,
+
+        /// Consecutive primary failures before failing over.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        failure_threshold: Option<usize>,
+
+        /// How often the primary is probed while down.
+        #[serde(
+            default,
+            with = "humantime_serde",
+            skip_serializing_if = "Option::is_none"
+        )]
+        probe_interval: Option<Duration>,
+
+        /// Timeout connecting to the primary.
+        #[serde(
+            default,
+            with = "humantime_serde",
+            skip_serializing_if = "Option::is_none"
+        )]
+        connect_timeout: Option<Duration>,
+
+        /// Timeout for each request to the primary.
+        #[serde(
+            default,
+            with = "humantime_serde",
+            skip_serializing_if = "Option::is_none"
+        )]
+        request_timeout: Option<Duration>,
+    },
+}
+
+impl Default for BackendConfigModel {
+    fn default() -> Self {
+        BackendConfigModel::Influx {
+            host: None,
+            bucket: None,
+            token: None,
+            org: None,
+            precision: None,
+        }
+    }
+}
+
+impl BackendConfigModel {
+    /// Construct the backend factory described by this model.
+    pub fn into_backend_factory(
+        self,
+    ) -> std::io::Result<Arc<dyn types::BackendFactory + 'static + Send + Sync>>
+    {
+        let invalid = |msg: &str| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                msg.to_string(),
+            )
+        };
+
+        Ok(match self {
+            BackendConfigModel::Influx {
+                host,
+                bucket,
+                token,
+                org,
+                precision,
+            } => {
+                let mut factory = types::InfluxHttpBackendFactory::new();
+                match (host, bucket, token) {
+                    (Some(host), Some(bucket), Some(token)) => {
+                        factory = factory.with_endpoint(host, bucket, token);
+                    }
+                    (None, None, None) => (),
+                    _ => {
+                        return Err(invalid(
+                            "influx backend requires all or none of host, bucket and token",
+                        ))
+                    }
+                }
+                if let Some(org) = org {
+                    factory = factory.with_org(org);
+                }
+                if let Some(precision) = precision {
+                    factory = factory.with_precision(precision);
+                }
+                Arc::new(factory)
+            }
+            BackendConfigModel::Url { url } => {
+                InfluxiveWriterConfig::from_url(&url)?.backend
+            }
+            BackendConfigModel::File {
+                path,
+                format,
+                window,
+            } => {
+                if window.is_some() && format != FileFormatModel::Parquet {
+                    return Err(invalid(
+                        "window only applies to the parquet format",
+                    ));
+                }
+                let format = match format {
+                    FileFormatModel::LineProtocol => {
+                        types::FileFormat::LineProtocol
+                    }
+                    FileFormatModel::JsonLines => types::FileFormat::JsonLines,
+                    FileFormatModel::Csv => types::FileFormat::Csv,
+                    #[cfg(feature = "parquet")]
+                    FileFormatModel::Parquet => types::FileFormat::Parquet {
+                        window: window.unwrap_or(Duration::from_secs(3600)),
+                    },
+                    #[cfg(not(feature = "parquet"))]
+                    FileFormatModel::Parquet => {
+                        return Err(invalid(
+                            "parquet file format requires the parquet feature",
+                        ))
+                    }
+                };
+                Arc::new(
+                    types::LineProtocolFileBackendFactory::new(path)
+                        .with_format(format),
+                )
+            }
+            BackendConfigModel::Tcp { addr } => {
+                Arc::new(types::LineProtocolSocketBackendFactory::new(
+                    types::LineProtocolSocketAddr::Tcp(addr),
+                ))
+            }
+            #[cfg(unix)]
+            BackendConfigModel::Unix { path } => {
+                Arc::new(types::LineProtocolSocketBackendFactory::new(
+                    types::LineProtocolSocketAddr::Unix(path),
+                ))
+            }
+            BackendConfigModel::Udp { addr } => {
+                Arc::new(types::LineProtocolUdpBackendFactory::new(addr))
+            }
+            BackendConfigModel::Graphite {
+                addr,
+                prefix,
+                tag_mode,
+            } => {
+                let mut factory = types::GraphiteBackendFactory::new(addr);
+                if let Some(prefix) = prefix {
+                    factory = factory.with_prefix(prefix);
+                }
+                if let Some(tag_mode) = tag_mode {
+                    factory = factory.with_tag_mode(tag_mode);
+                }
+                Arc::new(factory)
+            }
+            BackendConfigModel::Statsd {
+                addr,
+                prefix,
+                flavor,
+            } => {
+                let mut factory = types::StatsdBackendFactory::new(addr);
+                if let Some(prefix) = prefix {
+                    factory = factory.with_prefix(prefix);
+                }
+                if let Some(flavor) = flavor {
+                    factory = factory.with_flavor(flavor);
+                }
+                Arc::new(factory)
+            }
+            #[cfg(feature = "otlp")]
+            BackendConfigModel::OtlpHttp { endpoint, headers } => {
+                let mut factory = types::OtlpHttpBackendFactory::new(endpoint);
+                for (k, v) in headers {
+                    factory = factory.with_header(k, v);
+                }
+                Arc::new(factory)
+            }
+            BackendConfigModel::Failover {
+                secondary,
+                failure_threshold,
+                probe_interval,
+                connect_timeout,
+                request_timeout,
+            } => {
+                let mut factory =
+                    types::FailoverBackendFactory::new_with_secondary_backend(
+                        secondary.into_backend_factory()?,
+                    );
+                if let Some(failure_threshold) = failure_threshold {
+                    factory = factory.with_failure_threshold(failure_threshold);
+                }
+                if let Some(probe_interval) = probe_interval {
+                    factory = factory.with_probe_interval(probe_interval);
+                }
+                if let Some(connect_timeout) = connect_timeout {
+                    factory = factory.with_connect_timeout(connect_timeout);
+                }
+                if let Some(request_timeout) = request_timeout {
+                    factory = factory.with_request_timeout(request_timeout);
+                }
+                Arc::new(factory)
+            }
+        })
+    }
+}
+
+/// Data-only description of an [InfluxiveWriterConfig], e.g. loaded
+/// from a TOML or YAML file. Durations are written like `100ms` or `5s`,
+/// missing values take the [InfluxiveWriterConfig] defaults.
+///
+/// ```toml
+/// batch_duration = "250ms"
+/// batch_buffer_size = 8192
+///
+/// [backend]
+/// type = "file"
+/// path = "/var/metrics.influx"
+/// ```
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct InfluxiveWriterConfigModel {
+    /// See [InfluxiveWriterConfig::batch_duration].
+    #[serde(with = "humantime_serde")]
+    pub batch_duration: Duration,
+
+    /// See [InfluxiveWriterConfig::batch_buffer_size].
+    pub batch_buffer_size: usize,
+
+    /// See [InfluxiveWriterConfig::backend].
+    pub backend: BackendConfigModel,
+
+    /// See [InfluxiveWriterConfig::background_thread].
+    pub background_thread: bool,
+
+    /// See [InfluxiveWriterConfig::max_retries].
+    pub max_retries: usize,
+
+    /// See [InfluxiveWriterConfig::retry_backoff].
+    #[serde(with = "humantime_serde")]
+    pub retry_backoff: Duration,
+
+    /// See [InfluxiveWriterConfig::max_in_flight].
+    pub max_in_flight: usize,
+
+    /// See [InfluxiveWriterConfig::default_tags].
+    pub default_tags: std::collections::BTreeMap<String, String>,
+
+    /// See [InfluxiveWriterConfig::measurement_prefix].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub measurement_prefix: Option<String>,
+}
+
+impl Default for InfluxiveWriterConfigModel {
+    fn default() -> Self {
+        let config = InfluxiveWriterConfig::default();
+        Self {
+            batch_duration: config.batch_duration,
+            batch_buffer_size: config.batch_buffer_size,
+            backend: BackendConfigModel::default(),
+            background_thread: config.background_thread,
+            max_retries: config.max_retries,
+            retry_backoff: config.retry_backoff,
+            max_in_flight: config.max_in_flight,
+            default_tags: std::collections::BTreeMap::new(),
+            measurement_prefix: config.measurement_prefix,
+        }
+    }
+}
+
+impl TryFrom<InfluxiveWriterConfigModel> for InfluxiveWriterConfig {
+    type Error = std::io::Error;
+
+    fn try_from(model: InfluxiveWriterConfigModel) -> std::io::Result<Self> {
+        Ok(Self {
+            batch_duration: model.batch_duration,
+            batch_buffer_size: model.batch_buffer_size,
+            backend: model.backend.into_backend_factory()?,
+            background_thread: model.background_thread,
+            max_retries: model.max_retries,
+            retry_backoff: model.retry_backoff,
+            max_in_flight: model.max_in_flight,
+            default_tags: model
+                .default_tags
+                .into_iter()
+                .map(|(name, value)| (name.into(), value.into()))
+                .collect(),
+            measurement_prefix: model.measurement_prefix,
+            token_provider: None,
+        })
+    }
+}
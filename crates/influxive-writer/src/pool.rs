@@ -0,0 +1,517 @@
+use super::*;
+use std::collections::BTreeMap;
+
+/// A logical writer of an [InfluxiveWriterPool],
+/// see [InfluxiveWriterPool::writer].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct InfluxiveTenant {
+    /// The bucket metrics of this tenant are written to.
+    pub bucket: String,
+
+    /// Write to the v2 API of this organization, see
+    /// [types::InfluxHttpBackendFactory::with_org].
+    /// Defaults to `None`, writing to the v1 compatibility API.
+    pub org: Option<String>,
+
+    /// Tags added to every metric of this tenant, taking precedence over
+    /// the pool's [InfluxiveWriterConfig::default_tags].
+    /// Defaults to none.
+    pub tags: Vec<(StringType, DataType)>,
+}
+
+impl InfluxiveTenant {
+    /// Construct a tenant writing to this bucket.
+    pub fn new<B: Into<String>>(bucket: B) -> Self {
+        Self {
+            bucket: bucket.into(),
+            org: None,
+            tags: Vec::new(),
+        }
+    }
+
+    /// Apply [InfluxiveTenant::org].
+    pub fn with_org<O: Into<String>>(mut self, org: O) -> Self {
+        self.org = Some(org.into());
+        self
+    }
+
+    /// Add a tag to [InfluxiveTenant::tags].
+    pub fn with_tag<N, V>(mut self, name: N, value: V) -> Self
+    where
+        N: Into<StringType>,
+        V: Into<DataType>,
+    {
+        self.tags.push((name.into(), value.into()));
+        self
+    }
+}
+
+enum PoolCmd {
+    Add(usize, InfluxiveTenant, Arc<ingest::Ingest>, SharedStats),
+    Remove(usize),
+    Flush(
+        Option<usize>,
+        tokio::sync::oneshot::Sender<types::BackendResult<()>>,
+    ),
+}
+
+struct PoolTenant {
+    ingest: Arc<ingest::Ingest>,
+
+    /// `None` while a batch of this tenant is being sent.
+    write_buf: Option<WriteBuf>,
+
+    /// The tenant writer was dropped, close once its batch is sent.
+    removed: bool,
+
+    /// Flush requests waiting for this tenant to be flushed.
+    flushes: Vec<usize>,
+}
+
+/// A flush request, answered once every tenant it covers is flushed.
+struct PendingFlush {
+    remaining: usize,
+    result: types::BackendResult<()>,
+    respond: tokio::sync::oneshot::Sender<types::BackendResult<()>>,
+}
+
+/// The flush requests served by a finished flush, and its result.
+type Flushed = Option<(Vec<usize>, types::BackendResult<()>)>;
+
+type InFlight = tokio::task::JoinSet<(usize, WriteBuf, Flushed)>;
+
+/// Count a flush of one tenant towards the requests waiting for it,
+/// answering those that are complete.
+fn flushed(
+    flushes: &mut BTreeMap<usize, PendingFlush>,
+    ids: Vec<usize>,
+    result: types::BackendResult<()>,
+) {
+    for id in ids {
+        let Some(flush) = flushes.get_mut(&id) else {
+            continue;
+        };
+        flush.remaining -= 1;
+        if let (Ok(()), Err(err)) = (&flush.result, &result) {
+            flush.result = Err(duplicate(err));
+        }
+        if flush.remaining == 0 {
+            let flush = flushes.remove(&id).unwrap();
+            let _ = flush.respond.send(flush.result);
+        }
+    }
+}
+
+/// Errors are not `Clone`, but one flush may answer many requests.
+fn duplicate(err: &types::BackendError) -> types::BackendError {
+    use types::BackendError::*;
+    match err {
+        Io(err) => Io(std::io::Error::new(err.kind(), err.to_string())),
+        Http { status, message } => Http {
+            status: *status,
+            message: message.clone(),
+        },
+        Rejected(message) => Rejected(message.clone()),
+        Other(err) => Other(err.to_string().into()),
+    }
+}
+
+/// Send the remaining metrics of a removed tenant and close its backend
+/// in the background. Its pending flushes are answered right away, the
+/// close sends everything written before them.
+fn remove_tenant(
+    tenant: PoolTenant,
+    write_buf: WriteBuf,
+    flushes: &mut BTreeMap<usize, PendingFlush>,
+    closing: &mut tokio::task::JoinSet<()>,
+) {
+    flushed(flushes, tenant.flushes, Ok(()));
+    closing.spawn(close_tenant(tenant.ingest, write_buf));
+}
+
+/// Move queued metrics into the write buffer of each tenant, and start
+/// flushing the tenants with pending flush requests, or sending the due
+/// batches, one per tenant at a time and at most
+/// [InfluxiveWriterConfig::max_in_flight] overall. Tenants are visited
+/// round-robin from `cursor`, so a busy tenant can't starve the others.
+///
+/// While no send can be started, metrics are left queued, so a tenant
+/// writing faster than its batches are sent drops them at its bounded
+/// ingest queue, rather than buffering them without limit.
+fn schedule(
+    tenants: &mut BTreeMap<usize, PoolTenant>,
+    cursor: &mut usize,
+    in_flight: &mut InFlight,
+    max_in_flight: usize,
+) {
+    let ids = tenants
+        .range(*cursor..)
+        .chain(tenants.range(..*cursor))
+        .map(|(id, _)| *id)
+        .collect::<Vec<_>>();
+
+    for id in ids {
+        let tenant = tenants.get_mut(&id).unwrap();
+        let Some(write_buf) = tenant.write_buf.as_mut() else {
+            continue;
+        };
+
+        if in_flight.len() >= max_in_flight {
+            break;
+        }
+
+        for metric in tenant.ingest.drain() {
+            write_buf.process(metric);
+        }
+
+        if !tenant.flushes.is_empty() {
+            let flushes = std::mem::take(&mut tenant.flushes);
+            let mut write_buf = tenant.write_buf.take().unwrap();
+            in_flight.spawn(async move {
+                let result = write_buf.flush().await;
+                (id, write_buf, Some((flushes, result)))
+            });
+            *cursor = id + 1;
+        } else if write_buf.is_due() {
+            let mut write_buf = tenant.write_buf.take().unwrap();
+            in_flight.spawn(async move {
+                // errors are logged and counted in the tenant stats
+                let _ = write_buf.send().await;
+                (id, write_buf, None)
+            });
+            *cursor = id + 1;
+        }
+    }
+}
+
+/// Send the remaining metrics of a tenant and close its backend.
+async fn close_tenant(ingest: Arc<ingest::Ingest>, mut write_buf: WriteBuf) {
+    for metric in ingest.drain() {
+        write_buf.process(metric);
+    }
+    if let Err(err) = write_buf.flush().await {
+        tracing::warn!(?err, "flush metrics error");
+    }
+    if let Err(err) = write_buf.backend.close().await {
+        tracing::warn!(?err, "close metrics backend error");
+    }
+}
+
+/// The batching loop shared by all tenants of a pool. Runs until the
+/// [InfluxiveWriterPool] is dropped, then sends the remaining metrics
+/// of every tenant.
+async fn pool_task(
+    config: InfluxiveWriterConfig,
+    host: String,
+    token: String,
+    notify: Arc<tokio::sync::Notify>,
+    mut pool_recv: tokio::sync::mpsc::UnboundedReceiver<PoolCmd>,
+) {
+    // one connection pool for all tenants
+    let client = reqwest::Client::new();
+    let max_in_flight = config.max_in_flight.max(1);
+
+    let mut tenants = BTreeMap::<usize, PoolTenant>::new();
+    let mut flushes = BTreeMap::<usize, PendingFlush>::new();
+    let mut next_flush = 0;
+    let mut cursor = 0;
+    let mut in_flight = InFlight::new();
+    let mut closing = tokio::task::JoinSet::new();
+
+    loop {
+        // while all sends are in flight, due batches wait for a slot
+        let deadline = if in_flight.len() < max_in_flight {
+            tenants
+                .values()
+                .filter_map(|t| t.write_buf.as_ref()?.deadline())
+                .min()
+        } else {
+            None
+        };
+
+        tokio::select! {
+            cmd = pool_recv.recv() => match cmd {
+                Some(PoolCmd::Add(id, tenant, ingest, stats)) => {
+                    let mut factory = types::InfluxHttpBackendFactory::new()
+                        .with_client(client.clone());
+                    if let Some(org) = tenant.org {
+                        factory = factory.with_org(org);
+                    }
+                    let backend = types::BackendFactory::with_token_auth(
+                        &factory,
+                        host.clone(),
+                        tenant.bucket,
+                        token.clone(),
+                    );
+
+                    let mut tenant_config = config.clone();
+                    tenant_config.default_tags = tenant
+                        .tags
+                        .into_iter()
+                        .chain(config.default_tags.iter().cloned())
+                        .collect();
+
+                    tenants.insert(
+                        id,
+                        PoolTenant {
+                            ingest,
+                            write_buf: Some(WriteBuf::new(
+                                tenant_config,
+                                backend,
+                                stats,
+                                SharedConnectivity::default(),
+                            )),
+                            removed: false,
+                            flushes: Vec::new(),
+                        },
+                    );
+                }
+                Some(PoolCmd::Remove(id)) => {
+                    if let Some(tenant) = tenants.get_mut(&id) {
+                        tenant.removed = true;
+                        if let Some(write_buf) = tenant.write_buf.take() {
+                            let tenant = tenants.remove(&id).unwrap();
+                            remove_tenant(
+                                tenant,
+                                write_buf,
+                                &mut flushes,
+                                &mut closing,
+                            );
+                        }
+                    }
+                }
+                Some(PoolCmd::Flush(id, respond)) => {
+                    // flushed by schedule, without holding up the loop
+                    let flush_id = next_flush;
+                    next_flush += 1;
+                    let mut remaining = 0;
+                    for (tenant_id, tenant) in tenants.iter_mut() {
+                        if tenant.removed
+                            || id.is_some_and(|id| id != *tenant_id)
+                        {
+                            continue;
+                        }
+                        tenant.flushes.push(flush_id);
+                        remaining += 1;
+                    }
+                    if remaining == 0 {
+                        let _ = respond.send(Ok(()));
+                    } else {
+                        flushes.insert(
+                            flush_id,
+                            PendingFlush {
+                                remaining,
+                                result: Ok(()),
+                                respond,
+                            },
+                        );
+                    }
+                }
+                None => break,
+            },
+            _ = notify.notified() => (),
+            Some(done) = in_flight.join_next(), if !in_flight.is_empty() => {
+                if let Ok((id, write_buf, done)) = done {
+                    if let Some((ids, result)) = done {
+                        flushed(&mut flushes, ids, result);
+                    }
+                    if tenants.get(&id).is_some_and(|t| t.removed) {
+                        let tenant = tenants.remove(&id).unwrap();
+                        remove_tenant(
+                            tenant,
+                            write_buf,
+                            &mut flushes,
+                            &mut closing,
+                        );
+                    } else if let Some(tenant) = tenants.get_mut(&id) {
+                        tenant.write_buf = Some(write_buf);
+                    }
+                }
+            }
+            _ = tokio::time::sleep_until(
+                deadline.unwrap_or_else(tokio::time::Instant::now)
+            ), if deadline.is_some() => (),
+        }
+
+        schedule(&mut tenants, &mut cursor, &mut in_flight, max_in_flight);
+    }
+
+    while let Some(done) = in_flight.join_next().await {
+        if let Ok((id, write_buf, done)) = done {
+            if let Some((ids, result)) = done {
+                flushed(&mut flushes, ids, result);
+            }
+            if let Some(tenant) = tenants.get_mut(&id) {
+                tenant.write_buf = Some(write_buf);
+            }
+        }
+    }
+    for (_, mut tenant) in std::mem::take(&mut tenants) {
+        if let Some(write_buf) = tenant.write_buf.take() {
+            remove_tenant(tenant, write_buf, &mut flushes, &mut closing);
+        }
+    }
+    while closing.join_next().await.is_some() {}
+}
+
+/// Writes the metrics of many logical writers, e.g. one per tenant
+/// bucket, to one InfluxDB instance over HTTP, sharing one batching
+/// loop and one connection pool.
+///
+/// Each tenant writer queues, batches and retries on its own, and keeps
+/// its own [InfluxiveWriterStats]. Due batches are sent round-robin
+/// between tenants, at most [InfluxiveWriterConfig::max_in_flight] at a
+/// time, and at most one per tenant. A tenant writing more than its
+/// [InfluxiveWriterConfig::batch_buffer_size] while its batch is being
+/// sent, or while no send can be started, only drops its own metrics.
+///
+/// The pool always writes to InfluxDB over HTTP,
+/// [InfluxiveWriterConfig::backend] is ignored.
+pub struct InfluxiveWriterPool {
+    config: InfluxiveWriterConfig,
+    send: tokio::sync::mpsc::UnboundedSender<PoolCmd>,
+    notify: Arc<tokio::sync::Notify>,
+    next_id: std::sync::atomic::AtomicUsize,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Drop for InfluxiveWriterPool {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            // close the channel so the batching loop flushes and exits
+            let (closed, _) = tokio::sync::mpsc::unbounded_channel();
+            drop(std::mem::replace(&mut self.send, closed));
+            let _ = thread.join();
+        }
+    }
+}
+
+impl InfluxiveWriterPool {
+    /// Construct a new pool writing to this InfluxDB host, authenticated
+    /// by a token. The batching loop is spawned like the one of
+    /// [InfluxiveWriter::with_token_auth].
+    pub fn with_token_auth<H: AsRef<str>, T: AsRef<str>>(
+        config: InfluxiveWriterConfig,
+        host: H,
+        token: T,
+    ) -> Self {
+        let host = host.as_ref().to_string();
+        let token = token.as_ref().to_string();
+
+        let (pool_send, pool_recv) = tokio::sync::mpsc::unbounded_channel();
+        let notify = Arc::new(tokio::sync::Notify::new());
+        let task_notify = notify.clone();
+        let task_config = config.clone();
+
+        let thread = spawn_batching(config.background_thread, move || {
+            pool_task(task_config, host, token, task_notify, pool_recv)
+        });
+
+        Self {
+            config,
+            send: pool_send,
+            notify,
+            next_id: std::sync::atomic::AtomicUsize::new(0),
+            thread,
+        }
+    }
+
+    /// Add a logical writer for a tenant. Dropping the writer sends its
+    /// remaining metrics and removes it from the pool. Writers don't keep
+    /// the pool alive, metrics written after the pool is dropped are
+    /// ignored.
+    pub fn writer(&self, tenant: InfluxiveTenant) -> InfluxiveTenantWriter {
+        let id = self
+            .next_id
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let ingest = Arc::new(ingest::Ingest::with_notify(
+            self.config.batch_buffer_size,
+            self.notify.clone(),
+        ));
+        let stats = SharedStats::default();
+
+        let _ = self.send.send(PoolCmd::Add(
+            id,
+            tenant,
+            ingest.clone(),
+            stats.clone(),
+        ));
+
+        InfluxiveTenantWriter {
+            id,
+            ingest,
+            stats,
+            send: self.send.downgrade(),
+        }
+    }
+
+    /// Send the buffered metrics of every tenant now, e.g. before exiting.
+    /// Metrics written before this call are included.
+    pub async fn flush(&self) -> types::BackendResult<()> {
+        pool_flush(&self.send, None).await
+    }
+}
+
+async fn pool_flush(
+    send: &tokio::sync::mpsc::UnboundedSender<PoolCmd>,
+    id: Option<usize>,
+) -> types::BackendResult<()> {
+    let (respond, result) = tokio::sync::oneshot::channel();
+    if send.send(PoolCmd::Flush(id, respond)).is_err() {
+        return Err(err_other("writer closed").into());
+    }
+    result
+        .await
+        .unwrap_or_else(|_| Err(err_other("writer closed").into()))
+}
+
+/// Logical writer of one tenant of an [InfluxiveWriterPool].
+pub struct InfluxiveTenantWriter {
+    id: usize,
+    ingest: Arc<ingest::Ingest>,
+    stats: SharedStats,
+    send: tokio::sync::mpsc::WeakUnboundedSender<PoolCmd>,
+}
+
+impl Drop for InfluxiveTenantWriter {
+    fn drop(&mut self) {
+        if let Some(send) = self.send.upgrade() {
+            let _ = send.send(PoolCmd::Remove(self.id));
+        }
+    }
+}
+
+impl InfluxiveTenantWriter {
+    /// Get a snapshot of the statistics of this tenant.
+    pub fn stats(&self) -> InfluxiveWriterStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// Send the buffered metrics of this tenant now.
+    /// Metrics written before this call are included.
+    pub async fn flush(&self) -> types::BackendResult<()> {
+        match self.send.upgrade() {
+            Some(send) => pool_flush(&send, Some(self.id)).await,
+            None => Err(err_other("writer closed").into()),
+        }
+    }
+
+    /// Log a metric for this tenant, see [InfluxiveWriter::write_metric].
+    pub fn write_metric(&self, metric: Metric) {
+        if !self.ingest.push(metric) {
+            if self.send.strong_count() == 0 {
+                /* ignore this, can happen during shutdown */
+                return;
+            }
+            self.stats.lock().unwrap().metrics_dropped += 1;
+            tracing::warn!("metrics overloaded, dropping metric");
+        }
+    }
+}
+
+impl influxive_core::MetricWriter for InfluxiveTenantWriter {
+    fn write_metric(&self, metric: Metric) {
+        InfluxiveTenantWriter::write_metric(self, metric);
+    }
+}
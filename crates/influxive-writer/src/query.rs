@@ -0,0 +1,592 @@
+use crate::*;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+mod csv;
+pub(crate) use csv::*;
+
+#[cfg(feature = "serde")]
+mod de;
+
+/// The data type of a [QueryColumn], from the `#datatype` annotation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum QueryDataType {
+    /// `string`.
+    String,
+
+    /// `double`.
+    Double,
+
+    /// `long`.
+    Long,
+
+    /// `unsignedLong`.
+    UnsignedLong,
+
+    /// `boolean`.
+    Boolean,
+
+    /// `dateTime:RFC3339` or `dateTime:RFC3339Nano`.
+    DateTime,
+
+    /// `duration`.
+    Duration,
+
+    /// `base64Binary`, the values are kept as the encoded string.
+    Base64Binary,
+}
+
+impl QueryDataType {
+    fn parse(s: &str) -> Self {
+        match s {
+            "double" => QueryDataType::Double,
+            "long" => QueryDataType::Long,
+            "unsignedLong" => QueryDataType::UnsignedLong,
+            "boolean" => QueryDataType::Boolean,
+            "dateTime:RFC3339" | "dateTime:RFC3339Nano" => {
+                QueryDataType::DateTime
+            }
+            "duration" => QueryDataType::Duration,
+            "base64Binary" => QueryDataType::Base64Binary,
+            _ => QueryDataType::String,
+        }
+    }
+}
+
+/// A typed value of a [QueryRecord].
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryValue {
+    /// An empty cell without a default.
+    Null,
+
+    /// A string, or base64 encoded binary.
+    String(String),
+
+    /// A float.
+    Double(f64),
+
+    /// A signed integer.
+    Long(i64),
+
+    /// An unsigned integer.
+    UnsignedLong(u64),
+
+    /// A boolean.
+    Boolean(bool),
+
+    /// A timestamp.
+    DateTime(SystemTime),
+
+    /// A duration in nanoseconds, which may be negative.
+    Duration(i64),
+}
+
+impl QueryValue {
+    /// Is this an empty cell.
+    pub fn is_null(&self) -> bool {
+        matches!(self, QueryValue::Null)
+    }
+
+    /// The value of a string (or base64 binary) cell.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            QueryValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The value of a numeric cell, converted to a float.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            QueryValue::Double(f) => Some(*f),
+            QueryValue::Long(i) => Some(*i as f64),
+            QueryValue::UnsignedLong(u) => Some(*u as f64),
+            _ => None,
+        }
+    }
+
+    /// The value of an integer cell, if it fits in an `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            QueryValue::Long(i) => Some(*i),
+            QueryValue::UnsignedLong(u) => (*u).try_into().ok(),
+            _ => None,
+        }
+    }
+
+    /// The value of an integer cell, if it fits in a `u64`.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            QueryValue::Long(i) => (*i).try_into().ok(),
+            QueryValue::UnsignedLong(u) => Some(*u),
+            _ => None,
+        }
+    }
+
+    /// The value of a boolean cell.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            QueryValue::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// The value of a timestamp cell.
+    pub fn as_time(&self) -> Option<SystemTime> {
+        match self {
+            QueryValue::DateTime(t) => Some(*t),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for QueryValue {
+    /// Formats the value as an annotated CSV cell, an empty string
+    /// for [QueryValue::Null].
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryValue::Null => Ok(()),
+            QueryValue::String(s) => f.write_str(s),
+            QueryValue::Double(d) if d.is_infinite() => {
+                f.write_str(if *d > 0.0 { "+Inf" } else { "-Inf" })
+            }
+            QueryValue::Double(d) => d.fmt(f),
+            QueryValue::Long(i) => i.fmt(f),
+            QueryValue::UnsignedLong(u) => u.fmt(f),
+            QueryValue::Boolean(b) => b.fmt(f),
+            QueryValue::DateTime(t) => {
+                f.write_str(&format_rfc3339(time_to_nanos(*t)))
+            }
+            QueryValue::Duration(d) => write!(f, "{d}ns"),
+        }
+    }
+}
+
+/// A column of a [QueryTable].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct QueryColumn {
+    /// The column name, e.g. `_time`, `_value` or a tag key.
+    pub name: String,
+
+    /// The type of the values in this column.
+    pub data_type: QueryDataType,
+
+    /// Is this column part of the group key of its table.
+    pub group: bool,
+}
+
+/// A row of a query result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryRecord {
+    result: Arc<str>,
+    table: i64,
+    columns: Arc<[QueryColumn]>,
+    values: Vec<QueryValue>,
+}
+
+impl QueryRecord {
+    /// The name of the result this record belongs to,
+    /// `_result` unless the query yields under other names.
+    pub fn result(&self) -> &str {
+        &self.result
+    }
+
+    /// The index of the table this record belongs to.
+    pub fn table(&self) -> i64 {
+        self.table
+    }
+
+    /// The columns of this record, excluding `result` and `table`.
+    pub fn columns(&self) -> &[QueryColumn] {
+        &self.columns
+    }
+
+    /// The values of this record, in the order of [QueryRecord::columns].
+    pub fn values(&self) -> &[QueryValue] {
+        &self.values
+    }
+
+    /// The value of the named column.
+    pub fn get(&self, name: &str) -> Option<&QueryValue> {
+        self.columns
+            .iter()
+            .position(|c| c.name == name)
+            .map(|i| &self.values[i])
+    }
+
+    /// The `_time` of this record.
+    pub fn time(&self) -> Option<SystemTime> {
+        self.get("_time").and_then(QueryValue::as_time)
+    }
+
+    /// The `_value` of this record.
+    pub fn value(&self) -> Option<&QueryValue> {
+        self.get("_value")
+    }
+
+    /// The `_field` of this record.
+    pub fn field(&self) -> Option<&str> {
+        self.get("_field").and_then(QueryValue::as_str)
+    }
+
+    /// The `_measurement` of this record.
+    pub fn measurement(&self) -> Option<&str> {
+        self.get("_measurement").and_then(QueryValue::as_str)
+    }
+
+    /// Deserialize this record into `T`, e.g. a struct with a field per
+    /// column (use `#[serde(rename = "_value")]` for the Flux columns),
+    /// or a tuple of the values in column order. Timestamps deserialize
+    /// as RFC3339 strings, as nanoseconds since the unix epoch into
+    /// integers, or into a `SystemTime`.
+    #[cfg(feature = "serde")]
+    pub fn deserialize<T: serde::de::DeserializeOwned>(
+        &self,
+    ) -> std::io::Result<T> {
+        T::deserialize(de::RecordDeserializer(self)).map_err(|err| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("table {}: {err}", self.table),
+            )
+        })
+    }
+}
+
+/// A table of a query result: the records sharing a group key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryTable {
+    result: Arc<str>,
+    table: i64,
+    columns: Arc<[QueryColumn]>,
+    records: Vec<QueryRecord>,
+}
+
+impl QueryTable {
+    /// The name of the result this table belongs to.
+    pub fn result(&self) -> &str {
+        &self.result
+    }
+
+    /// The index of this table.
+    pub fn table(&self) -> i64 {
+        self.table
+    }
+
+    /// The columns of this table, excluding `result` and `table`.
+    pub fn columns(&self) -> &[QueryColumn] {
+        &self.columns
+    }
+
+    /// The records of this table.
+    pub fn records(&self) -> &[QueryRecord] {
+        &self.records
+    }
+
+    /// Take the records of this table.
+    pub fn into_records(self) -> Vec<QueryRecord> {
+        self.records
+    }
+
+    /// The values of the group key columns, which are the same for
+    /// every record of the table.
+    pub fn group_key(&self) -> Vec<(&str, &QueryValue)> {
+        let Some(first) = self.records.first() else {
+            return Vec::new();
+        };
+        self.columns
+            .iter()
+            .zip(first.values.iter())
+            .filter(|(c, _)| c.group)
+            .map(|(c, v)| (c.name.as_str(), v))
+            .collect()
+    }
+}
+
+/// Parse an annotated CSV query response, as returned by the InfluxDB
+/// `/api/v2/query` API or `influx query --raw`, into its tables.
+/// An error returned in the response is returned as an error.
+pub fn parse_annotated_csv(csv: &str) -> std::io::Result<Vec<QueryTable>> {
+    let mut rows = CsvRows::default();
+    rows.push(csv.as_bytes());
+    let mut parser = AnnotatedCsv::default();
+    let mut tables: Vec<QueryTable> = Vec::new();
+    while let Some(row) = rows.next_row(true)? {
+        let Some(record) = parser.row(row)? else {
+            continue;
+        };
+        match tables.last_mut() {
+            Some(t)
+                if t.table == record.table
+                    && t.result == record.result
+                    && Arc::ptr_eq(&t.columns, &record.columns) =>
+            {
+                t.records.push(record)
+            }
+            _ => tables.push(QueryTable {
+                result: record.result.clone(),
+                table: record.table,
+                columns: record.columns.clone(),
+                records: vec![record],
+            }),
+        }
+    }
+    Ok(tables)
+}
+
+/// Runs Flux queries over the InfluxDB v2 HTTP API (`/api/v2/query`),
+/// parsing the annotated CSV response into typed [QueryTable]s.
+#[derive(Clone)]
+pub struct InfluxiveQueryClient {
+    client: reqwest::Client,
+    host: String,
+    org: String,
+    token: String,
+    timeout: std::time::Duration,
+}
+
+impl std::fmt::Debug for InfluxiveQueryClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // don't leak the token into logs
+        f.debug_struct("InfluxiveQueryClient")
+            .field("host", &self.host)
+            .field("org", &self.org)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl InfluxiveQueryClient {
+    /// Construct a client querying the InfluxDB at `host` as
+    /// organization `org`, authenticated with `token`.
+    pub fn new<H: Into<String>, O: Into<String>, T: Into<String>>(
+        host: H,
+        org: O,
+        token: T,
+    ) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            host: host.into().trim_end_matches('/').to_string(),
+            org: org.into(),
+            token: token.into(),
+            timeout: std::time::Duration::from_secs(30),
+        }
+    }
+
+    /// Send requests with this client, e.g. to configure timeouts.
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Timeout waiting for the response to a query, and then for each
+    /// chunk of the result, so a stalled server fails the query instead
+    /// of hanging it. Reading a large result may take longer in total.
+    /// Defaults to `30s`.
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Get the host this client queries.
+    pub fn get_host(&self) -> &str {
+        &self.host
+    }
+
+    /// Get the organization this client queries as.
+    pub fn get_org(&self) -> &str {
+        &self.org
+    }
+
+    pub(crate) async fn send(
+        &self,
+        flux: StringType,
+    ) -> std::io::Result<reqwest::Response> {
+        let mut body = String::from("{\"query\":");
+        types::json_str(&mut body, flux.as_str());
+        body.push_str(
+            ",\"type\":\"flux\",\"dialect\":{\"header\":true,\
+             \"annotations\":[\"datatype\",\"group\",\"default\"]}}",
+        );
+
+        let res = self
+            .client
+            .post(format!("{}/api/v2/query", self.host))
+            .query(&[("org", self.org.as_str())])
+            .header("Authorization", format!("Token {}", self.token))
+            .header("Accept", "application/csv")
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send();
+        let res = tokio::time::timeout(self.timeout, res)
+            .await
+            .map_err(|_| timed_out(self.timeout))?
+            .map_err(std::io::Error::other)?;
+
+        let status = res.status();
+        if status.is_success() {
+            Ok(res)
+        } else {
+            let text = tokio::time::timeout(self.timeout, res.text()).await;
+            Err(std::io::Error::other(format!(
+                "query failed with status {}: {}",
+                status.as_u16(),
+                text.ok().and_then(Result::ok).unwrap_or_default(),
+            )))
+        }
+    }
+
+    /// Run a Flux query, returning the raw annotated CSV response.
+    pub async fn query_raw<Q: Into<StringType>>(
+        &self,
+        flux: Q,
+    ) -> std::io::Result<String> {
+        let mut res = self.send(flux.into()).await?;
+        let mut body = Vec::new();
+        while let Some(chunk) = next_chunk(&mut res, self.timeout).await? {
+            body.extend_from_slice(chunk.as_ref());
+        }
+        String::from_utf8(body).map_err(std::io::Error::other)
+    }
+
+    /// Run a Flux query, returning the tables of the result.
+    pub async fn query<Q: Into<StringType>>(
+        &self,
+        flux: Q,
+    ) -> std::io::Result<Vec<QueryTable>> {
+        parse_annotated_csv(&self.query_raw(flux).await?)
+    }
+
+    /// Run a Flux query, returning a stream of the records of the result,
+    /// parsed while the response is still arriving, so results larger
+    /// than memory can be processed. Dropping the stream cancels the
+    /// query by closing the connection. The stream ends after the first
+    /// error.
+    pub async fn query_stream<Q: Into<StringType>>(
+        &self,
+        flux: Q,
+    ) -> std::io::Result<QueryStream> {
+        let res = self.send(flux.into()).await?;
+        Ok(QueryStream::new(res, self.timeout))
+    }
+
+    /// Run a Flux query, deserializing every record of the result into
+    /// `T`, see [QueryRecord::deserialize].
+    #[cfg(feature = "serde")]
+    pub async fn query_as<
+        T: serde::de::DeserializeOwned,
+        Q: Into<StringType>,
+    >(
+        &self,
+        flux: Q,
+    ) -> std::io::Result<Vec<T>> {
+        self.query(flux)
+            .await?
+            .iter()
+            .flat_map(QueryTable::records)
+            .map(QueryRecord::deserialize)
+            .collect()
+    }
+}
+
+fn timed_out(timeout: std::time::Duration) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::TimedOut,
+        format!("query timed out after {timeout:?}"),
+    )
+}
+
+/// Read the next chunk of a response body within `timeout`.
+async fn next_chunk(
+    res: &mut reqwest::Response,
+    timeout: std::time::Duration,
+) -> std::io::Result<Option<impl AsRef<[u8]>>> {
+    tokio::time::timeout(timeout, res.chunk())
+        .await
+        .map_err(|_| timed_out(timeout))?
+        .map_err(std::io::Error::other)
+}
+
+struct QueryStreamState {
+    res: Option<reqwest::Response>,
+    timeout: std::time::Duration,
+    rows: CsvRows,
+    parser: AnnotatedCsv,
+}
+
+impl QueryStreamState {
+    async fn next_record(&mut self) -> Option<std::io::Result<QueryRecord>> {
+        loop {
+            let row = match self.rows.next_row(self.res.is_none()) {
+                Ok(Some(row)) => row,
+                Ok(None) => {
+                    // once the response is finished, all rows were taken
+                    match next_chunk(self.res.as_mut()?, self.timeout).await {
+                        Ok(Some(chunk)) => self.rows.push(chunk.as_ref()),
+                        Ok(None) => self.res = None,
+                        Err(err) => return Some(Err(err)),
+                    }
+                    continue;
+                }
+                Err(err) => return Some(Err(err)),
+            };
+            match self.parser.row(row) {
+                Ok(Some(record)) => return Some(Ok(record)),
+                Ok(None) => (),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+/// The records of a query result, parsed while the response arrives,
+/// see [InfluxiveQueryClient::query_stream].
+pub struct QueryStream(
+    std::pin::Pin<
+        Box<
+            dyn futures::Stream<Item = std::io::Result<QueryRecord>>
+                + 'static
+                + Send,
+        >,
+    >,
+);
+
+impl std::fmt::Debug for QueryStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("QueryStream")
+    }
+}
+
+impl QueryStream {
+    fn new(res: reqwest::Response, timeout: std::time::Duration) -> Self {
+        let state = QueryStreamState {
+            res: Some(res),
+            timeout,
+            rows: CsvRows::default(),
+            parser: AnnotatedCsv::default(),
+        };
+        Self(Box::pin(futures::stream::unfold(
+            Some(state),
+            |state| async move {
+                let mut state = state?;
+                match state.next_record().await? {
+                    Ok(record) => Some((Ok(record), Some(state))),
+                    // dropping the state closes the connection
+                    Err(err) => Some((Err(err), None)),
+                }
+            },
+        )))
+    }
+}
+
+impl futures::Stream for QueryStream {
+    type Item = std::io::Result<QueryRecord>;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        self.0.as_mut().poll_next(cx)
+    }
+}
@@ -0,0 +1,440 @@
+use super::*;
+
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+
+fn invalid(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+/// Days since the unix epoch of a proleptic Gregorian calendar date.
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Proleptic Gregorian calendar date of days since the unix epoch.
+fn civil_from_days(z: i64) -> (i64, i64, i64) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    (
+        if m <= 2 {
+            yoe + era * 400 + 1
+        } else {
+            yoe + era * 400
+        },
+        m,
+        d,
+    )
+}
+
+/// Nanoseconds since the unix epoch, saturating at the `i64` range.
+pub(crate) fn time_to_nanos(t: SystemTime) -> i64 {
+    match t.duration_since(std::time::UNIX_EPOCH) {
+        Ok(d) => d.as_nanos().try_into().unwrap_or(i64::MAX),
+        Err(err) => {
+            -i64::try_from(err.duration().as_nanos()).unwrap_or(i64::MAX)
+        }
+    }
+}
+
+/// The time at nanoseconds since the unix epoch.
+pub(crate) fn nanos_to_time(nanos: i64) -> SystemTime {
+    let d = std::time::Duration::from_nanos(nanos.unsigned_abs());
+    if nanos < 0 {
+        std::time::UNIX_EPOCH - d
+    } else {
+        std::time::UNIX_EPOCH + d
+    }
+}
+
+/// Format nanoseconds since the unix epoch as RFC3339 in UTC,
+/// with trailing zeros of the fraction trimmed.
+pub(crate) fn format_rfc3339(nanos: i64) -> String {
+    let secs = nanos.div_euclid(NANOS_PER_SEC);
+    let frac = nanos.rem_euclid(NANOS_PER_SEC);
+    let (y, m, d) = civil_from_days(secs.div_euclid(86400));
+    let sod = secs.rem_euclid(86400);
+    let mut out = format!(
+        "{y:04}-{m:02}-{d:02}T{:02}:{:02}:{:02}",
+        sod / 3600,
+        sod % 3600 / 60,
+        sod % 60
+    );
+    if frac > 0 {
+        let frac = format!("{frac:09}");
+        out.push('.');
+        out.push_str(frac.trim_end_matches('0'));
+    }
+    out.push('Z');
+    out
+}
+
+/// Parse an RFC3339 timestamp, e.g. `2023-01-02T03:04:05.5Z`,
+/// into nanoseconds since the unix epoch.
+pub(crate) fn parse_rfc3339(s: &str) -> Option<i64> {
+    let num = |s: &str| -> Option<i64> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        s.parse().ok()
+    };
+
+    let (date, time) = s.split_once('T')?;
+    let mut date = date.split('-');
+    let (y, m, d) =
+        (num(date.next()?)?, num(date.next()?)?, num(date.next()?)?);
+
+    let (time, offset) = if let Some(time) = time.strip_suffix('Z') {
+        (time, 0)
+    } else {
+        let at = time.rfind(['+', '-'])?;
+        let (time, offset) = time.split_at(at);
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let (oh, om) = offset[1..].split_once(':')?;
+        (time, sign * (num(oh)? * 3600 + num(om)? * 60))
+    };
+
+    let (time, frac) = match time.split_once('.') {
+        Some((time, frac)) if frac.len() <= 9 => {
+            (time, num(frac)? * 10_i64.pow(9 - frac.len() as u32))
+        }
+        Some(_) => return None,
+        None => (time, 0),
+    };
+    let mut time = time.split(':');
+    let (hh, mm, ss) =
+        (num(time.next()?)?, num(time.next()?)?, num(time.next()?)?);
+
+    let secs =
+        days_from_civil(y, m, d) * 86400 + hh * 3600 + mm * 60 + ss - offset;
+    secs.checked_mul(NANOS_PER_SEC)?.checked_add(frac)
+}
+
+/// Parse a Flux duration literal, e.g. `-15m` or `1h30m`, into nanoseconds.
+pub(crate) fn parse_duration(s: &str) -> Option<i64> {
+    let (sign, mut rest) = match s.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, s),
+    };
+    if rest.is_empty() {
+        return None;
+    }
+
+    let mut total: i64 = 0;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        if digits == 0 {
+            return None;
+        }
+        let n: i64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let unit = match &rest[..unit_len] {
+            "ns" => 1,
+            "us" | "µs" => 1_000,
+            "ms" => 1_000_000,
+            "s" => NANOS_PER_SEC,
+            "m" => 60 * NANOS_PER_SEC,
+            "h" => 3600 * NANOS_PER_SEC,
+            "d" => 86400 * NANOS_PER_SEC,
+            "w" => 7 * 86400 * NANOS_PER_SEC,
+            _ => return None,
+        };
+        rest = &rest[unit_len..];
+        total = total.checked_add(n.checked_mul(unit)?)?;
+    }
+    Some(sign * total)
+}
+
+/// Splits CSV into rows as bytes arrive, honoring quoted cells
+/// spanning lines.
+#[derive(Default)]
+pub(crate) struct CsvRows {
+    buf: Vec<u8>,
+    /// Start of the next row in `buf`.
+    start: usize,
+    /// Bytes of the next row already scanned for its end.
+    scanned: usize,
+    quoted: bool,
+    line: u64,
+}
+
+impl CsvRows {
+    /// Append received bytes.
+    pub(crate) fn push(&mut self, bytes: &[u8]) {
+        if self.start > 0 {
+            self.buf.drain(..self.start);
+            self.scanned -= self.start;
+            self.start = 0;
+        }
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Take the next complete row. With `eof`, the remaining bytes are
+    /// the last row even without a trailing newline.
+    pub(crate) fn next_row(
+        &mut self,
+        eof: bool,
+    ) -> std::io::Result<Option<Vec<String>>> {
+        let mut end = None;
+        while self.scanned < self.buf.len() {
+            match self.buf[self.scanned] {
+                b'"' => self.quoted = !self.quoted,
+                b'\n' if !self.quoted => end = Some(self.scanned),
+                _ => (),
+            }
+            self.scanned += 1;
+            if end.is_some() {
+                break;
+            }
+        }
+        let (end, next) = match end {
+            Some(end) => (end, end + 1),
+            None if eof && self.start < self.buf.len() => {
+                (self.buf.len(), self.buf.len())
+            }
+            None => return Ok(None),
+        };
+
+        self.line += 1;
+        let row = std::str::from_utf8(&self.buf[self.start..end])
+            .map_err(|err| invalid(format!("line {}: {err}", self.line)))?;
+        let row = split_row(row.strip_suffix('\r').unwrap_or(row));
+        self.start = next;
+        self.scanned = next;
+        self.quoted = false;
+        Ok(Some(row))
+    }
+}
+
+fn split_row(row: &str) -> Vec<String> {
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut chars = row.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                cell.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => cells.push(std::mem::take(&mut cell)),
+            c => cell.push(c),
+        }
+    }
+    cells.push(cell);
+    cells
+}
+
+/// Turns annotated CSV rows into records.
+#[derive(Default)]
+pub(crate) struct AnnotatedCsv {
+    datatypes: Option<Vec<String>>,
+    groups: Option<Vec<String>>,
+    defaults: Option<Vec<String>>,
+    header: Option<Header>,
+}
+
+struct Header {
+    columns: Arc<[QueryColumn]>,
+    /// Row cell index of each column.
+    cells: Vec<usize>,
+    defaults: Vec<String>,
+    result: Option<usize>,
+    default_result: Arc<str>,
+    table: Option<usize>,
+    error: bool,
+}
+
+impl AnnotatedCsv {
+    /// Consume a row, returning a record if it is a data row.
+    pub(crate) fn row(
+        &mut self,
+        row: Vec<String>,
+    ) -> std::io::Result<Option<QueryRecord>> {
+        // an empty line separates tables with different columns
+        if row.iter().all(String::is_empty) {
+            *self = Self::default();
+            return Ok(None);
+        }
+
+        match row[0].as_str() {
+            "#datatype" => {
+                self.header = None;
+                self.datatypes = Some(row);
+                return Ok(None);
+            }
+            "#group" => {
+                self.header = None;
+                self.groups = Some(row);
+                return Ok(None);
+            }
+            "#default" => {
+                self.header = None;
+                self.defaults = Some(row);
+                return Ok(None);
+            }
+            a if a.starts_with('#') => return Ok(None),
+            _ => (),
+        }
+
+        let Some(header) = &self.header else {
+            self.header = Some(self.header(row));
+            return Ok(None);
+        };
+
+        if header.error {
+            let message = header
+                .cells
+                .first()
+                .and_then(|&i| row.get(i))
+                .cloned()
+                .unwrap_or_default();
+            return Err(std::io::Error::other(format!(
+                "query failed: {message}"
+            )));
+        }
+
+        let cell = |i: Option<usize>| i.and_then(|i| row.get(i));
+        let result = match cell(header.result) {
+            Some(r) if !r.is_empty() => r.as_str().into(),
+            _ => header.default_result.clone(),
+        };
+        let table = match cell(header.table) {
+            Some(t) if !t.is_empty() => t
+                .parse()
+                .map_err(|_| invalid(format!("invalid table index: {t}")))?,
+            _ => 0,
+        };
+        let mut values = Vec::with_capacity(header.columns.len());
+        for ((column, &i), default) in header
+            .columns
+            .iter()
+            .zip(header.cells.iter())
+            .zip(header.defaults.iter())
+        {
+            let cell = match row.get(i).map(String::as_str) {
+                None | Some("") => default.as_str(),
+                Some(cell) => cell,
+            };
+            values.push(parse_value(column, cell)?);
+        }
+
+        Ok(Some(QueryRecord {
+            result,
+            table,
+            columns: header.columns.clone(),
+            values,
+        }))
+    }
+
+    fn header(&self, row: Vec<String>) -> Header {
+        let annotation = |a: &Option<Vec<String>>, i: usize| {
+            a.as_ref()
+                .and_then(|a| a.get(i))
+                .cloned()
+                .unwrap_or_default()
+        };
+
+        let mut columns = Vec::new();
+        let mut cells = Vec::new();
+        let mut defaults = Vec::new();
+        let mut result = None;
+        let mut default_result = String::from("_result");
+        let mut table = None;
+        // the first cell is the annotation column
+        for (i, name) in row.into_iter().enumerate().skip(1) {
+            match name.as_str() {
+                "result" => {
+                    result = Some(i);
+                    let default = annotation(&self.defaults, i);
+                    if !default.is_empty() {
+                        default_result = default;
+                    }
+                }
+                "table" => table = Some(i),
+                _ => {
+                    columns.push(QueryColumn {
+                        name,
+                        data_type: QueryDataType::parse(&annotation(
+                            &self.datatypes,
+                            i,
+                        )),
+                        group: annotation(&self.groups, i) == "true",
+                    });
+                    cells.push(i);
+                    defaults.push(annotation(&self.defaults, i));
+                }
+            }
+        }
+
+        let error = columns.len() == 2
+            && columns[0].name == "error"
+            && columns[1].name == "reference";
+
+        Header {
+            columns: columns.into(),
+            cells,
+            defaults,
+            result,
+            default_result: default_result.into(),
+            table,
+            error,
+        }
+    }
+}
+
+fn parse_value(
+    column: &QueryColumn,
+    cell: &str,
+) -> std::io::Result<QueryValue> {
+    if cell.is_empty() && column.data_type != QueryDataType::String {
+        return Ok(QueryValue::Null);
+    }
+    let bad = || {
+        invalid(format!(
+            "invalid {:?} value in column {}: {cell}",
+            column.data_type, column.name
+        ))
+    };
+    Ok(match column.data_type {
+        QueryDataType::String | QueryDataType::Base64Binary => {
+            QueryValue::String(cell.to_string())
+        }
+        QueryDataType::Double => match cell {
+            "+Inf" => QueryValue::Double(f64::INFINITY),
+            "-Inf" => QueryValue::Double(f64::NEG_INFINITY),
+            _ => QueryValue::Double(cell.parse().map_err(|_| bad())?),
+        },
+        QueryDataType::Long => {
+            QueryValue::Long(cell.parse().map_err(|_| bad())?)
+        }
+        QueryDataType::UnsignedLong => {
+            QueryValue::UnsignedLong(cell.parse().map_err(|_| bad())?)
+        }
+        QueryDataType::Boolean => match cell {
+            "true" => QueryValue::Boolean(true),
+            "false" => QueryValue::Boolean(false),
+            _ => return Err(bad()),
+        },
+        QueryDataType::DateTime => QueryValue::DateTime(nanos_to_time(
+            parse_rfc3339(cell).ok_or_else(bad)?,
+        )),
+        QueryDataType::Duration => {
+            QueryValue::Duration(parse_duration(cell).ok_or_else(bad)?)
+        }
+    })
+}
@@ -0,0 +1,191 @@
+use super::*;
+use serde::de::value::{Error, MapDeserializer, SeqDeserializer};
+use serde::de::{self, IntoDeserializer, Visitor};
+
+/// Deserializes a record as a map of column names to values,
+/// or as a sequence of values.
+pub(crate) struct RecordDeserializer<'a>(pub(crate) &'a QueryRecord);
+
+impl<'de> de::Deserializer<'de> for RecordDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let mut map = MapDeserializer::new(
+            self.0
+                .columns
+                .iter()
+                .zip(self.0.values.iter())
+                .map(|(c, v)| (c.name.as_str(), ValueDeserializer(v))),
+        );
+        let value = visitor.visit_map(&mut map)?;
+        map.end()?;
+        Ok(value)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let mut seq =
+            SeqDeserializer::new(self.0.values.iter().map(ValueDeserializer));
+        let value = visitor.visit_seq(&mut seq)?;
+        seq.end()?;
+        Ok(value)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct map struct
+        enum identifier ignored_any
+    }
+}
+
+struct ValueDeserializer<'a>(&'a QueryValue);
+
+impl<'de> IntoDeserializer<'de, Error> for ValueDeserializer<'_> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.0 {
+            QueryValue::Null => visitor.visit_unit(),
+            QueryValue::String(s) => visitor.visit_str(s),
+            QueryValue::Double(f) => visitor.visit_f64(*f),
+            QueryValue::Long(i) => visitor.visit_i64(*i),
+            QueryValue::UnsignedLong(u) => visitor.visit_u64(*u),
+            QueryValue::Boolean(b) => visitor.visit_bool(*b),
+            QueryValue::DateTime(_) => visitor.visit_string(self.0.to_string()),
+            QueryValue::Duration(d) => visitor.visit_i64(*d),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.0 {
+            QueryValue::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.0 {
+            QueryValue::DateTime(t) => visitor.visit_i64(time_to_nanos(*t)),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.0 {
+            QueryValue::DateTime(t) => visitor.visit_i64(time_to_nanos(*t)),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_i128<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_u128<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        // the serde representation of std::time::SystemTime
+        match self.0 {
+            QueryValue::DateTime(t) if name == "SystemTime" => {
+                let since =
+                    t.duration_since(std::time::UNIX_EPOCH).map_err(|_| {
+                        de::Error::custom("time is before the unix epoch")
+                    })?;
+                let mut map = MapDeserializer::new(
+                    [
+                        (fields[0], since.as_secs()),
+                        (fields[1], since.subsec_nanos() as u64),
+                    ]
+                    .into_iter(),
+                );
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.0 {
+            QueryValue::String(s) => {
+                visitor.visit_enum(s.as_str().into_deserializer())
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 u8 u16 u32 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map
+        identifier ignored_any
+    }
+}
@@ -0,0 +1,403 @@
+use super::*;
+use std::path::{Path, PathBuf};
+
+/// Streaming reader of InfluxDB Line Protocol, e.g. a file written by
+/// [types::LineProtocolFileBackendFactory].
+///
+/// Blank lines and `#` comments are skipped. An invalid line is
+/// consumed and reported as an [std::io::ErrorKind::InvalidData] error
+/// naming its line number, so reading can continue past it.
+pub struct LineProtocolReader<R> {
+    reader: R,
+    precision: types::Precision,
+    follow: bool,
+    offset: u64,
+    line_number: u64,
+    line: Vec<u8>,
+}
+
+impl<R: tokio::io::AsyncBufRead + Unpin> LineProtocolReader<R> {
+    /// Read Line Protocol with nanosecond timestamps from `reader`.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            precision: types::Precision::Nanoseconds,
+            follow: false,
+            offset: 0,
+            line_number: 0,
+            line: Vec::new(),
+        }
+    }
+
+    /// Timestamp precision of the read lines.
+    /// Defaults to [types::Precision::Nanoseconds].
+    pub fn with_precision(mut self, precision: types::Precision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// If true, the input is still being written: a last line without a
+    /// trailing newline is not read until it is completed, and reading
+    /// can continue after the end of the input was reached.
+    /// Defaults to `false`.
+    pub fn with_follow(mut self, follow: bool) -> Self {
+        self.follow = follow;
+        self
+    }
+
+    /// The byte offset `reader` starts at, if it was positioned past the
+    /// start of its source, e.g. when resuming from a checkpoint.
+    /// Defaults to `0`.
+    pub fn with_offset(mut self, offset: u64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// The byte offset just past the last line read.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// The number of lines read, counting from the start offset.
+    pub fn line_number(&self) -> u64 {
+        self.line_number
+    }
+
+    /// Read the next metric, or `None` at the end of the input.
+    pub async fn next_metric(&mut self) -> std::io::Result<Option<Metric>> {
+        use tokio::io::AsyncBufReadExt;
+
+        loop {
+            // an incomplete line read while following is kept in `line`
+            // and completed by the next read
+            self.reader.read_until(b'\n', &mut self.line).await?;
+            if self.line.is_empty()
+                || (self.follow && !self.line.ends_with(b"\n"))
+            {
+                return Ok(None);
+            }
+            self.offset += self.line.len() as u64;
+            self.line_number += 1;
+            let line = std::mem::take(&mut self.line);
+
+            let invalid = |err: String| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("line {}: {err}", self.line_number),
+                )
+            };
+
+            let line = std::str::from_utf8(&line)
+                .map_err(|err| invalid(err.to_string()))?
+                .trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            return parse_line_protocol(
+                line,
+                std::time::Duration::from_nanos(self.precision.as_nanos()),
+                std::time::SystemTime::now(),
+            )
+            .map(Some)
+            .map_err(|err| invalid(err.to_string()));
+        }
+    }
+}
+
+/// How far a [LineProtocolReplay] got.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ReplayProgress {
+    /// The byte offset in the file replayed up to.
+    pub offset: u64,
+
+    /// The number of metrics replayed by this run.
+    pub metrics: u64,
+
+    /// The number of invalid lines skipped by this run,
+    /// see [LineProtocolReplay::with_skip_invalid].
+    pub invalid_lines: u64,
+}
+
+/// Push a Line Protocol file, e.g. one written by
+/// [types::LineProtocolFileBackendFactory] while offline, into a
+/// [MetricWriter] or a [types::Backend], without running Telegraf.
+///
+/// With a checkpoint path, the byte offset replayed up to is saved there
+/// after every batch, and a following replay resumes from it. If the
+/// file is shorter than the checkpoint, it is assumed to be a new file
+/// and is replayed from the start.
+#[derive(Debug, Clone)]
+pub struct LineProtocolReplay {
+    path: PathBuf,
+    checkpoint_path: Option<PathBuf>,
+    precision: types::Precision,
+    batch_size: usize,
+    skip_invalid: bool,
+}
+
+impl LineProtocolReplay {
+    /// Replay the Line Protocol file at `path`.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            checkpoint_path: None,
+            precision: types::Precision::Nanoseconds,
+            batch_size: 5000,
+            skip_invalid: false,
+        }
+    }
+
+    /// File to save the replayed byte offset to, and to resume from.
+    /// Defaults to `None`, replaying the whole file every time.
+    pub fn with_checkpoint_path(
+        mut self,
+        checkpoint_path: Option<PathBuf>,
+    ) -> Self {
+        self.checkpoint_path = checkpoint_path;
+        self
+    }
+
+    /// Timestamp precision of the file.
+    /// Defaults to [types::Precision::Nanoseconds].
+    pub fn with_precision(mut self, precision: types::Precision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Metrics sent between checkpoints.
+    /// Defaults to `5000`.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// If true, invalid lines are logged and skipped,
+    /// otherwise the replay stops at the first invalid line.
+    /// Defaults to `false`.
+    pub fn with_skip_invalid(mut self, skip_invalid: bool) -> Self {
+        self.skip_invalid = skip_invalid;
+        self
+    }
+
+    /// The byte offset saved in the checkpoint file, `0` if there is none.
+    pub fn checkpoint(&self) -> std::io::Result<u64> {
+        match &self.checkpoint_path {
+            Some(path) => Ok(Checkpoint::read(path)?.offset),
+            None => Ok(0),
+        }
+    }
+
+    /// Send the metrics of the file through `writer` in batches, see
+    /// [InfluxiveWriter::write_batch], checkpointing after each delivered
+    /// batch. If a batch fails, the error is returned and the next replay
+    /// resumes with that batch.
+    pub async fn to_writer(
+        &self,
+        writer: &InfluxiveWriter,
+    ) -> types::BackendResult<ReplayProgress> {
+        let mut reader = self.open().await?;
+        let mut progress = ReplayProgress {
+            offset: reader.offset(),
+            ..Default::default()
+        };
+        let mut batch = Vec::new();
+
+        loop {
+            let metric = self.next(&mut reader, &mut progress).await?;
+            let done = metric.is_none();
+            if let Some(metric) = metric {
+                batch.push(metric);
+            }
+
+            if batch.len() >= self.batch_size || (done && !batch.is_empty()) {
+                let count = batch.len() as u64;
+                writer.write_batch(std::mem::take(&mut batch)).await?;
+                progress.metrics += count;
+                self.save(reader.offset(), &mut progress)?;
+            }
+
+            if done {
+                self.save(reader.offset(), &mut progress)?;
+                return Ok(progress);
+            }
+        }
+    }
+
+    /// Send the metrics of the file through `backend` in batches,
+    /// checkpointing after each delivered batch. If a send fails, the
+    /// error is returned and the next replay resumes with that batch.
+    pub async fn to_backend(
+        &self,
+        backend: &mut dyn types::Backend,
+    ) -> types::BackendResult<ReplayProgress> {
+        let mut reader = self.open().await?;
+        let mut progress = ReplayProgress {
+            offset: reader.offset(),
+            ..Default::default()
+        };
+
+        loop {
+            let metric = self.next(&mut reader, &mut progress).await?;
+            let done = metric.is_none();
+            if let Some(metric) = metric {
+                backend.buffer_metric(metric);
+                progress.metrics += 1;
+            }
+
+            if backend.buffer_count() >= self.batch_size
+                || (done && backend.buffer_count() > 0)
+            {
+                backend.send().await?;
+                backend.flush().await?;
+                self.save(reader.offset(), &mut progress)?;
+            }
+
+            if done {
+                self.save(reader.offset(), &mut progress)?;
+                return Ok(progress);
+            }
+        }
+    }
+
+    async fn open(
+        &self,
+    ) -> std::io::Result<
+        LineProtocolReader<tokio::io::BufReader<tokio::fs::File>>,
+    > {
+        use tokio::io::AsyncSeekExt;
+
+        let mut file = tokio::fs::File::open(&self.path).await?;
+        let len = file.metadata().await?.len();
+
+        let mut offset = self.checkpoint()?;
+        if offset > len {
+            tracing::warn!(
+                path = ?self.path,
+                offset,
+                len,
+                "replay file shorter than checkpoint, replaying from start"
+            );
+            offset = 0;
+        }
+        file.seek(std::io::SeekFrom::Start(offset)).await?;
+
+        Ok(LineProtocolReader::new(tokio::io::BufReader::new(file))
+            .with_precision(self.precision)
+            .with_offset(offset))
+    }
+
+    async fn next<R: tokio::io::AsyncBufRead + Unpin>(
+        &self,
+        reader: &mut LineProtocolReader<R>,
+        progress: &mut ReplayProgress,
+    ) -> std::io::Result<Option<Metric>> {
+        loop {
+            match reader.next_metric().await {
+                Err(err)
+                    if self.skip_invalid
+                        && err.kind() == std::io::ErrorKind::InvalidData =>
+                {
+                    tracing::warn!(?err, path = ?self.path, "skip invalid line");
+                    progress.invalid_lines += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    fn save(
+        &self,
+        offset: u64,
+        progress: &mut ReplayProgress,
+    ) -> std::io::Result<()> {
+        progress.offset = offset;
+        match &self.checkpoint_path {
+            Some(path) => Checkpoint {
+                offset,
+                file_id: None,
+            }
+            .write(path),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Progress through a file, saved as `{offset}` or `{offset} {file_id}`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Checkpoint {
+    /// The byte offset read up to.
+    pub offset: u64,
+
+    /// Identifies the file the offset is in, to detect rotated files.
+    pub file_id: Option<u64>,
+}
+
+impl Checkpoint {
+    /// Read a checkpoint, the default if the file does not exist.
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        let s = match std::fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self::default())
+            }
+            Err(err) => return Err(err),
+        };
+
+        let invalid = || {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid checkpoint file {path:?}"),
+            )
+        };
+        let mut parts = s.split_whitespace();
+        let offset = parts
+            .next()
+            .and_then(|o| o.parse().ok())
+            .ok_or_else(invalid)?;
+        let file_id = match parts.next() {
+            Some(id) => Some(id.parse().map_err(|_| invalid())?),
+            None => None,
+        };
+        Ok(Self { offset, file_id })
+    }
+
+    /// Atomically replace a checkpoint, synced to disk so a crash
+    /// leaves either the old or the new checkpoint.
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let content = match self.file_id {
+            Some(id) => format!("{} {id}", self.offset),
+            None => self.offset.to_string(),
+        };
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+        std::fs::rename(&tmp, path)?;
+
+        // persist the rename, directories can't be opened elsewhere
+        #[cfg(unix)]
+        {
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            std::fs::File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+/// Send every metric of the Line Protocol file at `path` through
+/// `writer`, see [LineProtocolReplay] for checkpoints and other options.
+pub async fn replay_file<P: Into<PathBuf>>(
+    path: P,
+    writer: &InfluxiveWriter,
+) -> types::BackendResult<ReplayProgress> {
+    LineProtocolReplay::new(path).to_writer(writer).await
+}
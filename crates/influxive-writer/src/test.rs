@@ -34,11 +34,7 @@ impl Backend for TestBackend {
         self.buffer_count
     }
 
-    fn send(
-        &mut self,
-    ) -> std::pin::Pin<
-        Box<dyn std::future::Future<Output = ()> + '_ + Send + Sync>,
-    > {
+    fn send(&mut self) -> BackendFuture<'_, ()> {
         Box::pin(async move {
             // simulate it taking a while to do things
             tokio::time::sleep(std::time::Duration::from_millis(10)).await;
@@ -52,8 +48,14 @@ impl Backend for TestBackend {
                 "@@@ {:0.2} - write",
                 self.test_start.elapsed().as_secs_f64()
             );
+
+            Ok(())
         })
     }
+
+    fn clear(&mut self) {
+        self.buffer_count = 0;
+    }
 }
 
 #[derive(Debug)]
@@ -93,7 +95,7 @@ impl BackendFactory for TestFactory {
 fn create_file_writer(
     temp_dir: &tempfile::TempDir,
 ) -> (std::path::PathBuf, InfluxiveWriter) {
-    std::fs::create_dir_all(&temp_dir).unwrap();
+    std::fs::create_dir_all(temp_dir).unwrap();
     let test_path = temp_dir
         .path()
         .join(std::path::PathBuf::from("test_metrics.influx"));
@@ -135,7 +137,7 @@ async fn writer_file_one() {
     let line = res.unwrap();
     let split = line.split(',').collect::<Vec<&str>>();
     assert_eq!(split[0], "my.metric");
-    assert!(split[1].split(' ').collect::<Vec<&str>>()[1].contains(&"3.77"));
+    assert!(split[1].split(' ').collect::<Vec<&str>>()[1].contains("3.77"));
 }
 
 #[tokio::test(flavor = "multi_thread")]
@@ -268,6 +270,7 @@ async fn writer_stress() {
         batch_duration: std::time::Duration::from_millis(30),
         batch_buffer_size: 10,
         backend: factory.clone(),
+        ..Default::default()
     };
 
     let writer = InfluxiveWriter::with_token_auth(config, "", "", "");
@@ -330,3 +333,3125 @@ async fn writer_stress() {
 
     assert!(factory.get_write_count() < 250);
 }
+
+/// Read lines from accepted socket connections until `count` lines have
+/// arrived, closing each connection after `per_conn` lines.
+async fn read_socket_lines<S, F, Fut>(
+    mut accept: F,
+    count: usize,
+    per_conn: usize,
+) -> Vec<String>
+where
+    S: tokio::io::AsyncRead + Unpin,
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = S>,
+{
+    use tokio::io::AsyncBufReadExt;
+
+    let mut out = Vec::new();
+    while out.len() < count {
+        let stream = accept().await;
+        let mut lines = tokio::io::BufReader::new(stream).lines();
+        let mut conn_count = 0;
+        while let Some(line) = lines.next_line().await.unwrap() {
+            out.push(line);
+            conn_count += 1;
+            if conn_count >= per_conn || out.len() >= count {
+                break;
+            }
+        }
+    }
+    out
+}
+
+fn create_socket_writer(
+    factory: LineProtocolSocketBackendFactory,
+) -> InfluxiveWriter {
+    let config = InfluxiveWriterConfig::default()
+        .with_batch_duration(std::time::Duration::from_millis(30))
+        .with_backend(Arc::new(
+            factory
+                .with_reconnect_interval(std::time::Duration::from_millis(10)),
+        ));
+    InfluxiveWriter::with_token_auth(config, "", "", "")
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_tcp_reconnect() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+
+    let writer = create_socket_writer(LineProtocolSocketBackendFactory::new(
+        LineProtocolSocketAddr::Tcp(addr),
+    ));
+
+    let reader = tokio::task::spawn(async move {
+        // drop the connection after every 2 lines to force reconnects
+        read_socket_lines(|| async { listener.accept().await.unwrap().0 }, 6, 2)
+            .await
+    });
+
+    for n in 0..6 {
+        writer.write_metric(
+            Metric::new(std::time::SystemTime::now(), "my.metric")
+                .with_field("val", n)
+                .with_tag("tag", "test-tag"),
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(60)).await;
+    }
+
+    let lines = tokio::time::timeout(std::time::Duration::from_secs(5), reader)
+        .await
+        .unwrap()
+        .unwrap();
+
+    for (n, line) in lines.iter().enumerate() {
+        assert!(line.starts_with("my.metric,tag=test-tag "), "{line}");
+        assert!(line.contains(&format!("val={n}i")), "{line}");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_tcp_buffers_while_disconnected() {
+    // reserve a port, but don't listen on it yet
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+
+    let writer = create_socket_writer(LineProtocolSocketBackendFactory::new(
+        LineProtocolSocketAddr::Tcp(addr.to_string()),
+    ));
+
+    for n in 0..3 {
+        writer.write_metric(
+            Metric::new(std::time::SystemTime::now(), "my.metric")
+                .with_field("val", n),
+        );
+    }
+
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    let lines = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        read_socket_lines(
+            || async { listener.accept().await.unwrap().0 },
+            3,
+            usize::MAX,
+        ),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(3, lines.len());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_tcp_reports_send_errors() {
+    // reserve a port, but don't listen on it
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+
+    let writer = InfluxiveWriter::with_token_auth(
+        InfluxiveWriterConfig::default()
+            .with_max_retries(1)
+            .with_retry_backoff(std::time::Duration::from_millis(1))
+            .with_backend(Arc::new(
+                LineProtocolSocketBackendFactory::new(
+                    LineProtocolSocketAddr::Tcp(addr.to_string()),
+                )
+                .with_reconnect_interval(std::time::Duration::from_millis(1)),
+            )),
+        "",
+        "",
+        "",
+    );
+    let connectivity = writer.connectivity();
+
+    writer.write_metric(
+        Metric::new(std::time::SystemTime::now(), "my.metric")
+            .with_field("val", 1),
+    );
+    assert!(matches!(writer.flush().await, Err(BackendError::Io(_))));
+
+    let stats = writer.stats();
+    assert_eq!(2, stats.send_errors);
+    assert_eq!(1, stats.retries);
+    assert_eq!(0, stats.metrics_sent);
+    assert_eq!(1, stats.metrics_dropped);
+    assert_eq!(
+        InfluxiveWriterConnectivity::Disconnected,
+        *connectivity.borrow()
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_unix_socket() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("telegraf.sock");
+    let listener = tokio::net::UnixListener::bind(&path).unwrap();
+
+    let writer = create_socket_writer(LineProtocolSocketBackendFactory::new(
+        LineProtocolSocketAddr::Unix(path),
+    ));
+
+    for n in 0..4 {
+        writer.write_metric(
+            Metric::new(std::time::SystemTime::UNIX_EPOCH, "my.metric")
+                .with_field("val", n),
+        );
+    }
+
+    let lines = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        read_socket_lines(
+            || async { listener.accept().await.unwrap().0 },
+            4,
+            usize::MAX,
+        ),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!("my.metric val=0i 0", lines[0]);
+    assert_eq!("my.metric val=3i 0", lines[3]);
+}
+
+/// A request captured by [http_stub].
+struct StubRequest {
+    head: String,
+    body: Vec<u8>,
+}
+
+/// Minimal HTTP/1.1 server answering every request with the current
+/// `status`, forwarding the captured requests to the returned receiver.
+async fn http_stub(
+    status: Arc<std::sync::atomic::AtomicU16>,
+) -> (
+    std::net::SocketAddr,
+    tokio::sync::mpsc::UnboundedReceiver<StubRequest>,
+) {
+    http_stub_gated(status, None).await
+}
+
+/// Like [http_stub], but requests whose head contains the gate's pattern
+/// are only answered once a permit of its semaphore is available.
+async fn http_stub_gated(
+    status: Arc<std::sync::atomic::AtomicU16>,
+    gate: Option<(&'static str, Arc<tokio::sync::Semaphore>)>,
+) -> (
+    std::net::SocketAddr,
+    tokio::sync::mpsc::UnboundedReceiver<StubRequest>,
+) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (send, recv) = tokio::sync::mpsc::unbounded_channel();
+
+    tokio::task::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let send = send.clone();
+            let status = status.clone();
+            let gate = gate.clone();
+            tokio::task::spawn(async move {
+                loop {
+                    let mut buf = Vec::new();
+                    let head_end = loop {
+                        if let Some(pos) =
+                            buf.windows(4).position(|w| w == b"\r\n\r\n")
+                        {
+                            break pos + 4;
+                        }
+                        let mut chunk = [0; 4096];
+                        match socket.read(&mut chunk).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                        }
+                    };
+                    let head =
+                        String::from_utf8_lossy(&buf[..head_end]).to_string();
+                    let len = head
+                        .lines()
+                        .find_map(|l| {
+                            let (k, v) = l.split_once(':')?;
+                            if k.eq_ignore_ascii_case("content-length") {
+                                v.trim().parse::<usize>().ok()
+                            } else {
+                                None
+                            }
+                        })
+                        .unwrap_or(0);
+                    let mut body = buf[head_end..].to_vec();
+                    while body.len() < len {
+                        let mut chunk = [0; 4096];
+                        match socket.read(&mut chunk).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => body.extend_from_slice(&chunk[..n]),
+                        }
+                    }
+                    // answer with the status set before the request
+                    // was forwarded, tests change it once they see it
+                    let status =
+                        status.load(std::sync::atomic::Ordering::SeqCst);
+                    let held = match &gate {
+                        Some((pattern, permits)) if head.contains(pattern) => {
+                            Some(permits.clone())
+                        }
+                        _ => None,
+                    };
+                    let _ = send.send(StubRequest { head, body });
+                    if let Some(permits) = held {
+                        permits.acquire().await.unwrap().forget();
+                    }
+                    let response = format!(
+                        "HTTP/1.1 {status} Stub\r\nX-Influxdb-Version: stub\r\nContent-Length: 0\r\n\r\n"
+                    );
+                    if socket.write_all(response.as_bytes()).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+
+    (addr, recv)
+}
+
+#[cfg(feature = "otlp")]
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_otlp_http() {
+    use otlp::proto::{any_value, number_data_point};
+    use prost::Message;
+
+    let (addr, mut recv) = http_stub(Arc::new(200.into())).await;
+
+    let config = InfluxiveWriterConfig::default()
+        .with_batch_duration(std::time::Duration::from_millis(30))
+        .with_backend(Arc::new(
+            OtlpHttpBackendFactory::new(format!("http://{addr}/v1/metrics"))
+                .with_header("Authorization", "Bearer my.token")
+                .with_resource_attribute("service.name", "my.service"),
+        ));
+    let writer = InfluxiveWriter::with_token_auth(config, "", "", "");
+
+    for n in 0..3 {
+        writer.write_metric(
+            Metric::new(std::time::SystemTime::UNIX_EPOCH, "my.metric")
+                .with_field("value", n as f64)
+                .with_field("count", n)
+                .with_field("note", "skipped")
+                .with_tag("tag", "test-tag"),
+        );
+    }
+
+    let req =
+        tokio::time::timeout(std::time::Duration::from_secs(5), recv.recv())
+            .await
+            .unwrap()
+            .unwrap();
+
+    assert!(req.head.starts_with("POST /v1/metrics HTTP/1.1\r\n"));
+    let head = req.head.to_ascii_lowercase();
+    assert!(head.contains("content-type: application/x-protobuf"));
+    assert!(head.contains("authorization: bearer my.token"));
+
+    let req = otlp::proto::ExportMetricsServiceRequest::decode(&req.body[..])
+        .unwrap();
+    let rm = &req.resource_metrics[0];
+    let resource_attr = &rm.resource.as_ref().unwrap().attributes[0];
+    assert_eq!("service.name", resource_attr.key);
+    assert_eq!(
+        Some(any_value::Value::StringValue("my.service".into())),
+        resource_attr.value.as_ref().unwrap().value
+    );
+
+    let metrics = &rm.scope_metrics[0].metrics;
+    assert_eq!(2, metrics.len());
+    assert_eq!("my.metric", metrics[0].name);
+    assert_eq!("my.metric.count", metrics[1].name);
+
+    let points = &metrics[0].gauge.as_ref().unwrap().data_points;
+    assert_eq!(3, points.len());
+    assert_eq!(0, points[0].time_unix_nano);
+    assert_eq!("tag", points[0].attributes[0].key);
+    assert_eq!(
+        Some(number_data_point::Value::AsDouble(2.0)),
+        points[2].value
+    );
+
+    let points = &metrics[1].gauge.as_ref().unwrap().data_points;
+    assert_eq!(Some(number_data_point::Value::AsInt(2)), points[2].value);
+}
+
+#[test]
+fn graphite_format() {
+    let metric = || {
+        Metric::new(
+            std::time::SystemTime::UNIX_EPOCH
+                + std::time::Duration::from_millis(1_700_000_000_500),
+            "my.metric",
+        )
+        .with_field("value", 3.5)
+        .with_field("count", 2)
+        .with_field("note", "skipped")
+        .with_field("nan", f64::NAN)
+        .with_field("inf", f64::INFINITY)
+        .with_tag("host", "a.b")
+        .with_tag("zone", "z 1")
+    };
+
+    let mut lines = Vec::new();
+    graphite::metric_to_graphite(
+        Some("app"),
+        GraphiteTagMode::Tagged,
+        metric(),
+        &mut lines,
+    );
+    graphite::metric_to_graphite(
+        None,
+        GraphiteTagMode::Path,
+        metric(),
+        &mut lines,
+    );
+    graphite::metric_to_graphite(
+        None,
+        GraphiteTagMode::Drop,
+        metric(),
+        &mut lines,
+    );
+
+    assert_eq!(
+        vec![
+            "app.my.metric;host=a.b;zone=z_1 3.5 1700000000\n",
+            "app.my.metric.count;host=a.b;zone=z_1 2 1700000000\n",
+            "a_b.z_1.my.metric 3.5 1700000000\n",
+            "a_b.z_1.my.metric.count 2 1700000000\n",
+            "my.metric 3.5 1700000000\n",
+            "my.metric.count 2 1700000000\n",
+        ],
+        lines
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_graphite() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+
+    let config = InfluxiveWriterConfig::default()
+        .with_batch_duration(std::time::Duration::from_millis(30))
+        .with_backend(Arc::new(
+            GraphiteBackendFactory::new(addr)
+                .with_prefix("svc")
+                .with_tag_mode(GraphiteTagMode::Path),
+        ));
+    let writer = InfluxiveWriter::with_token_auth(config, "", "", "");
+
+    writer.write_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "my.metric")
+            .with_field("value", true)
+            .with_tag("host", "h1"),
+    );
+
+    let lines = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        read_socket_lines(
+            || async { listener.accept().await.unwrap().0 },
+            1,
+            usize::MAX,
+        ),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(vec!["svc.h1.my.metric 1 0"], lines);
+}
+
+#[test]
+fn statsd_format() {
+    let metric = || {
+        Metric::new(std::time::SystemTime::now(), "my.metric")
+            .with_field("value", -1.5)
+            .with_field("count", 7u64)
+            .with_field("delta", -5i64)
+            .with_field("note", "skipped")
+            .with_field("nan", f64::NAN)
+            .with_field("inf", f64::NEG_INFINITY)
+            .with_tag("host", "a:b")
+            .with_tag("zone", "z")
+    };
+
+    let mut lines = Vec::new();
+    statsd::metric_to_statsd(
+        Some("app"),
+        StatsdFlavor::DogStatsd,
+        metric(),
+        &mut lines,
+    );
+    statsd::metric_to_statsd(None, StatsdFlavor::Statsd, metric(), &mut lines);
+
+    assert_eq!(
+        vec![
+            "app.my.metric:-1.5|g|#host:a_b,zone:z",
+            "app.my.metric.count:7|g|#host:a_b,zone:z",
+            "app.my.metric.delta:-5|g|#host:a_b,zone:z",
+            // negative gauges are set to zero first, not decremented
+            "my.metric:0|g\nmy.metric:-1.5|g",
+            "my.metric.count:7|g",
+            "my.metric.delta:0|g\nmy.metric.delta:-5|g",
+        ],
+        lines
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_statsd_packs_datagrams() {
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap().to_string();
+
+    let config = InfluxiveWriterConfig::default()
+        .with_batch_duration(std::time::Duration::from_millis(30))
+        .with_backend(Arc::new(
+            StatsdBackendFactory::new(addr)
+                .with_flavor(StatsdFlavor::Statsd)
+                // "m.nn:n|g" is 8 bytes, so 3 lines + 2 newlines fit in 30
+                .with_max_packet_size(30),
+        ));
+    let writer = InfluxiveWriter::with_token_auth(config, "", "", "");
+
+    for n in 10..16 {
+        writer.write_metric(
+            Metric::new(std::time::SystemTime::now(), format!("m.{n}"))
+                .with_field("value", 1),
+        );
+    }
+
+    let mut packets = Vec::new();
+    let mut buf = [0; 1500];
+    while packets.len() < 2 {
+        let len = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            socket.recv(&mut buf),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        packets.push(String::from_utf8_lossy(&buf[..len]).to_string());
+    }
+
+    assert_eq!(
+        vec![
+            "m.10:1|g\nm.11:1|g\nm.12:1|g",
+            "m.13:1|g\nm.14:1|g\nm.15:1|g"
+        ],
+        packets
+    );
+}
+
+fn create_format_writer(
+    path: std::path::PathBuf,
+    format: FileFormat,
+) -> InfluxiveWriter {
+    InfluxiveWriter::with_token_auth(
+        InfluxiveWriterConfig::create_with_file(path, format)
+            .with_batch_duration(std::time::Duration::from_millis(30)),
+        "",
+        "",
+        "",
+    )
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_file_json_lines() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("metrics.jsonl");
+    let writer = create_format_writer(path.clone(), FileFormat::JsonLines);
+
+    writer.write_metric(
+        Metric::new(
+            std::time::SystemTime::UNIX_EPOCH
+                + std::time::Duration::from_nanos(1_700_000_000_000_000_001),
+            "my.metric",
+        )
+        .with_field("value", 3.5)
+        .with_field("nan", f64::NAN)
+        .with_field("count", 2u64)
+        .with_field("ok", true)
+        .with_field("note", "a \"quoted\"\nline")
+        .with_tag("host", "h1"),
+    );
+
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    assert_eq!(
+        concat!(
+            r#"{"measurement":"my.metric","time":1700000000000000001,"#,
+            r#""tags":{"host":"h1"},"fields":{"value":3.5,"nan":null,"#,
+            r#""count":2,"ok":true,"note":"a \"quoted\"\nline"}}"#,
+            "\n",
+        ),
+        std::fs::read_to_string(&path).unwrap()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_file_csv() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let dir = temp_dir.path().join("csv");
+    let writer = create_format_writer(dir.clone(), FileFormat::Csv);
+
+    let ts = |n| {
+        std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_nanos(n)
+    };
+
+    writer.write_metric(
+        Metric::new(ts(1), "cpu")
+            .with_field("value", 1.5)
+            .with_field("idle", 2)
+            .with_tag("host", "a,b"),
+    );
+    // same columns in a different order land in the same file
+    writer.write_metric(
+        Metric::new(ts(2), "cpu")
+            .with_tag("host", "c")
+            .with_field("idle", 3)
+            .with_field("value", 2.5),
+    );
+    writer.write_metric(Metric::new(ts(3), "mem").with_field("value", 7));
+
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    // a new column starts a new file with a widened header
+    writer.write_metric(
+        Metric::new(ts(4), "cpu")
+            .with_field("value", 3.5)
+            .with_tag("zone", "z"),
+    );
+    // the old shape now lands in the widened file
+    writer.write_metric(
+        Metric::new(ts(5), "cpu")
+            .with_field("value", 4.5)
+            .with_field("idle", 4)
+            .with_tag("host", "d"),
+    );
+
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    assert_eq!(
+        "time,host,idle,value\n1,\"a,b\",2,1.5\n2,c,3,2.5\n",
+        std::fs::read_to_string(dir.join("cpu.csv")).unwrap()
+    );
+    assert_eq!(
+        "time,host,idle,value,zone\n4,,,3.5,z\n5,d,4,4.5,\n",
+        std::fs::read_to_string(dir.join("cpu.1.csv")).unwrap()
+    );
+    assert_eq!(
+        "time,value\n3,7\n",
+        std::fs::read_to_string(dir.join("mem.csv")).unwrap()
+    );
+
+    // a new writer appends to the file whose header fits
+    drop(writer);
+    let writer = create_format_writer(dir.clone(), FileFormat::Csv);
+    writer.write_metric(Metric::new(ts(6), "mem").with_field("value", 8));
+
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    assert_eq!(
+        "time,value\n3,7\n6,8\n",
+        std::fs::read_to_string(dir.join("mem.csv")).unwrap()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_file_csv_tag_and_field_with_same_key() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let dir = temp_dir.path().join("csv");
+    let writer = create_format_writer(dir.clone(), FileFormat::Csv);
+
+    writer.write_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "cpu")
+            .with_field("host", 1)
+            .with_tag("host", "a"),
+    );
+    writer.flush().await.unwrap();
+
+    assert_eq!(
+        "time,host,host_field\n0,a,1\n",
+        std::fs::read_to_string(dir.join("cpu.csv")).unwrap()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_file_csv_tag_named_time() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let dir = temp_dir.path().join("csv");
+    let writer = create_format_writer(dir.clone(), FileFormat::Csv);
+
+    writer.write_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "cpu")
+            .with_tag("time", "noon")
+            .with_tag("host", "a")
+            .with_tag("host", "b")
+            .with_field("time", 1),
+    );
+    writer.flush().await.unwrap();
+
+    assert_eq!(
+        "time,host,host_tag,time_tag,time_field\n0,a,b,noon,1\n",
+        std::fs::read_to_string(dir.join("cpu.csv")).unwrap()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_file_recovers_from_open_error() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let dir = temp_dir.path().join("missing");
+    let path = dir.join("metrics.jsonl");
+    let writer = create_format_writer(path.clone(), FileFormat::JsonLines);
+
+    writer.write_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+            .with_field("value", 1),
+    );
+    assert!(writer.flush().await.is_err());
+
+    std::fs::create_dir(&dir).unwrap();
+    writer.write_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+            .with_field("value", 2),
+    );
+    writer.flush().await.unwrap();
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert!(content.contains(r#""fields":{"value":2}"#), "{content}");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn file_backend_keeps_batch_on_failed_send() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let dir = temp_dir.path().join("missing");
+    let path = dir.join("metrics.influx");
+    let mut backend = LineProtocolFileBackendFactory::new(path.clone())
+        .with_token_auth(String::new(), String::new(), String::new());
+
+    backend.buffer_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+            .with_field("value", 1),
+    );
+    assert!(backend.send().await.is_err());
+    assert_eq!(1, backend.buffer_count());
+
+    std::fs::create_dir(&dir).unwrap();
+    backend.send().await.unwrap();
+    assert_eq!(0, backend.buffer_count());
+    assert_eq!("m value=1i 0\n", std::fs::read_to_string(&path).unwrap());
+}
+
+#[cfg(feature = "parquet")]
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_file_parquet_readable_after_flush() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let dir = temp_dir.path().join("parquet");
+    let writer = create_format_writer(
+        dir.clone(),
+        FileFormat::Parquet {
+            window: std::time::Duration::from_secs(60),
+        },
+    );
+
+    let ts = |s| {
+        std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(s)
+    };
+    let rows = |name: &str| {
+        let file = std::fs::File::open(dir.join("cpu").join(name)).unwrap();
+        parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder::try_new(
+            file,
+        )
+        .unwrap()
+        .build()
+        .unwrap()
+        .map(|b| b.unwrap().num_rows())
+        .sum::<usize>()
+    };
+
+    writer.write_metric(Metric::new(ts(60), "cpu").with_field("value", 1.5));
+    writer.flush().await.unwrap();
+    assert_eq!(1, rows("60.parquet"));
+
+    // the same window continues in a new file
+    writer.write_metric(Metric::new(ts(61), "cpu").with_field("value", 2.5));
+    writer.flush().await.unwrap();
+    assert_eq!(1, rows("60-1.parquet"));
+}
+
+#[cfg(feature = "parquet")]
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_file_parquet() {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Float64Type, TimestampNanosecondType};
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let dir = temp_dir.path().join("parquet");
+    let writer = create_format_writer(
+        dir.clone(),
+        FileFormat::Parquet {
+            window: std::time::Duration::from_secs(60),
+        },
+    );
+
+    let ts = |s| {
+        std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(s)
+    };
+
+    writer.write_metric(
+        Metric::new(ts(60), "cpu")
+            .with_field("value", 1.5)
+            .with_tag("host", "a"),
+    );
+    writer.write_metric(
+        Metric::new(ts(61), "cpu")
+            .with_field("value", 2.5)
+            .with_tag("host", "b"),
+    );
+    // a later window rolls the file
+    writer.write_metric(Metric::new(ts(120), "cpu").with_field("value", 3.5));
+
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    // dropping the writer closes the open files
+    drop(writer);
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let read = |name: &str| {
+        let file = std::fs::File::open(dir.join("cpu").join(name)).unwrap();
+        parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder::try_new(
+            file,
+        )
+        .unwrap()
+        .build()
+        .unwrap()
+        .map(|b| b.unwrap())
+        .collect::<Vec<_>>()
+    };
+
+    let batches = read("60.parquet");
+    assert_eq!(1, batches.len());
+    let batch = &batches[0];
+    assert_eq!(
+        vec!["time", "host", "value"],
+        batch
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| f.name().as_str())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        &[60_000_000_000, 61_000_000_000],
+        batch
+            .column(0)
+            .as_primitive::<TimestampNanosecondType>()
+            .values()
+    );
+    assert_eq!(
+        vec![Some("a"), Some("b")],
+        batch
+            .column(1)
+            .as_string::<i32>()
+            .iter()
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        &[1.5, 2.5],
+        batch.column(2).as_primitive::<Float64Type>().values()
+    );
+
+    let batches = read("120.parquet");
+    assert_eq!(
+        &[3.5],
+        batches[0].column(1).as_primitive::<Float64Type>().values()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_routing() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let file_writer = |name: &str| {
+        let path = temp_dir.path().join(name);
+        let writer = InfluxiveWriter::with_token_auth(
+            InfluxiveWriterConfig::create_with_influx_file(path.clone())
+                .with_batch_duration(std::time::Duration::from_millis(30)),
+            "",
+            "",
+            "",
+        );
+        (path, Arc::new(writer))
+    };
+
+    let (app_path, app) = file_writer("app.influx");
+    let (debug_path, debug) = file_writer("debug.influx");
+    let (other_path, other) = file_writer("other.influx");
+
+    let router = RoutingMetricWriter::new()
+        .with_prefix_route("app.", app)
+        .with_route(
+            Arc::new(|metric: &Metric| {
+                metric.tags.iter().any(|(k, v)| {
+                    k.as_str() == "level" && v.to_string() == "debug"
+                })
+            }),
+            debug,
+        )
+        .with_default_route(other);
+
+    let ts = std::time::SystemTime::UNIX_EPOCH;
+    router.write_metric(Metric::new(ts, "app.a").with_field("value", 1));
+    router.write_metric(
+        Metric::new(ts, "infra.b")
+            .with_field("value", 2)
+            .with_tag("level", "debug"),
+    );
+    router.write_metric(Metric::new(ts, "infra.c").with_field("value", 3));
+    router.write_metric(Metric::new(ts, "app.d").with_field("value", 4));
+
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let read = |path| std::fs::read_to_string(path).unwrap();
+    assert_eq!("app.a value=1i 0\napp.d value=4i 0\n", read(app_path));
+    assert_eq!("infra.b,level=debug value=2i 0\n", read(debug_path));
+    assert_eq!("infra.c value=3i 0\n", read(other_path));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_failover_to_file_and_back() {
+    let status = Arc::new(std::sync::atomic::AtomicU16::new(500));
+    let (addr, mut recv) = http_stub(status.clone()).await;
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("failover.influx");
+
+    let config = InfluxiveWriterConfig::create_with_failover(
+        FailoverBackendFactory::new_with_secondary_backend(Arc::new(
+            LineProtocolFileBackendFactory::new(path.clone()),
+        ))
+        .with_failure_threshold(2)
+        .with_probe_interval(std::time::Duration::from_millis(20)),
+    )
+    .with_batch_duration(std::time::Duration::from_millis(10));
+    let writer = InfluxiveWriter::with_token_auth(
+        config,
+        format!("http://{addr}"),
+        "my.bucket",
+        "my.token",
+    );
+
+    let write = |n: i64| {
+        writer.write_metric(
+            Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+                .with_field("value", n),
+        );
+    };
+    let settle = || tokio::time::sleep(std::time::Duration::from_millis(80));
+
+    // two failed writes switch over, each failed batch lands in the file
+    write(1);
+    settle().await;
+    write(2);
+    settle().await;
+    // switched over, the primary is only probed
+    write(3);
+    settle().await;
+
+    assert_eq!(
+        "m value=1i 0\nm value=2i 0\nm value=3i 0\n",
+        std::fs::read_to_string(&path).unwrap()
+    );
+    let health = writer.health().await;
+    assert!(health.reachable);
+    assert!(health.degraded);
+
+    status.store(204, std::sync::atomic::Ordering::SeqCst);
+    settle().await;
+    write(4);
+    settle().await;
+
+    let health = writer.health().await;
+    assert!(health.reachable);
+    assert!(!health.degraded);
+
+    assert_eq!(
+        "m value=1i 0\nm value=2i 0\nm value=3i 0\n",
+        std::fs::read_to_string(&path).unwrap()
+    );
+
+    let mut writes = Vec::new();
+    let mut pings = 0;
+    while let Ok(req) = recv.try_recv() {
+        if req
+            .head
+            .starts_with("POST /write?db=my.bucket&precision=ns ")
+        {
+            assert!(req.head.contains("authorization: Token my.token"));
+            writes.push(String::from_utf8(req.body).unwrap());
+        } else if req.head.starts_with("GET /ping ") {
+            pings += 1;
+        } else {
+            panic!("unexpected request: {}", req.head);
+        }
+    }
+    assert_eq!(
+        vec!["m value=1i 0\n", "m value=2i 0\n", "m value=4i 0\n"],
+        writes
+    );
+    assert!(pings > 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_failover_from_unresponsive_primary() {
+    // accepts connections, but never answers
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::task::spawn(async move {
+        let mut sockets = Vec::new();
+        loop {
+            sockets.push(listener.accept().await.unwrap().0);
+        }
+    });
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("failover.influx");
+
+    let config = InfluxiveWriterConfig::create_with_failover(
+        FailoverBackendFactory::new_with_secondary_backend(Arc::new(
+            LineProtocolFileBackendFactory::new(path.clone()),
+        ))
+        .with_failure_threshold(1)
+        .with_request_timeout(std::time::Duration::from_millis(100)),
+    );
+    let writer = InfluxiveWriter::with_token_auth(
+        config,
+        format!("http://{addr}"),
+        "my.bucket",
+        "my.token",
+    );
+
+    // the health check doesn't wait for the primary forever
+    let health = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        writer.health(),
+    )
+    .await
+    .unwrap();
+    assert!(health.reachable);
+    assert!(health.degraded);
+
+    writer.write_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+            .with_field("value", 1),
+    );
+    tokio::time::timeout(std::time::Duration::from_secs(5), writer.flush())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!("m value=1i 0\n", std::fs::read_to_string(&path).unwrap());
+
+    server.abort();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_failover_ignores_rejected_batches() {
+    let status = Arc::new(std::sync::atomic::AtomicU16::new(400));
+    let (addr, mut recv) = http_stub(status.clone()).await;
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("failover.influx");
+
+    let config = InfluxiveWriterConfig::create_with_failover(
+        FailoverBackendFactory::new_with_secondary_backend(Arc::new(
+            LineProtocolFileBackendFactory::new(path.clone()),
+        ))
+        .with_failure_threshold(1)
+        .with_probe_interval(std::time::Duration::from_secs(60)),
+    )
+    .with_batch_duration(std::time::Duration::from_millis(10));
+    let writer = InfluxiveWriter::with_token_auth(
+        config,
+        format!("http://{addr}"),
+        "my.bucket",
+        "my.token",
+    );
+
+    let write = |n: i64| {
+        writer.write_metric(
+            Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+                .with_field("value", n),
+        );
+    };
+
+    // a bad batch is dropped, and neither lands in the secondary
+    // nor switches away from the primary
+    write(1);
+    assert!(writer.flush().await.is_err());
+    status.store(204, std::sync::atomic::Ordering::SeqCst);
+    write(2);
+    writer.flush().await.unwrap();
+
+    assert_eq!("", std::fs::read_to_string(&path).unwrap());
+    assert!(!writer.health().await.degraded);
+
+    let mut writes = Vec::new();
+    while let Ok(req) = recv.try_recv() {
+        if req.head.starts_with("POST /write") {
+            writes.push(String::from_utf8(req.body).unwrap());
+        }
+    }
+    assert_eq!(vec!["m value=1i 0\n", "m value=2i 0\n"], writes);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_failover_token_provider_on_unauthorized() {
+    let status = Arc::new(std::sync::atomic::AtomicU16::new(401));
+    let (addr, mut recv) = http_stub(status.clone()).await;
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("failover.influx");
+
+    let provider_status = status.clone();
+    let config = InfluxiveWriterConfig::create_with_failover(
+        FailoverBackendFactory::new_with_secondary_backend(Arc::new(
+            LineProtocolFileBackendFactory::new(path.clone()),
+        ))
+        .with_failure_threshold(1),
+    )
+    .with_max_retries(0)
+    .with_token_provider(TokenProvider::new(move || {
+        provider_status.store(204, std::sync::atomic::Ordering::SeqCst);
+        Box::pin(async move { Ok("new.token".to_string()) })
+    }));
+    let writer = InfluxiveWriter::with_token_auth(
+        config,
+        format!("http://{addr}"),
+        "my.bucket",
+        "old.token",
+    );
+
+    writer.write_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+            .with_field("value", 1),
+    );
+    writer.flush().await.unwrap();
+
+    let first = recv.recv().await.unwrap();
+    assert!(first.head.contains("Token old.token"), "{}", first.head);
+    let second = recv.recv().await.unwrap();
+    assert!(second.head.contains("Token new.token"), "{}", second.head);
+    assert_eq!(first.body, second.body);
+    assert_eq!("", std::fs::read_to_string(&path).unwrap());
+    assert!(!writer.health().await.degraded);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_failover_probe_follows_endpoint() {
+    let (old_addr, _old_recv) =
+        http_stub(Arc::new(std::sync::atomic::AtomicU16::new(500))).await;
+    let (new_addr, mut new_recv) =
+        http_stub(Arc::new(std::sync::atomic::AtomicU16::new(204))).await;
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("failover.influx");
+
+    let config = InfluxiveWriterConfig::create_with_failover(
+        FailoverBackendFactory::new_with_secondary_backend(Arc::new(
+            LineProtocolFileBackendFactory::new(path.clone()),
+        ))
+        .with_failure_threshold(1)
+        .with_probe_interval(std::time::Duration::from_millis(20)),
+    )
+    .with_max_retries(0);
+    let writer = InfluxiveWriter::with_token_auth(
+        config,
+        format!("http://{old_addr}"),
+        "my.bucket",
+        "old.token",
+    );
+
+    let write = |n: i64| {
+        writer.write_metric(
+            Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+                .with_field("value", n),
+        );
+    };
+
+    // the old primary fails, switching over
+    write(1);
+    writer.flush().await.unwrap();
+    assert!(writer.health().await.degraded);
+
+    // the probe finds the new primary, switching back
+    writer
+        .handle()
+        .set_token_auth(format!("http://{new_addr}"), "my.bucket", "new.token")
+        .await
+        .unwrap();
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while writer.health().await.degraded {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    write(2);
+    writer.flush().await.unwrap();
+    assert_eq!("m value=1i 0\n", std::fs::read_to_string(&path).unwrap());
+    loop {
+        let req = new_recv.recv().await.unwrap();
+        if req.head.starts_with("POST /write") {
+            assert!(req.head.contains("Token new.token"), "{}", req.head);
+            assert_eq!(b"m value=2i 0\n".as_slice(), req.body.as_slice());
+            break;
+        }
+    }
+}
+
+#[test]
+fn writer_without_runtime() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("sync.influx");
+
+    // no tokio runtime here, the writer runs on its own thread
+    let writer = InfluxiveWriter::with_token_auth(
+        InfluxiveWriterConfig::create_with_influx_file(path.clone())
+            .with_batch_duration(std::time::Duration::from_secs(60)),
+        "",
+        "",
+        "",
+    );
+
+    for n in 0..3 {
+        writer.write_metric(
+            Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+                .with_field("value", n),
+        );
+    }
+
+    // dropping the writer blocks until the buffered metrics are written
+    drop(writer);
+
+    assert_eq!(
+        "m value=0i 0\nm value=1i 0\nm value=2i 0\n",
+        std::fs::read_to_string(&path).unwrap()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_flushes_on_drop() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("drop.influx");
+
+    let writer = InfluxiveWriter::with_token_auth(
+        InfluxiveWriterConfig::create_with_influx_file(path.clone())
+            .with_batch_duration(std::time::Duration::from_secs(60)),
+        "",
+        "",
+        "",
+    );
+
+    writer.write_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+            .with_field("value", 1),
+    );
+    drop(writer);
+
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    assert_eq!("m value=1i 0\n", std::fs::read_to_string(&path).unwrap());
+}
+
+fn create_http_writer(
+    addr: std::net::SocketAddr,
+    max_retries: usize,
+) -> InfluxiveWriter {
+    InfluxiveWriter::with_token_auth(
+        InfluxiveWriterConfig::default()
+            .with_batch_duration(std::time::Duration::from_millis(10))
+            .with_max_retries(max_retries)
+            .with_retry_backoff(std::time::Duration::from_millis(20)),
+        format!("http://{addr}"),
+        "my.bucket",
+        "my.token",
+    )
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_retries_until_success() {
+    let status = Arc::new(std::sync::atomic::AtomicU16::new(503));
+    let (addr, mut recv) = http_stub(status.clone()).await;
+    let writer = create_http_writer(addr, 3);
+
+    writer.write_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+            .with_field("value", 1),
+    );
+
+    let first = recv.recv().await.unwrap();
+    status.store(204, std::sync::atomic::Ordering::SeqCst);
+    let second = recv.recv().await.unwrap();
+    assert_eq!(first.body, second.body);
+    assert_eq!(b"m value=1i 0\n".as_slice(), second.body.as_slice());
+
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+    let stats = writer.stats();
+    assert_eq!(1, stats.metrics_sent);
+    assert_eq!(1, stats.batches_sent);
+    assert_eq!(1, stats.send_errors);
+    assert_eq!(1, stats.retries);
+    assert_eq!(0, stats.metrics_dropped);
+    assert!(stats.last_success.is_some());
+    assert!(stats.last_error.unwrap().contains("503"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_drops_after_retries() {
+    let status = Arc::new(std::sync::atomic::AtomicU16::new(500));
+    let (addr, mut recv) = http_stub(status.clone()).await;
+    let writer = create_http_writer(addr, 2);
+
+    writer.write_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+            .with_field("value", 1),
+    );
+
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    let mut requests = 0;
+    while recv.try_recv().is_ok() {
+        requests += 1;
+    }
+    // the first attempt and two retries
+    assert_eq!(3, requests);
+
+    let stats = writer.stats();
+    assert_eq!(0, stats.metrics_sent);
+    assert_eq!(3, stats.send_errors);
+    assert_eq!(2, stats.retries);
+    assert_eq!(1, stats.metrics_dropped);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_does_not_retry_rejected_writes() {
+    let status = Arc::new(std::sync::atomic::AtomicU16::new(400));
+    let (addr, _recv) = http_stub(status.clone()).await;
+    let writer = create_http_writer(addr, 3);
+
+    writer.write_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+            .with_field("value", 1),
+    );
+
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let stats = writer.stats();
+    assert_eq!(1, stats.send_errors);
+    assert_eq!(0, stats.retries);
+    assert_eq!(1, stats.metrics_dropped);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_health_and_connectivity() {
+    let status = Arc::new(std::sync::atomic::AtomicU16::new(204));
+    let (addr, mut recv) = http_stub(status.clone()).await;
+    let writer = create_http_writer(addr, 0);
+    let mut connectivity = writer.connectivity();
+    assert_eq!(InfluxiveWriterConnectivity::Unknown, *connectivity.borrow());
+
+    let health = writer.health().await;
+    assert!(health.reachable);
+    assert_eq!(Some("stub"), health.version.as_deref());
+    assert!(health.last_success.is_none());
+    assert!(recv.recv().await.unwrap().head.starts_with("GET /ping "));
+    connectivity.changed().await.unwrap();
+    assert_eq!(
+        InfluxiveWriterConnectivity::Connected,
+        *connectivity.borrow_and_update()
+    );
+
+    writer.write_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+            .with_field("value", 1),
+    );
+    writer.flush().await.unwrap();
+    // still connected, no transition to report
+    assert!(!connectivity.has_changed().unwrap());
+
+    status.store(503, std::sync::atomic::Ordering::SeqCst);
+    writer.write_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+            .with_field("value", 2),
+    );
+    assert!(writer.flush().await.is_err());
+    connectivity.changed().await.unwrap();
+    assert_eq!(
+        InfluxiveWriterConnectivity::Disconnected,
+        *connectivity.borrow_and_update()
+    );
+
+    let health = writer.health().await;
+    assert!(!health.reachable);
+    assert!(health.last_success.is_some());
+    assert!(health.error.unwrap().contains("503"));
+
+    status.store(204, std::sync::atomic::Ordering::SeqCst);
+    assert!(writer.health().await.reachable);
+    connectivity.changed().await.unwrap();
+    assert_eq!(
+        InfluxiveWriterConnectivity::Connected,
+        *connectivity.borrow_and_update()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_token_provider_on_unauthorized() {
+    let status = Arc::new(std::sync::atomic::AtomicU16::new(401));
+    let (addr, mut recv) = http_stub(status.clone()).await;
+
+    let provider_status = status.clone();
+    let writer = InfluxiveWriter::with_token_auth(
+        InfluxiveWriterConfig::default()
+            .with_max_retries(0)
+            .with_token_provider(TokenProvider::new(move || {
+                provider_status.store(204, std::sync::atomic::Ordering::SeqCst);
+                Box::pin(async move { Ok("new.token".to_string()) })
+            })),
+        format!("http://{addr}"),
+        "my.bucket",
+        "old.token",
+    );
+
+    writer.write_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+            .with_field("value", 1),
+    );
+    writer.flush().await.unwrap();
+
+    let first = recv.recv().await.unwrap();
+    assert!(first.head.contains("Token old.token"), "{}", first.head);
+    let second = recv.recv().await.unwrap();
+    assert!(second.head.contains("Token new.token"), "{}", second.head);
+    assert_eq!(first.body, second.body);
+
+    let stats = writer.stats();
+    assert_eq!(1, stats.metrics_sent);
+    assert_eq!(1, stats.send_errors);
+    assert_eq!(0, stats.metrics_dropped);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_handle_updates_running_writer() {
+    let status = Arc::new(std::sync::atomic::AtomicU16::new(204));
+    let (addr, mut recv) = http_stub(status.clone()).await;
+    let writer = InfluxiveWriter::with_token_auth(
+        InfluxiveWriterConfig::default()
+            .with_batch_duration(std::time::Duration::from_secs(60)),
+        format!("http://{addr}"),
+        "my.bucket",
+        "my.token",
+    );
+    let handle = writer.handle();
+
+    let write = |n: i64| {
+        writer.write_metric(
+            Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+                .with_field("value", n),
+        );
+    };
+
+    // buffered metrics are sent with the rotated token
+    write(1);
+    handle.set_token("rotated.token").await.unwrap();
+    writer.flush().await.unwrap();
+    let req = recv.recv().await.unwrap();
+    assert!(req.head.contains("Token rotated.token"), "{}", req.head);
+    assert_eq!(b"m value=1i 0\n".as_slice(), req.body.as_slice());
+
+    handle
+        .set_token_auth(format!("http://{addr}"), "other.bucket", "other.token")
+        .await
+        .unwrap();
+    write(2);
+    writer.flush().await.unwrap();
+    let req = recv.recv().await.unwrap();
+    assert!(
+        req.head
+            .starts_with("POST /write?db=other.bucket&precision=ns "),
+        "{}",
+        req.head
+    );
+    assert!(req.head.contains("Token other.token"), "{}", req.head);
+
+    // a shorter batch duration sends without a flush
+    handle
+        .set_config(
+            InfluxiveWriterConfig::default()
+                .with_batch_duration(std::time::Duration::from_millis(10))
+                .with_default_tag("host", "my-host"),
+        )
+        .await
+        .unwrap();
+    write(3);
+    let req =
+        tokio::time::timeout(std::time::Duration::from_secs(5), recv.recv())
+            .await
+            .unwrap()
+            .unwrap();
+    assert_eq!(
+        b"m,host=my-host value=3i 0\n".as_slice(),
+        req.body.as_slice()
+    );
+
+    drop(writer);
+    assert!(handle.set_token("late.token").await.is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_pool_tenants() {
+    let status = Arc::new(std::sync::atomic::AtomicU16::new(204));
+    let (addr, mut recv) = http_stub(status.clone()).await;
+    let pool = InfluxiveWriterPool::with_token_auth(
+        InfluxiveWriterConfig::default()
+            .with_batch_duration(std::time::Duration::from_secs(60))
+            .with_default_tag("service", "my.service"),
+        format!("http://{addr}"),
+        "my.token",
+    );
+
+    let a =
+        pool.writer(InfluxiveTenant::new("bucket.a").with_tag("tenant", "a"));
+    let b = pool.writer(
+        InfluxiveTenant::new("bucket.b")
+            .with_org("org.b")
+            .with_tag("service", "b.service"),
+    );
+
+    for n in 0..3 {
+        a.write_metric(
+            Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+                .with_field("value", n),
+        );
+    }
+    b.write_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+            .with_field("value", 9),
+    );
+    pool.flush().await.unwrap();
+
+    let mut bodies = std::collections::HashMap::new();
+    for _ in 0..2 {
+        let req = recv.recv().await.unwrap();
+        assert!(req.head.contains("Token my.token"), "{}", req.head);
+        let path = req.head.split(' ').nth(1).unwrap().to_string();
+        bodies.insert(path, String::from_utf8(req.body).unwrap());
+    }
+    assert_eq!(
+        "m,tenant=a,service=my.service value=0i 0\n\
+         m,tenant=a,service=my.service value=1i 0\n\
+         m,tenant=a,service=my.service value=2i 0\n",
+        bodies["/write?db=bucket.a&precision=ns"]
+    );
+    // tenant tags take precedence over the pool defaults
+    assert_eq!(
+        "m,service=b.service value=9i 0\n",
+        bodies["/api/v2/write?org=org.b&bucket=bucket.b&precision=ns"]
+    );
+
+    assert_eq!(3, a.stats().metrics_sent);
+    assert_eq!(1, b.stats().metrics_sent);
+
+    // dropping a tenant writer sends its remaining metrics
+    b.write_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+            .with_field("value", 10),
+    );
+    drop(b);
+    let req =
+        tokio::time::timeout(std::time::Duration::from_secs(5), recv.recv())
+            .await
+            .unwrap()
+            .unwrap();
+    assert_eq!(
+        b"m,service=b.service value=10i 0\n".as_slice(),
+        req.body.as_slice()
+    );
+
+    // tenant writers don't keep the pool alive
+    drop(pool);
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert!(a.flush().await.is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_pool_flush_does_not_block_other_tenants() {
+    let permits = Arc::new(tokio::sync::Semaphore::new(0));
+    let (addr, mut recv) = http_stub_gated(
+        Arc::new(204.into()),
+        Some(("db=bucket.slow", permits.clone())),
+    )
+    .await;
+    let pool = Arc::new(InfluxiveWriterPool::with_token_auth(
+        InfluxiveWriterConfig::default()
+            .with_batch_duration(std::time::Duration::from_millis(10))
+            .with_max_in_flight(2),
+        format!("http://{addr}"),
+        "my.token",
+    ));
+
+    let slow = pool.writer(InfluxiveTenant::new("bucket.slow"));
+    let fast = pool.writer(InfluxiveTenant::new("bucket.fast"));
+    let write = |writer: &InfluxiveTenantWriter, n: i64| {
+        writer.write_metric(
+            Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+                .with_field("value", n),
+        );
+    };
+
+    // the slow tenant's batch is held by the server
+    write(&slow, 1);
+    let req = recv.recv().await.unwrap();
+    assert!(req.head.contains("db=bucket.slow"), "{}", req.head);
+
+    // meanwhile, the other tenant flushes
+    write(&fast, 2);
+    tokio::time::timeout(std::time::Duration::from_secs(5), fast.flush())
+        .await
+        .unwrap()
+        .unwrap();
+    let req = recv.recv().await.unwrap();
+    assert!(req.head.contains("db=bucket.fast"), "{}", req.head);
+
+    // a pool flush waits for the slow tenant
+    write(&slow, 3);
+    let pool_flush = tokio::task::spawn({
+        let pool = pool.clone();
+        async move { pool.flush().await }
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert!(!pool_flush.is_finished());
+
+    // removing the slow tenant while its batch is being sent
+    // closes it once the batch is done
+    drop(slow);
+    permits.add_permits(1);
+    tokio::time::timeout(std::time::Duration::from_secs(5), pool_flush)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    permits.add_permits(1);
+    let req =
+        tokio::time::timeout(std::time::Duration::from_secs(5), recv.recv())
+            .await
+            .unwrap()
+            .unwrap();
+    assert!(req.head.contains("db=bucket.slow"), "{}", req.head);
+    assert_eq!(b"m value=3i 0\n".as_slice(), req.body.as_slice());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_pool_saturated_tenants_drop_at_ingest() {
+    let permits = Arc::new(tokio::sync::Semaphore::new(0));
+    let (addr, mut recv) = http_stub_gated(
+        Arc::new(204.into()),
+        Some(("db=bucket.slow", permits.clone())),
+    )
+    .await;
+    let pool = InfluxiveWriterPool::with_token_auth(
+        InfluxiveWriterConfig::default()
+            .with_batch_duration(std::time::Duration::from_millis(10))
+            .with_batch_buffer_size(4)
+            .with_max_in_flight(1),
+        format!("http://{addr}"),
+        "my.token",
+    );
+
+    let slow = pool.writer(InfluxiveTenant::new("bucket.slow"));
+    let other = pool.writer(InfluxiveTenant::new("bucket.other"));
+    let write = |writer: &InfluxiveTenantWriter, n: i64| {
+        writer.write_metric(
+            Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+                .with_field("value", n),
+        );
+    };
+
+    // the slow tenant's batch takes the only send slot
+    write(&slow, 1);
+    let req = recv.recv().await.unwrap();
+    assert!(req.head.contains("db=bucket.slow"), "{}", req.head);
+
+    // meanwhile, the other tenant's metrics stay in its bounded queue
+    for n in 0..20 {
+        write(&other, n);
+        if n % 4 == 3 {
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+    }
+    assert_eq!(16, other.stats().metrics_dropped);
+
+    permits.add_permits(1);
+    tokio::time::timeout(std::time::Duration::from_secs(5), other.flush())
+        .await
+        .unwrap()
+        .unwrap();
+    let req = recv.recv().await.unwrap();
+    assert!(req.head.contains("db=bucket.other"), "{}", req.head);
+    assert_eq!(4, other.stats().metrics_sent);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_flush() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("flush.influx");
+
+    let writer = InfluxiveWriter::with_token_auth(
+        InfluxiveWriterConfig::create_with_influx_file(path.clone())
+            .with_batch_duration(std::time::Duration::from_secs(60)),
+        "",
+        "",
+        "",
+    );
+
+    writer.write_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+            .with_field("value", 1),
+    );
+    writer.flush().await.unwrap();
+
+    assert_eq!("m value=1i 0\n", std::fs::read_to_string(&path).unwrap());
+    assert_eq!(1, writer.stats().metrics_sent);
+}
+
+#[test]
+fn config_from_url() {
+    let config = InfluxiveWriterConfig::from_url(
+        "influx+https://my%2Ftoken@influx.example:8443/my.bucket?org=x&precision=ms",
+    )
+    .unwrap();
+    let debug = format!("{:?}", config.backend);
+    assert!(debug.contains("https://influx.example:8443"), "{debug}");
+    assert!(debug.contains("my.bucket"), "{debug}");
+    assert!(debug.contains("Milliseconds"), "{debug}");
+    assert!(!debug.contains("my/token"), "{debug}");
+
+    assert!(
+        InfluxiveWriterConfig::from_url("file:///var/metrics.influx").is_ok()
+    );
+    assert!(InfluxiveWriterConfig::from_url("udp://127.0.0.1:8089").is_ok());
+
+    for invalid in [
+        "not a url",
+        "ftp://host/bucket",
+        "influx+http://token@host:8086",
+        "influx+http://token@host:8086/bucket?precision=h",
+        "file:///var/metrics?format=xml",
+        "udp://127.0.0.1",
+    ] {
+        let err = InfluxiveWriterConfig::from_url(invalid).unwrap_err();
+        assert_eq!(std::io::ErrorKind::InvalidInput, err.kind(), "{invalid}");
+    }
+}
+
+#[test]
+fn config_from_env() {
+    let vars = |vars: &'static [(&'static str, &'static str)]| {
+        move |key: &str| {
+            vars.iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+        }
+    };
+
+    let config = InfluxiveWriterConfig::from_vars(vars(&[
+        ("INFLUXIVE_URL", "udp://127.0.0.1:8089"),
+        ("INFLUXIVE_BATCH_DURATION_MS", "250"),
+        ("INFLUXIVE_BATCH_BUFFER_SIZE", "10"),
+        ("INFLUXIVE_MAX_RETRIES", "0"),
+    ]))
+    .unwrap();
+    assert_eq!(std::time::Duration::from_millis(250), config.batch_duration);
+    assert_eq!(10, config.batch_buffer_size);
+    assert_eq!(0, config.max_retries);
+    assert!(format!("{:?}", config.backend).contains("8089"));
+
+    let config = InfluxiveWriterConfig::from_vars(vars(&[
+        ("INFLUX_HOST", "http://127.0.0.1:8086"),
+        ("INFLUX_BUCKET", "my.bucket"),
+        ("INFLUX_TOKEN", "my.token"),
+        ("INFLUX_ORG", "my.org"),
+    ]))
+    .unwrap();
+    assert!(format!("{:?}", config.backend).contains("my.org"));
+
+    assert!(InfluxiveWriterConfig::from_vars(vars(&[(
+        "INFLUX_HOST",
+        "http://127.0.0.1:8086"
+    )]))
+    .is_err());
+    assert!(InfluxiveWriterConfig::from_vars(vars(&[
+        ("INFLUXIVE_URL", "udp://127.0.0.1:8089"),
+        ("INFLUXIVE_MAX_RETRIES", "many"),
+    ]))
+    .is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_from_url_http_v2() {
+    let status = Arc::new(std::sync::atomic::AtomicU16::new(204));
+    let (addr, mut recv) = http_stub(status).await;
+
+    let writer = InfluxiveWriter::from_url(&format!(
+        "influx+http://my.token@{addr}/my.bucket?org=my.org&precision=ms"
+    ))
+    .unwrap();
+
+    writer.write_metric(
+        Metric::new(
+            std::time::SystemTime::UNIX_EPOCH
+                + std::time::Duration::from_nanos(1_234_567_890),
+            "m",
+        )
+        .with_field("value", 1),
+    );
+
+    let req = recv.recv().await.unwrap();
+    assert!(
+        req.head.starts_with(
+            "POST /api/v2/write?org=my.org&bucket=my.bucket&precision=ms "
+        ),
+        "{}",
+        req.head
+    );
+    assert!(req.head.contains("Token my.token"), "{}", req.head);
+    assert_eq!(b"m value=1i 1234\n".as_slice(), req.body.as_slice());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_from_url_file() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("url.jsonl");
+
+    let mut url = reqwest::Url::from_file_path(&path).unwrap();
+    url.set_query(Some("format=json-lines"));
+    let writer = InfluxiveWriter::from_url(url.as_str()).unwrap();
+
+    writer.write_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+            .with_field("value", 1),
+    );
+    writer.flush().await.unwrap();
+
+    assert_eq!(
+        "{\"measurement\":\"m\",\"time\":0,\"tags\":{},\"fields\":{\"value\":1}}\n",
+        std::fs::read_to_string(&path).unwrap()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_from_url_udp() {
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+
+    let writer = InfluxiveWriter::from_url(&format!("udp://{addr}")).unwrap();
+
+    for n in 0..3 {
+        writer.write_metric(
+            Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+                .with_field("value", n),
+        );
+    }
+    writer.flush().await.unwrap();
+
+    let mut buf = [0; 1500];
+    let len = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        socket.recv(&mut buf),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(
+        "m value=0i 0\nm value=1i 0\nm value=2i 0\n",
+        String::from_utf8_lossy(&buf[..len])
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_udp_without_listener() {
+    // bind and drop to get a port nobody listens on
+    let addr = tokio::net::UdpSocket::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let writers = [
+        InfluxiveWriter::from_url(&format!("udp://{addr}")).unwrap(),
+        InfluxiveWriter::with_token_auth(
+            InfluxiveWriterConfig::default().with_backend(Arc::new(
+                StatsdBackendFactory::new(addr.to_string()),
+            )),
+            "",
+            "",
+            "",
+        ),
+    ];
+
+    for writer in writers {
+        // the refused first send is reported on the following ones
+        for n in 0..3 {
+            writer.write_metric(
+                Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+                    .with_field("value", n),
+            );
+            writer.flush().await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(0, writer.stats().send_errors);
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn config_model_toml() {
+    let model: InfluxiveWriterConfigModel = toml::from_str(
+        r#"
+batch_duration = "250ms"
+max_retries = 5
+measurement_prefix = "myapp."
+
+[default_tags]
+service = "my.service"
+
+[backend]
+type = "failover"
+probe_interval = "10s"
+request_timeout = "2s"
+
+[backend.secondary]
+type = "file"
+path = "/var/metrics.jsonl"
+format = "json-lines"
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(std::time::Duration::from_millis(250), model.batch_duration);
+    assert_eq!(5, model.max_retries);
+    // unset values keep the defaults
+    assert_eq!(4096, model.batch_buffer_size);
+    assert_eq!(
+        BackendConfigModel::Failover {
+            secondary: Box::new(BackendConfigModel::File {
+                path: "/var/metrics.jsonl".into(),
+                format: FileFormatModel::JsonLines,
+                window: None,
+            }),
+            failure_threshold: None,
+            probe_interval: Some(std::time::Duration::from_secs(10)),
+            connect_timeout: None,
+            request_timeout: Some(std::time::Duration::from_secs(2)),
+        },
+        model.backend
+    );
+
+    let config = InfluxiveWriterConfig::try_from(model).unwrap();
+    assert_eq!(std::time::Duration::from_millis(250), config.batch_duration);
+    assert_eq!(Some("myapp."), config.measurement_prefix.as_deref());
+    assert_eq!(1, config.default_tags.len());
+    assert_eq!("service", config.default_tags[0].0.as_str());
+    assert_eq!("my.service", config.default_tags[0].1.to_string());
+    assert!(format!("{:?}", config.backend).contains("metrics.jsonl"));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn config_model_yaml() {
+    let model: InfluxiveWriterConfigModel = serde_yaml::from_str(
+        r#"
+retry_backoff: 1s
+backend:
+  type: influx
+  host: http://127.0.0.1:8086
+  bucket: my.bucket
+  token: my.token
+  org: my.org
+  precision: ms
+"#,
+    )
+    .unwrap();
+    assert_eq!(std::time::Duration::from_secs(1), model.retry_backoff);
+
+    let config = InfluxiveWriterConfig::try_from(model.clone()).unwrap();
+    let debug = format!("{:?}", config.backend);
+    assert!(debug.contains("my.org"), "{debug}");
+    assert!(debug.contains("Milliseconds"), "{debug}");
+
+    // round trips
+    let yaml = serde_yaml::to_string(&model).unwrap();
+    assert_eq!(
+        model,
+        serde_yaml::from_str::<InfluxiveWriterConfigModel>(&yaml).unwrap()
+    );
+
+    // partial endpoints are rejected
+    let model: InfluxiveWriterConfigModel = serde_yaml::from_str(
+        "backend: { type: influx, host: 'http://127.0.0.1:8086' }",
+    )
+    .unwrap();
+    assert!(InfluxiveWriterConfig::try_from(model).is_err());
+
+    // unknown fields are rejected
+    assert!(serde_yaml::from_str::<InfluxiveWriterConfigModel>(
+        "batch_duraton: 1s"
+    )
+    .is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_default_tags_and_prefix() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("tags.influx");
+
+    let writer = InfluxiveWriter::with_token_auth(
+        InfluxiveWriterConfig::create_with_influx_file(path.clone())
+            .with_default_tag("host", "default-host")
+            .with_default_tag("service", "my.service")
+            .with_measurement_prefix("myapp."),
+        "",
+        "",
+        "",
+    );
+
+    writer.write_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+            .with_field("value", 1),
+    );
+    // explicit tags override the defaults
+    writer.write_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+            .with_field("value", 2)
+            .with_tag("host", "my-host"),
+    );
+    writer.flush().await.unwrap();
+
+    assert_eq!(
+        "myapp.m,host=default-host,service=my.service value=1i 0\n\
+         myapp.m,host=my-host,service=my.service value=2i 0\n",
+        std::fs::read_to_string(&path).unwrap()
+    );
+}
+
+#[test]
+fn idle_writer_does_not_wake_up() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let writer = InfluxiveWriter::with_token_auth(
+            InfluxiveWriterConfig::create_with_influx_file(
+                temp_dir.path().join("idle.influx"),
+            )
+            .with_batch_duration(std::time::Duration::from_millis(1)),
+            "",
+            "",
+            "",
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+        // the runtime only wakes up for this sleep, a timer ticking every
+        // third of the batch duration would wake it up on every tick
+        let metrics = tokio::runtime::Handle::current().metrics();
+        let parked = metrics.worker_park_count(0);
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let wakeups = metrics.worker_park_count(0) - parked;
+        assert!(wakeups <= 3, "{wakeups} wakeups");
+
+        drop(writer);
+    });
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_sends_at_batch_deadline() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("deadline.influx");
+
+    let writer = InfluxiveWriter::with_token_auth(
+        InfluxiveWriterConfig::create_with_influx_file(path.clone())
+            .with_batch_duration(std::time::Duration::from_millis(300)),
+        "",
+        "",
+        "",
+    );
+
+    writer.write_metric(
+        Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+            .with_field("value", 1),
+    );
+
+    // not yet due
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert_eq!("", std::fs::read_to_string(&path).unwrap_or_default());
+
+    tokio::time::sleep(std::time::Duration::from_millis(400)).await;
+    assert_eq!("m value=1i 0\n", std::fs::read_to_string(&path).unwrap());
+    assert_eq!(1, writer.stats().batches_sent);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_concurrent_producers_keep_order() {
+    const PRODUCERS: usize = 8;
+    const COUNT: usize = 500;
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("producers.influx");
+
+    let writer = Arc::new(InfluxiveWriter::with_token_auth(
+        InfluxiveWriterConfig::create_with_influx_file(path.clone())
+            .with_batch_buffer_size(PRODUCERS * COUNT),
+        "",
+        "",
+        "",
+    ));
+
+    let threads = (0..PRODUCERS)
+        .map(|producer| {
+            let writer = writer.clone();
+            std::thread::spawn(move || {
+                for seq in 0..COUNT {
+                    writer.write_metric(
+                        Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+                            .with_field("seq", seq as u64)
+                            .with_tag("producer", producer as u64),
+                    );
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    writer.flush().await.unwrap();
+
+    // e.g. "m,producer=3u seq=42u 0"
+    let mut next = [0; PRODUCERS];
+    for line in std::fs::read_to_string(&path).unwrap().lines() {
+        let (series, rest) = line.split_once(' ').unwrap();
+        let producer: usize = series
+            .trim_start_matches("m,producer=")
+            .trim_end_matches('u')
+            .parse()
+            .unwrap();
+        let seq: usize = rest
+            .trim_start_matches("seq=")
+            .split_once('u')
+            .unwrap()
+            .0
+            .parse()
+            .unwrap();
+        assert_eq!(next[producer], seq, "{line}");
+        next[producer] += 1;
+    }
+    assert_eq!([COUNT; PRODUCERS], next);
+    assert_eq!(0, writer.stats().metrics_dropped);
+}
+
+#[test]
+fn ingest_keeps_order_across_threads() {
+    let ingest = ingest::Ingest::with_shards(
+        100,
+        Arc::new(tokio::sync::Notify::new()),
+        4,
+    );
+
+    // like a task moving between worker threads, each write comes from
+    // another thread, and so another shard
+    for n in 0..20 {
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                assert!(ingest.push(
+                    Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+                        .with_field("value", n),
+                ));
+            });
+        });
+    }
+
+    let values = ingest
+        .drain()
+        .into_iter()
+        .map(|m| match m.fields[0].1 {
+            DataType::I64(n) => n,
+            _ => panic!(),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!((0..20).collect::<Vec<_>>(), values);
+}
+
+#[test]
+fn ingest_shards_share_capacity() {
+    let ingest =
+        ingest::Ingest::with_shards(8, Arc::new(tokio::sync::Notify::new()), 4);
+    let push = || {
+        ingest.push(
+            Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+                .with_field("value", 1),
+        )
+    };
+
+    // four threads, so four shards, together only hold the capacity
+    let pushed = std::thread::scope(|s| {
+        (0..4)
+            .map(|_| s.spawn(|| (0..3).filter(|_| push()).count()))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|t| t.join().unwrap())
+            .sum::<usize>()
+    });
+    assert_eq!(8, pushed);
+    assert!(!push());
+
+    // draining releases the capacity again
+    assert_eq!(8, ingest.drain().len());
+    assert_eq!(8, (0..10).filter(|_| push()).count());
+}
+
+#[derive(Debug, Default, Clone)]
+struct SlowBackendFactory {
+    in_flight: Arc<std::sync::atomic::AtomicUsize>,
+    max_in_flight: Arc<std::sync::atomic::AtomicUsize>,
+    sent: Arc<std::sync::Mutex<Vec<Metric>>>,
+}
+
+struct SlowBackend {
+    factory: SlowBackendFactory,
+    buffer: Vec<Metric>,
+}
+
+impl Backend for SlowBackend {
+    fn buffer_metric(&mut self, metric: Metric) {
+        self.buffer.push(metric);
+    }
+
+    fn buffer_count(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn send(&mut self) -> BackendFuture<'_, ()> {
+        use std::sync::atomic::Ordering;
+
+        Box::pin(async move {
+            let in_flight =
+                self.factory.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.factory
+                .max_in_flight
+                .fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            self.factory.sent.lock().unwrap().append(&mut self.buffer);
+            self.factory.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        })
+    }
+
+    fn clear(&mut self) {
+        self.buffer.clear();
+    }
+}
+
+impl BackendFactory for SlowBackendFactory {
+    fn with_token_auth(
+        &self,
+        _host: String,
+        _bucket: String,
+        _token: String,
+    ) -> Box<dyn Backend + 'static + Send + Sync> {
+        Box::new(SlowBackend {
+            factory: self.clone(),
+            buffer: Vec::new(),
+        })
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writer_pipelines_sends() {
+    let factory = SlowBackendFactory::default();
+
+    let writer = InfluxiveWriter::with_token_auth(
+        InfluxiveWriterConfig::default()
+            .with_batch_duration(std::time::Duration::from_millis(10))
+            .with_max_in_flight(4)
+            .with_backend(Arc::new(factory.clone())),
+        "",
+        "",
+        "",
+    );
+
+    for seq in 0..200_u64 {
+        writer.write_metric(
+            Metric::new(std::time::SystemTime::UNIX_EPOCH, "m")
+                .with_field("seq", seq)
+                .with_tag("series", seq % 16),
+        );
+        if seq % 10 == 9 {
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        }
+    }
+    writer.flush().await.unwrap();
+
+    assert!(
+        factory
+            .max_in_flight
+            .load(std::sync::atomic::Ordering::SeqCst)
+            > 1
+    );
+
+    // metrics of each series arrive in the order written
+    let sent = factory.sent.lock().unwrap();
+    assert_eq!(200, sent.len());
+    let mut last: std::collections::HashMap<String, u64> = Default::default();
+    for metric in sent.iter() {
+        let series = metric.tags[0].1.to_string();
+        let seq = match metric.fields[0].1 {
+            DataType::U64(seq) => seq,
+            _ => panic!(),
+        };
+        if let Some(prev) = last.insert(series, seq) {
+            assert!(prev < seq);
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn line_protocol_reader() {
+    let input = "# comment\n\
+        \n\
+        my\\ metric,host=a\\,b f=1.5,i=-3i,u=3u,b=t,s=\"say \\\"hi\\\"\" 1700\n\
+        m v=1 1x\n\
+        m v=2 -1\n\
+        m\"q,t=a\"b s=\"x y\"";
+    let mut reader = LineProtocolReader::new(input.as_bytes())
+        .with_precision(Precision::Seconds);
+
+    let metric = reader.next_metric().await.unwrap().unwrap();
+    assert_eq!("my metric", metric.name.into_string());
+    assert_eq!(
+        std::time::UNIX_EPOCH + std::time::Duration::from_secs(1700),
+        metric.timestamp
+    );
+    assert_eq!("a,b", metric.tags[0].1.to_string());
+    let fields = metric
+        .fields
+        .iter()
+        .map(|(k, v)| format!("{k}={v:?}"))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        vec![
+            "f=F64(1.5)",
+            "i=I64(-3)",
+            "u=U64(3)",
+            "b=Bool(true)",
+            r#"s=String(String("say \"hi\""))"#,
+        ],
+        fields
+    );
+    assert_eq!(3, reader.line_number());
+
+    let err = reader.next_metric().await.unwrap_err();
+    assert_eq!(std::io::ErrorKind::InvalidData, err.kind());
+    assert!(err.to_string().starts_with("line 4:"), "{err}");
+
+    // timestamps before the epoch can't be sent, so they are invalid
+    let err = reader.next_metric().await.unwrap_err();
+    assert_eq!(std::io::ErrorKind::InvalidData, err.kind());
+    assert!(err.to_string().starts_with("line 5:"), "{err}");
+
+    // reading continues past an invalid line, the last line may lack '\n',
+    // quotes are literal in the measurement and tags
+    let metric = reader.next_metric().await.unwrap().unwrap();
+    assert_eq!("m\"q", metric.name.as_str());
+    assert_eq!("a\"b", metric.tags[0].1.to_string());
+    assert_eq!("x y", metric.fields[0].1.to_string());
+    assert!(reader.next_metric().await.unwrap().is_none());
+    assert_eq!(input.len() as u64, reader.offset());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn replay_file_resumes_from_checkpoint() {
+    use std::io::Write;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("offline.influx");
+    let checkpoint = tmp.path().join("offline.checkpoint");
+    std::fs::write(&path, "m v=1i 1\nm v=2i 2\nm v=3i 3\n").unwrap();
+
+    let replay = LineProtocolReplay::new(path.clone())
+        .with_checkpoint_path(Some(checkpoint.clone()))
+        .with_batch_size(2);
+
+    let factory = SlowBackendFactory::default();
+    let writer = InfluxiveWriter::with_token_auth(
+        InfluxiveWriterConfig::default()
+            .with_backend(Arc::new(factory.clone())),
+        "",
+        "",
+        "",
+    );
+    let progress = replay.to_writer(&writer).await.unwrap();
+    assert_eq!(3, progress.metrics);
+    assert_eq!(27, progress.offset);
+    assert_eq!(27, replay.checkpoint().unwrap());
+
+    // only the appended lines are replayed
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap();
+    file.write_all(b"m v=4i 4\nnot line protocol\nm v=5i 5\n")
+        .unwrap();
+    assert!(replay.to_writer(&writer).await.is_err());
+    assert_eq!(27, replay.checkpoint().unwrap());
+
+    let progress = replay
+        .clone()
+        .with_skip_invalid(true)
+        .to_writer(&writer)
+        .await
+        .unwrap();
+    assert_eq!(2, progress.metrics);
+    assert_eq!(1, progress.invalid_lines);
+
+    let values = factory
+        .sent
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|m| m.fields[0].1.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(vec!["1", "2", "3", "4", "5"], values);
+
+    // a file shorter than the checkpoint is a new file
+    std::fs::write(&path, "m v=6i 6\n").unwrap();
+    let progress = replay_file(path, &writer).await.unwrap();
+    assert_eq!(1, progress.metrics);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn replay_to_writer_delivers_every_batch() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("offline.influx");
+    let checkpoint = tmp.path().join("offline.checkpoint");
+    let lines = (0..20)
+        .map(|n| format!("m v={n}i {n}\n"))
+        .collect::<String>();
+    std::fs::write(&path, &lines).unwrap();
+
+    let status = Arc::new(std::sync::atomic::AtomicU16::new(500));
+    let (addr, mut recv) = http_stub(status.clone()).await;
+    // a queue much smaller than the file must not drop metrics
+    let writer = InfluxiveWriter::with_token_auth(
+        InfluxiveWriterConfig::default()
+            .with_batch_buffer_size(2)
+            .with_max_retries(0),
+        format!("http://{addr}"),
+        "my.bucket",
+        "my.token",
+    );
+
+    let replay = LineProtocolReplay::new(path)
+        .with_checkpoint_path(Some(checkpoint))
+        .with_batch_size(5);
+
+    // nothing is checkpointed past a batch that was not delivered
+    assert!(replay.to_writer(&writer).await.is_err());
+    assert_eq!(0, replay.checkpoint().unwrap());
+    while recv.try_recv().is_ok() {}
+    let dropped = writer.stats().metrics_dropped;
+
+    status.store(204, std::sync::atomic::Ordering::SeqCst);
+    let progress = replay.to_writer(&writer).await.unwrap();
+    assert_eq!(20, progress.metrics);
+    assert_eq!(lines.len() as u64, replay.checkpoint().unwrap());
+
+    let mut body = String::new();
+    while let Ok(req) = recv.try_recv() {
+        body.push_str(&String::from_utf8(req.body).unwrap());
+    }
+    assert_eq!(lines, body);
+    assert_eq!(dropped, writer.stats().metrics_dropped);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn replay_over_http_skips_pre_epoch_lines() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("offline.influx");
+    std::fs::write(&path, "m v=1i 1\nm v=2i -1\nm v=3i 3\n").unwrap();
+
+    let status = Arc::new(std::sync::atomic::AtomicU16::new(204));
+    let (addr, mut recv) = http_stub(status).await;
+    let mut backend = InfluxHttpBackendFactory::new().with_token_auth(
+        format!("http://{addr}"),
+        "my.bucket".into(),
+        "my.token".into(),
+    );
+
+    let replay = LineProtocolReplay::new(path);
+    let err = replay.to_backend(&mut *backend).await.unwrap_err();
+    assert!(
+        matches!(&err, BackendError::Io(err) if err.kind() == std::io::ErrorKind::InvalidData),
+        "{err}"
+    );
+
+    backend.clear();
+    let progress = replay
+        .with_skip_invalid(true)
+        .to_backend(&mut *backend)
+        .await
+        .unwrap();
+    assert_eq!(2, progress.metrics);
+    assert_eq!(1, progress.invalid_lines);
+
+    let req = recv.try_recv().unwrap();
+    assert_eq!("m v=1i 1\nm v=3i 3\n", String::from_utf8(req.body).unwrap());
+    assert!(recv.try_recv().is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn replay_to_backend_resumes_after_failed_send() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("offline.influx");
+    let checkpoint = tmp.path().join("offline.checkpoint");
+    std::fs::write(&path, "m v=1i 1\nm v=2i 2\nm v=3i 3\n").unwrap();
+
+    let status = Arc::new(std::sync::atomic::AtomicU16::new(500));
+    let (addr, mut recv) = http_stub(status.clone()).await;
+    let mut backend = InfluxHttpBackendFactory::new().with_token_auth(
+        format!("http://{addr}"),
+        "my.bucket".into(),
+        "my.token".into(),
+    );
+
+    let replay = LineProtocolReplay::new(path)
+        .with_checkpoint_path(Some(checkpoint))
+        .with_batch_size(2);
+
+    assert!(replay.to_backend(&mut *backend).await.is_err());
+    assert_eq!(0, replay.checkpoint().unwrap());
+    backend.clear();
+
+    status.store(204, std::sync::atomic::Ordering::SeqCst);
+    let progress = replay.to_backend(&mut *backend).await.unwrap();
+    assert_eq!(3, progress.metrics);
+    assert_eq!(27, replay.checkpoint().unwrap());
+
+    let mut bodies = Vec::new();
+    while let Ok(req) = recv.try_recv() {
+        bodies.push(String::from_utf8(req.body).unwrap());
+    }
+    assert_eq!(
+        vec!["m v=1i 1\nm v=2i 2\n", "m v=1i 1\nm v=2i 2\n", "m v=3i 3\n"],
+        bodies
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn forwarder_follows_growing_and_rotated_file() {
+    use std::io::Write;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("metrics.influx");
+    let append = |data: &[u8]| {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(data)
+            .unwrap();
+    };
+
+    let factory = SlowBackendFactory::default();
+    let start = || {
+        LineProtocolForwarder::with_token_auth(
+            LineProtocolForwarderConfig::new(path.clone())
+                .with_poll_interval(std::time::Duration::from_millis(10))
+                .with_backend(Arc::new(factory.clone())),
+            "",
+            "",
+            "",
+        )
+    };
+    let sent = || {
+        factory
+            .sent
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|m| m.fields[0].1.to_string())
+            .collect::<Vec<_>>()
+    };
+    let wait_sent = |count: usize| async move {
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while sent().len() < count {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    };
+
+    append(b"m v=1i 1\nm v=2i 2\n");
+    let forwarder = start();
+    wait_sent(2).await;
+
+    // an incomplete line is forwarded once completed
+    append(b"m v=3i");
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert_eq!(2, sent().len());
+    append(b" 3\n");
+    wait_sent(3).await;
+
+    // lines written before a rotation are forwarded, then the new file
+    append(b"m v=4i 4\n");
+    std::fs::rename(&path, tmp.path().join("metrics.influx.1")).unwrap();
+    append(b"m v=5i 5\n");
+    wait_sent(5).await;
+    // the checkpoint is saved after the backend reports the send
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while forwarder.stats().offset != 9 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(1, forwarder.stats().rotations);
+
+    // a restart resumes from the checkpoint
+    forwarder.shutdown().await.unwrap();
+    assert_eq!(
+        "9",
+        std::fs::read_to_string(tmp.path().join("metrics.influx.checkpoint"))
+            .unwrap()
+            .split(' ')
+            .next()
+            .unwrap()
+    );
+    append(b"m v=6i 6\n");
+    let forwarder = start();
+    wait_sent(6).await;
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert_eq!(vec!["1", "2", "3", "4", "5", "6"], sent());
+    assert_eq!(18, forwarder.stats().offset);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn forwarder_stops_on_unretryable_error() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("metrics.influx");
+    let checkpoint = tmp.path().join("metrics.influx.checkpoint");
+    std::fs::write(&path, "m v=1i 1\nm v=2i 2\n").unwrap();
+
+    let status = Arc::new(std::sync::atomic::AtomicU16::new(401));
+    let (addr, mut recv) = http_stub(status.clone()).await;
+    let start = || {
+        LineProtocolForwarder::with_token_auth(
+            LineProtocolForwarderConfig::new(path.clone())
+                .with_poll_interval(std::time::Duration::from_millis(10)),
+            format!("http://{addr}"),
+            "my.bucket",
+            "my.token",
+        )
+    };
+
+    // an expired token stops the forwarder, without checkpointing
+    let forwarder = start();
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while !forwarder.is_stopped() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert!(matches!(
+        forwarder.shutdown().await,
+        Err(BackendError::Http { status: 401, .. })
+    ));
+    assert_eq!(
+        Some("0"),
+        std::fs::read_to_string(&checkpoint)
+            .unwrap()
+            .split(' ')
+            .next()
+    );
+    recv.recv().await.unwrap();
+
+    // once fixed, a new forwarder sends the same lines
+    status.store(204, std::sync::atomic::Ordering::SeqCst);
+    let forwarder = start();
+    let req =
+        tokio::time::timeout(std::time::Duration::from_secs(5), recv.recv())
+            .await
+            .unwrap()
+            .unwrap();
+    assert_eq!(b"m v=1i 1\nm v=2i 2\n".as_slice(), req.body.as_slice());
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while forwarder.stats().offset != 18 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    forwarder.shutdown().await.unwrap();
+}
+
+#[test]
+fn parse_annotated_csv_tables() {
+    let csv = "#datatype,string,long,dateTime:RFC3339,double,string,string\r
+#group,false,false,false,false,true,true\r
+#default,_result,,,,,\r
+,result,table,_time,_value,_field,host\r
+,,0,2023-03-04T05:06:07.25Z,1.5,usage,a\r
+,,0,2023-03-04T05:06:08Z,,usage,a\r
+,,1,2023-03-04T05:06:07Z,+Inf,usage,\"b,\"\"c\"\"\nd\"\r
+\r
+#datatype,string,long,dateTime:RFC3339,boolean,unsignedLong,duration\r
+#group,false,false,false,false,false,false\r
+#default,max,,,,7,\r
+,result,table,_time,ok,count,d\r
+,,2,1970-01-01T00:00:00Z,true,,-1h30m\r
+";
+    let tables = parse_annotated_csv(csv).unwrap();
+    assert_eq!(3, tables.len());
+
+    let t = &tables[0];
+    assert_eq!(
+        ("_result", 0, 2),
+        (t.result(), t.table(), t.records().len())
+    );
+    assert_eq!(
+        vec!["_time", "_value", "_field", "host"],
+        t.columns()
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(QueryDataType::DateTime, t.columns()[0].data_type);
+    assert!(t.columns()[3].group);
+    let r = &t.records()[0];
+    assert_eq!(Some("usage"), r.field());
+    assert_eq!(Some(&QueryValue::Double(1.5)), r.value());
+    assert_eq!(
+        Some(
+            std::time::UNIX_EPOCH
+                + std::time::Duration::from_millis(1_677_906_367_250)
+        ),
+        r.time()
+    );
+    assert_eq!("2023-03-04T05:06:07.25Z", r.values()[0].to_string());
+    assert!(t.records()[1].value().unwrap().is_null());
+    assert_eq!(
+        vec![
+            ("_field", &QueryValue::String("usage".into())),
+            ("host", &QueryValue::String("a".into()))
+        ],
+        t.group_key()
+    );
+
+    let r = &tables[1].records()[0];
+    assert_eq!(Some(f64::INFINITY), r.value().unwrap().as_f64());
+    assert_eq!(Some("b,\"c\"\nd"), r.get("host").unwrap().as_str());
+
+    let t = &tables[2];
+    assert_eq!(("max", 2), (t.result(), t.table()));
+    let r = &t.records()[0];
+    assert_eq!(Some(true), r.get("ok").unwrap().as_bool());
+    assert_eq!(Some(7), r.get("count").unwrap().as_u64());
+    assert_eq!(
+        Some(&QueryValue::Duration(-90 * 60 * 1_000_000_000)),
+        r.get("d")
+    );
+
+    let err = parse_annotated_csv(
+        "#datatype,string,string\n#group,true,true\n#default,,\n\
+         ,error,reference\n,\"bad query\",897\n",
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("bad query"), "{err}");
+
+    let err = parse_annotated_csv(
+        "#datatype,string,long,long\n,result,table,v\n,,0,1.5\n",
+    )
+    .unwrap_err();
+    assert_eq!(std::io::ErrorKind::InvalidData, err.kind());
+}
+
+#[test]
+fn csv_rows_across_chunks() {
+    let csv = ",a,b\n,\"x\ny\",ü\n,1,2";
+    let mut rows = CsvRows::default();
+    let mut out = Vec::new();
+    for b in csv.as_bytes() {
+        rows.push(std::slice::from_ref(b));
+        while let Some(row) = rows.next_row(false).unwrap() {
+            out.push(row);
+        }
+    }
+    assert_eq!(2, out.len());
+    out.push(rows.next_row(true).unwrap().unwrap());
+    assert!(rows.next_row(true).unwrap().is_none());
+    assert_eq!(vec!["", "x\ny", "ü"], out[1]);
+    assert_eq!(vec!["", "1", "2"], out[2]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn query_client_typed_results() {
+    let mock = influxive_mock::InfluxiveMock::new(
+        influxive_mock::InfluxiveMockConfig::default()
+            .with_token(Some("secret".into())),
+    )
+    .await
+    .unwrap();
+    let writer = InfluxiveWriter::with_token_auth(
+        InfluxiveWriterConfig::default()
+            .with_batch_duration(std::time::Duration::from_millis(5)),
+        mock.get_host(),
+        mock.get_bucket(),
+        "secret",
+    );
+    for n in 0..3 {
+        writer.write_metric(
+            Metric::new(
+                std::time::UNIX_EPOCH + std::time::Duration::from_secs(n + 1),
+                "my.metric",
+            )
+            .with_field("value", n as f64)
+            .with_tag("host", if n == 1 { "b" } else { "a" }),
+        );
+    }
+    writer.flush().await.unwrap();
+
+    let client =
+        InfluxiveQueryClient::new(mock.get_host(), mock.get_org(), "secret");
+    let flux = r#"from(bucket: "influxive")
+|> range(start: 0)
+|> filter(fn: (r) => r._measurement == "my.metric")"#;
+    let tables = client.query(flux).await.unwrap();
+    assert_eq!(2, tables.len());
+    assert_eq!(
+        vec![0.0, 2.0],
+        tables[0]
+            .records()
+            .iter()
+            .map(|r| r.value().unwrap().as_f64().unwrap())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        Some("b"),
+        tables[1].records()[0].get("host").unwrap().as_str()
+    );
+
+    assert!(client
+        .query_raw(flux)
+        .await
+        .unwrap()
+        .starts_with("#datatype"));
+
+    let built = FluxQuery::from_bucket("influxive")
+        .range_between(std::time::UNIX_EPOCH, FluxTime::Now)
+        .filter(FluxFilter::measurement("my.metric"))
+        .filter(!FluxFilter::eq("host", "b").or(FluxFilter::lt("_value", 1.0)));
+    let tables = client.query(built).await.unwrap();
+    assert_eq!(1, tables.len());
+    assert_eq!(Some(2.0), tables[0].records()[0].value().unwrap().as_f64());
+    assert!(client.query("buckets()").await.is_err());
+    assert!(
+        InfluxiveQueryClient::new(mock.get_host(), "influxive", "wrong")
+            .query(flux)
+            .await
+            .is_err()
+    );
+
+    #[cfg(feature = "serde")]
+    {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Row {
+            host: String,
+            #[serde(rename = "_value")]
+            value: f64,
+            #[serde(rename = "_time")]
+            time: std::time::SystemTime,
+            #[serde(rename = "_start")]
+            start: i64,
+            #[serde(rename = "_stop")]
+            stop: String,
+            missing: Option<String>,
+        }
+
+        let rows: Vec<Row> = client.query_as(flux).await.unwrap();
+        assert_eq!(3, rows.len());
+        assert_eq!(
+            Row {
+                host: "b".into(),
+                value: 1.0,
+                time: std::time::UNIX_EPOCH + std::time::Duration::from_secs(2),
+                start: 0,
+                stop: rows[2].stop.clone(),
+                missing: None,
+            },
+            rows[2]
+        );
+        assert!(rows[2].stop.ends_with('Z'));
+
+        // tuples take the values in column order
+        type Tuple = (String, String, i64, f64, String, String, String);
+        let tuples: Vec<Tuple> = client.query_as(flux).await.unwrap();
+        assert_eq!(2_000_000_000, tuples[2].2);
+        assert_eq!("b", tuples[2].6);
+    }
+}
+
+#[test]
+fn flux_query_builder() {
+    let t = std::time::UNIX_EPOCH + std::time::Duration::from_millis(1500);
+    let flux = FluxQuery::from_bucket("my.bucket")
+        .range_between(t, FluxTime::Now)
+        .filter(
+            FluxFilter::measurement("cpu")
+                .and(FluxFilter::field("usage").or(!FluxFilter::exists("x"))),
+        )
+        .aggregate_window(
+            std::time::Duration::from_secs(90),
+            FluxAggregate::Mean,
+            false,
+        )
+        .group_except(&["_time", "host"])
+        .pivot(&["_time"], &["_field"], "_value")
+        .ungroup()
+        .group(&["host"])
+        .limit(10)
+        .yield_as("means")
+        .to_string();
+    assert_eq!(
+        r#"from(bucket: "my.bucket")
+|> range(start: 1970-01-01T00:00:01.5Z, stop: now())
+|> filter(fn: (r) => (r["_measurement"] == "cpu") and ((r["_field"] == "usage") or (not (exists r["x"]))))
+|> aggregateWindow(every: 1m30s, fn: mean, createEmpty: false)
+|> group(columns: ["_time", "host"], mode: "except")
+|> pivot(rowKey: ["_time"], columnKey: ["_field"], valueColumn: "_value")
+|> group()
+|> group(columns: ["host"])
+|> limit(n: 10)
+|> yield(name: "means")"#,
+        flux
+    );
+
+    let render = |f: FluxFilter| f.to_string();
+    // quotes, backslashes and interpolation can't escape the literal
+    assert_eq!(
+        r#"r["a\"b"] == "x\" or true or r._x == \"\\ \${a}\n$b""#,
+        render(FluxFilter::eq(
+            "a\"b",
+            "x\" or true or r._x == \"\\ ${a}\n$b"
+        ))
+    );
+    assert_eq!(r#"r["v"] < 1.0"#, render(FluxFilter::lt("v", 1.0)));
+    assert_eq!(
+        r#"r["v"] >= 100000000000000000000.0"#,
+        render(FluxFilter::ge("v", 1e20))
+    );
+    assert_eq!(
+        r#"r["v"] != float(v: "NaN")"#,
+        render(FluxFilter::ne("v", f64::NAN))
+    );
+    assert_eq!(r#"r["v"] > -3"#, render(FluxFilter::gt("v", -3)));
+    assert_eq!(
+        r#"r["v"] <= uint(v: 3)"#,
+        render(FluxFilter::le("v", 3_u64))
+    );
+    assert_eq!(r#"r["v"] == true"#, render(FluxFilter::eq("v", true)));
+    assert_eq!(
+        r#"r["v"] == 1ms500us"#,
+        render(FluxFilter::eq("v", std::time::Duration::from_micros(1500)))
+    );
+    assert_eq!(
+        "from(bucket: \"b\")\n|> range(start: -15m)",
+        FluxQuery::from_bucket("b")
+            .range(FluxTime::Ago(std::time::Duration::from_secs(900)))
+            .to_string()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn query_stream_parses_while_arriving() {
+    use futures::StreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let host = format!("http://{}", listener.local_addr().unwrap());
+    let (first_read_s, first_read_r) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::task::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut req = Vec::new();
+        let mut buf = [0; 4096];
+        while !String::from_utf8_lossy(&req).contains("\"dialect\"") {
+            let n = socket.read(&mut buf).await.unwrap();
+            req.extend_from_slice(&buf[..n]);
+        }
+        socket
+            .write_all(
+                b"HTTP/1.1 200 OK\r\nContent-Type: text/csv\r\n\
+                  Connection: close\r\n\r\n\
+                  #datatype,string,long,double\r\n\
+                  #group,false,false,false\r\n\
+                  #default,_result,,\r\n\
+                  ,result,table,_value\r\n\
+                  ,,0,1\r\n,,0,",
+            )
+            .await
+            .unwrap();
+        // the first record must be parsed before the rest is sent
+        first_read_r.await.unwrap();
+        socket.write_all(b"2\r\n").await.unwrap();
+        // the client closes the connection when the stream is dropped
+        let n = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            socket.read(&mut buf),
+        )
+        .await
+        .unwrap()
+        .unwrap_or(0);
+        assert_eq!(0, n);
+    });
+
+    let client = InfluxiveQueryClient::new(host, "org", "token");
+    let mut stream = client.query_stream("from(bucket: \"b\")").await.unwrap();
+    let record = stream.next().await.unwrap().unwrap();
+    assert_eq!(Some(&QueryValue::Double(1.0)), record.value());
+    first_read_s.send(()).unwrap();
+    let record = stream.next().await.unwrap().unwrap();
+    assert_eq!(Some(&QueryValue::Double(2.0)), record.value());
+    drop(stream);
+    server.await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn query_times_out_on_stalled_response() {
+    use futures::StreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let host = format!("http://{}", listener.local_addr().unwrap());
+    let (done_s, done_r) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::task::spawn(async move {
+        let mut sockets = Vec::new();
+        for _ in 0..2 {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut req = Vec::new();
+            let mut buf = [0; 4096];
+            while !String::from_utf8_lossy(&req).contains("\"dialect\"") {
+                let n = socket.read(&mut buf).await.unwrap();
+                req.extend_from_slice(&buf[..n]);
+            }
+            // send part of the result, then stall with the connection open
+            socket
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\nContent-Type: text/csv\r\n\
+                      Connection: close\r\n\r\n\
+                      #datatype,string,long,double\r\n",
+                )
+                .await
+                .unwrap();
+            sockets.push(socket);
+        }
+        done_r.await.unwrap();
+    });
+
+    let client = InfluxiveQueryClient::new(host, "org", "token")
+        .with_timeout(std::time::Duration::from_millis(100));
+
+    let err = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        client.query_raw("from(bucket: \"b\")"),
+    )
+    .await
+    .unwrap()
+    .unwrap_err();
+    assert_eq!(std::io::ErrorKind::TimedOut, err.kind());
+
+    let mut stream = client.query_stream("from(bucket: \"b\")").await.unwrap();
+    let err =
+        tokio::time::timeout(std::time::Duration::from_secs(5), stream.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap_err();
+    assert_eq!(std::io::ErrorKind::TimedOut, err.kind());
+    assert!(stream.next().await.is_none());
+
+    done_s.send(()).unwrap();
+    server.await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn query_stream_from_mock() {
+    use futures::StreamExt;
+
+    let mock = influxive_mock::InfluxiveMock::new(
+        influxive_mock::InfluxiveMockConfig::default(),
+    )
+    .await
+    .unwrap();
+    let writer = InfluxiveWriter::with_token_auth(
+        InfluxiveWriterConfig::default()
+            .with_batch_duration(std::time::Duration::from_millis(5)),
+        mock.get_host(),
+        mock.get_bucket(),
+        "",
+    );
+    for n in 0..100 {
+        writer.write_metric(
+            Metric::new(
+                std::time::UNIX_EPOCH + std::time::Duration::from_secs(n + 1),
+                "my.metric",
+            )
+            .with_field("value", n)
+            .with_tag("host", if n % 2 == 0 { "a" } else { "b" }),
+        );
+    }
+    writer.flush().await.unwrap();
+
+    let client = InfluxiveQueryClient::new(mock.get_host(), "influxive", "");
+    let flux = FluxQuery::from_bucket("influxive")
+        .range(std::time::UNIX_EPOCH)
+        .filter(FluxFilter::measurement("my.metric"));
+    let records = client
+        .query_stream(&flux)
+        .await
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(100, records.len());
+    assert_eq!(1, records[50].table());
+    assert_eq!(Some("b"), records[50].get("host").unwrap().as_str());
+
+    assert!(client.query_stream("buckets()").await.is_err());
+    let mut empty = client
+        .query_stream(FluxQuery::from_bucket("influxive").range(FluxTime::Now))
+        .await
+        .unwrap();
+    assert!(empty.next().await.is_none());
+}
@@ -0,0 +1,102 @@
+/// Error returned by [Backend](super::Backend) operations.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BackendError {
+    /// The destination could not be reached or the transfer failed,
+    /// e.g. connection refused or timed out. Retryable.
+    Io(std::io::Error),
+
+    /// The server answered with a non-success HTTP status.
+    /// Retryable for `408`, `429` and `5xx` statuses.
+    Http {
+        /// The HTTP status code.
+        status: u16,
+
+        /// The response body, usually describing the error.
+        message: String,
+    },
+
+    /// The metrics were rejected and resending them will not help,
+    /// e.g. they could not be serialized. Not retryable.
+    Rejected(String),
+
+    /// Any other backend specific error. Not retryable.
+    Other(Box<dyn std::error::Error + 'static + Send + Sync>),
+}
+
+impl BackendError {
+    /// Should the writer retry the send that produced this error.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            BackendError::Io(_) => true,
+            BackendError::Http { status, .. } => {
+                *status == 408 || *status == 429 || *status >= 500
+            }
+            BackendError::Rejected(_) | BackendError::Other(_) => false,
+        }
+    }
+}
+
+impl std::fmt::Display for BackendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackendError::Io(err) => write!(f, "io error: {err}"),
+            BackendError::Http { status, message } => {
+                write!(f, "http status {status}: {message}")
+            }
+            BackendError::Rejected(message) => {
+                write!(f, "metrics rejected: {message}")
+            }
+            BackendError::Other(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for BackendError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BackendError::Io(err) => Some(err),
+            BackendError::Other(err) => Some(&**err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for BackendError {
+    fn from(err: std::io::Error) -> Self {
+        BackendError::Io(err)
+    }
+}
+
+impl From<reqwest::Error> for BackendError {
+    fn from(err: reqwest::Error) -> Self {
+        match err.status() {
+            Some(status) => BackendError::Http {
+                status: status.as_u16(),
+                message: err.to_string(),
+            },
+            None => BackendError::Io(std::io::Error::other(err)),
+        }
+    }
+}
+
+/// Result of [Backend](super::Backend) operations.
+pub type BackendResult<T> = Result<T, BackendError>;
+
+/// Boxed future returned by [Backend](super::Backend) operations.
+pub type BackendFuture<'a, T> = std::pin::Pin<
+    Box<dyn std::future::Future<Output = BackendResult<T>> + 'a + Send>,
+>;
+
+/// Health information reported by [Backend::health](super::Backend::health).
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct BackendHealth {
+    /// The server version, if the backend talks to a server reporting it.
+    pub version: Option<String>,
+
+    /// Writes are served by a fallback rather than the configured
+    /// destination, e.g. a [FailoverBackendFactory](super::FailoverBackendFactory)
+    /// switched to its secondary.
+    pub degraded: bool,
+}
@@ -0,0 +1,307 @@
+use super::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+struct FailoverBackend {
+    factory: FailoverBackendFactory,
+    buffer: Vec<Metric>,
+    // shared with the probe task, so it follows credential rotations
+    primary: Arc<std::sync::Mutex<http::InfluxHttp>>,
+    secondary: Box<dyn Backend + 'static + Send + Sync>,
+    consecutive_failures: usize,
+    primary_down: Arc<AtomicBool>,
+    probe_task: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for FailoverBackend {
+    fn drop(&mut self) {
+        self.probe_task.abort();
+    }
+}
+
+impl FailoverBackend {
+    fn primary(&self) -> http::InfluxHttp {
+        self.primary.lock().unwrap().clone()
+    }
+
+    async fn send_secondary(&mut self) -> BackendResult<()> {
+        for metric in self.buffer.drain(..) {
+            self.secondary.buffer_metric(metric);
+        }
+        self.secondary.send().await
+    }
+}
+
+impl Backend for FailoverBackend {
+    fn buffer_metric(&mut self, metric: Metric) {
+        self.buffer.push(metric);
+    }
+
+    fn buffer_count(&self) -> usize {
+        self.buffer.len() + self.secondary.buffer_count()
+    }
+
+    fn send(&mut self) -> BackendFuture<'_, ()> {
+        Box::pin(async move {
+            if self.primary_down.load(Ordering::SeqCst) {
+                return self.send_secondary().await;
+            }
+
+            if !self.buffer.is_empty() {
+                let body = self
+                    .buffer
+                    .iter()
+                    .cloned()
+                    .filter_map(|metric| query_to_line(metric_to_query(metric)))
+                    .collect::<String>();
+
+                match self.primary().write(body).await {
+                    Ok(()) => {
+                        self.consecutive_failures = 0;
+                        self.buffer.clear();
+                    }
+                    // a rejected batch says nothing about the primary,
+                    // and e.g. a 401 is handled by the writer
+                    Err(err) if !err.is_retryable() => return Err(err),
+                    Err(err) => {
+                        tracing::warn!(?err, "write metrics error");
+                        self.consecutive_failures += 1;
+                        if self.consecutive_failures
+                            >= self.factory.failure_threshold
+                        {
+                            tracing::warn!(
+                                failures = self.consecutive_failures,
+                                "switching to secondary metrics backend"
+                            );
+                            self.consecutive_failures = 0;
+                            self.primary_down.store(true, Ordering::SeqCst);
+                        }
+                        // don't lose the failed batch
+                        return self.send_secondary().await;
+                    }
+                }
+            }
+
+            // retry anything still held by the secondary
+            if self.secondary.buffer_count() > 0 {
+                self.secondary.send().await?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn clear(&mut self) {
+        self.buffer.clear();
+        self.secondary.clear();
+    }
+
+    fn flush(&mut self) -> BackendFuture<'_, ()> {
+        self.secondary.flush()
+    }
+
+    fn close(&mut self) -> BackendFuture<'_, ()> {
+        self.secondary.close()
+    }
+
+    fn set_token(&mut self, token: String) {
+        self.primary.lock().unwrap().set_token(token);
+    }
+
+    fn set_endpoint(&mut self, host: String, bucket: String) {
+        self.primary.lock().unwrap().set_endpoint(host, bucket);
+    }
+
+    fn health(&mut self) -> BackendFuture<'_, BackendHealth> {
+        Box::pin(async move {
+            if !self.primary_down.load(Ordering::SeqCst) {
+                match self.primary().ping().await {
+                    Ok(health) => return Ok(health),
+                    Err(err) => {
+                        tracing::debug!(
+                            ?err,
+                            "primary metrics health check failed"
+                        );
+                    }
+                }
+            }
+
+            let health = self.secondary.health().await?;
+            Ok(BackendHealth {
+                degraded: true,
+                ..health
+            })
+        })
+    }
+}
+
+/// Write to a primary InfluxDB instance, switching to a secondary
+/// backend after a number of consecutive failed writes. Only retryable
+/// errors count as failures, e.g. a batch rejected by the primary is
+/// reported to the writer instead, and a `401` lets the writer fetch a
+/// new token from its [InfluxiveWriterConfig::token_provider]. Batches
+/// that fail on the primary are written to the secondary. While
+/// switched over, the primary's `/ping` endpoint is probed in the
+/// background and writes switch back once it answers. Tokens and
+/// endpoints set on the writer apply to the probe, too. Health checks
+/// report [BackendHealth::degraded] while the primary is unreachable.
+///
+/// The primary is the host, bucket and token the writer is created
+/// with, see [InfluxiveWriterConfig::create_with_failover].
+#[derive(Debug, Clone)]
+pub struct FailoverBackendFactory {
+    secondary: Arc<dyn BackendFactory + 'static + Send + Sync>,
+    secondary_host: String,
+    secondary_bucket: String,
+    secondary_token: String,
+    failure_threshold: usize,
+    probe_interval: std::time::Duration,
+    connect_timeout: std::time::Duration,
+    request_timeout: std::time::Duration,
+}
+
+impl FailoverBackendFactory {
+    /// Fail over to a secondary InfluxDB instance.
+    pub fn new_with_secondary_endpoint<
+        H: Into<String>,
+        B: Into<String>,
+        T: Into<String>,
+    >(
+        host: H,
+        bucket: B,
+        token: T,
+    ) -> Self {
+        Self {
+            secondary: Arc::new(DefaultBackendFactory),
+            secondary_host: host.into(),
+            secondary_bucket: bucket.into(),
+            secondary_token: token.into(),
+            failure_threshold: 3,
+            probe_interval: std::time::Duration::from_secs(5),
+            connect_timeout: std::time::Duration::from_secs(5),
+            request_timeout: std::time::Duration::from_secs(10),
+        }
+    }
+
+    /// Fail over to any other backend, e.g. a
+    /// [LineProtocolFileBackendFactory]. The backend is created with
+    /// empty host, bucket and token.
+    pub fn new_with_secondary_backend(
+        secondary: Arc<dyn BackendFactory + 'static + Send + Sync>,
+    ) -> Self {
+        Self {
+            secondary,
+            secondary_host: String::new(),
+            secondary_bucket: String::new(),
+            secondary_token: String::new(),
+            failure_threshold: 3,
+            probe_interval: std::time::Duration::from_secs(5),
+            connect_timeout: std::time::Duration::from_secs(5),
+            request_timeout: std::time::Duration::from_secs(10),
+        }
+    }
+
+    /// Number of consecutive failed writes to the primary before
+    /// switching to the secondary.
+    /// Defaults to `3`.
+    pub fn with_failure_threshold(mut self, failure_threshold: usize) -> Self {
+        self.failure_threshold = failure_threshold.max(1);
+        self
+    }
+
+    /// How often to probe the primary while switched over.
+    /// Defaults to `5s`.
+    pub fn with_probe_interval(
+        mut self,
+        probe_interval: std::time::Duration,
+    ) -> Self {
+        self.probe_interval = probe_interval;
+        self
+    }
+
+    /// Timeout connecting to the primary. A timed out connect counts
+    /// as a failed write.
+    /// Defaults to `5s`.
+    pub fn with_connect_timeout(
+        mut self,
+        connect_timeout: std::time::Duration,
+    ) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// Timeout for each write to and probe of the primary, including
+    /// connecting. A primary that accepts connections but never answers
+    /// fails with this timeout, and counts as a failed write.
+    /// Defaults to `10s`.
+    pub fn with_request_timeout(
+        mut self,
+        request_timeout: std::time::Duration,
+    ) -> Self {
+        self.request_timeout = request_timeout;
+        self
+    }
+}
+
+impl BackendFactory for FailoverBackendFactory {
+    fn with_token_auth(
+        &self,
+        host: String,
+        bucket: String,
+        token: String,
+    ) -> Box<dyn Backend + 'static + Send + Sync> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.request_timeout)
+            .build()
+            .unwrap_or_else(|err| {
+                tracing::warn!(?err, "failover client error, no timeouts");
+                reqwest::Client::new()
+            });
+        let primary = Arc::new(std::sync::Mutex::new(
+            http::InfluxHttp::new(host, bucket, token).with_client(client),
+        ));
+        let secondary = self.secondary.with_token_auth(
+            self.secondary_host.clone(),
+            self.secondary_bucket.clone(),
+            self.secondary_token.clone(),
+        );
+
+        let primary_down = Arc::new(AtomicBool::new(false));
+
+        let probe = primary.clone();
+        let probe_down = primary_down.clone();
+        let probe_interval = self.probe_interval;
+        let probe_task = tokio::task::spawn(async move {
+            loop {
+                tokio::time::sleep(probe_interval).await;
+                if !probe_down.load(Ordering::SeqCst) {
+                    continue;
+                }
+                let primary = probe.lock().unwrap().clone();
+                match primary.ping().await {
+                    Ok(_) => {
+                        tracing::info!(
+                            "switching back to primary metrics backend"
+                        );
+                        probe_down.store(false, Ordering::SeqCst);
+                    }
+                    Err(err) => {
+                        tracing::debug!(?err, "primary metrics probe failed");
+                    }
+                }
+            }
+        });
+
+        let out: Box<dyn Backend + 'static + Send + Sync> =
+            Box::new(FailoverBackend {
+                factory: self.clone(),
+                buffer: Vec::new(),
+                primary,
+                secondary,
+                consecutive_failures: 0,
+                primary_down,
+                probe_task,
+            });
+        out
+    }
+}
@@ -0,0 +1,932 @@
+use super::*;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Serialization format written by [LineProtocolFileBackendFactory].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FileFormat {
+    /// InfluxDB Line Protocol appended to a single file.
+    LineProtocol,
+
+    /// One JSON object per metric appended to a single file, e.g.
+    /// `{"measurement":"m","time":1700000000000000000,"tags":{"host":"a"},"fields":{"value":3.5}}`.
+    /// The time is in nanoseconds since the unix epoch. Non-finite
+    /// floats are written as `null`.
+    JsonLines,
+
+    /// The path is a directory holding one `{measurement}.csv` file per
+    /// measurement. Columns are `time` (nanoseconds since the unix epoch),
+    /// then tag keys, then field keys, each sorted, and never change
+    /// within a file. A tag named `time`, or repeated within a metric, is
+    /// written to a `{key}_tag` column, and a field with the same key as
+    /// a column before it to a `{key}_field` column. If a measurement
+    /// gains new columns, a new file `{measurement}.{n}.csv` is started
+    /// with the widened header.
+    Csv,
+
+    /// The path is a directory holding `{measurement}/{window_start}.parquet`
+    /// files, where `window_start` is the unix time in seconds the time
+    /// window starts at. Each batch is written as a row group and a file
+    /// is closed once metrics for a later window arrive, the window has
+    /// been over for another full window, or the writer is flushed or
+    /// shut down. Metrics arriving for the window of a closed file start
+    /// a new `{window_start}-{n}.parquet` file. Requires the `parquet`
+    /// feature.
+    #[cfg(feature = "parquet")]
+    Parquet {
+        /// Timespan covered by each file.
+        window: std::time::Duration,
+    },
+}
+
+/// Serializes batches of metrics to disk. Writes are blocking,
+/// the backend runs them on the blocking thread pool.
+trait FileSink: 'static + Send {
+    fn write(&mut self, metrics: &[Metric]) -> std::io::Result<()>;
+
+    /// Make everything written so far durable and readable.
+    fn flush(&mut self) -> std::io::Result<()>;
+
+    /// Flush and release open files, later writes reopen them.
+    fn close(&mut self) -> std::io::Result<()>;
+}
+
+struct FileBackend {
+    buffer: Vec<Metric>,
+    // only accessed through `&mut self`, the mutex makes the backend Sync
+    sink: std::sync::Mutex<Option<Box<dyn FileSink>>>,
+}
+
+impl FileBackend {
+    /// Run a blocking operation on the sink on the blocking thread pool.
+    async fn run<R, F>(&mut self, f: F) -> Result<R, BackendError>
+    where
+        R: 'static + Send,
+        F: 'static + Send + FnOnce(&mut dyn FileSink) -> std::io::Result<R>,
+    {
+        let sink = self.sink.get_mut().unwrap_or_else(|e| e.into_inner());
+        let mut sink = match sink.take() {
+            Some(sink) => sink,
+            None => {
+                return Err(BackendError::Rejected(
+                    "metrics file closed".to_string(),
+                ))
+            }
+        };
+
+        let (sink, res) = tokio::task::spawn_blocking(move || {
+            let res = f(&mut *sink);
+            (sink, res)
+        })
+        .await
+        .map_err(|err| BackendError::Other(err.into()))?;
+
+        *self.sink.get_mut().unwrap_or_else(|e| e.into_inner()) = Some(sink);
+        Ok(res?)
+    }
+}
+
+impl Backend for FileBackend {
+    fn buffer_metric(&mut self, metric: Metric) {
+        self.buffer.push(metric);
+    }
+
+    fn buffer_count(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn send(&mut self) -> BackendFuture<'_, ()> {
+        Box::pin(async move {
+            // handed back by the blocking task, so a failed batch stays
+            // buffered to be retried
+            let buffer = std::mem::take(&mut self.buffer);
+            let (buffer, res) = self
+                .run(move |sink| {
+                    let res = sink.write(&buffer);
+                    Ok((buffer, res))
+                })
+                .await?;
+            self.buffer = buffer;
+            res?;
+            self.buffer.clear();
+            Ok(())
+        })
+    }
+
+    fn clear(&mut self) {
+        self.buffer.clear();
+    }
+
+    fn flush(&mut self) -> BackendFuture<'_, ()> {
+        Box::pin(async move { self.run(|sink| sink.flush()).await })
+    }
+
+    fn close(&mut self) -> BackendFuture<'_, ()> {
+        Box::pin(async move { self.run(|sink| sink.close()).await })
+    }
+}
+
+/// Write metrics to disk, by default as InfluxDB Line Protocol.
+/// See [FileFormat] for the alternative serializations.
+#[derive(Debug)]
+pub struct LineProtocolFileBackendFactory {
+    file_path: PathBuf,
+    format: FileFormat,
+}
+
+impl LineProtocolFileBackendFactory {
+    /// Creates a new instance with the provided file path.
+    pub fn new(file_path: PathBuf) -> Self {
+        Self {
+            file_path,
+            format: FileFormat::LineProtocol,
+        }
+    }
+
+    /// Serialization format to write.
+    /// Defaults to [FileFormat::LineProtocol].
+    pub fn with_format(mut self, format: FileFormat) -> Self {
+        self.format = format;
+        self
+    }
+}
+
+impl BackendFactory for LineProtocolFileBackendFactory {
+    fn with_token_auth(
+        &self,
+        _host: String,
+        _bucket: String,
+        _token: String,
+    ) -> Box<dyn Backend + 'static + Send + Sync> {
+        let sink: Box<dyn FileSink> = match self.format {
+            FileFormat::LineProtocol => Box::new(LineSink {
+                path: self.file_path.clone(),
+                writer: try_open_append(&self.file_path),
+                encode: |metric, out| {
+                    let query = metric_to_query(metric.clone());
+                    if let Some(line) = query_to_line(query) {
+                        out.push_str(&line);
+                    }
+                },
+            }),
+            FileFormat::JsonLines => Box::new(LineSink {
+                path: self.file_path.clone(),
+                writer: try_open_append(&self.file_path),
+                encode: metric_to_json,
+            }),
+            FileFormat::Csv => Box::new(CsvSink {
+                // created ahead of time where possible, else on write
+                dir_created: try_create_dir(&self.file_path),
+                dir: self.file_path.clone(),
+                files: HashMap::new(),
+            }),
+            #[cfg(feature = "parquet")]
+            FileFormat::Parquet { window } => {
+                // files are opened in per measurement subdirectories,
+                // which creates the directory on write if this fails
+                try_create_dir(&self.file_path);
+                Box::new(parquet_sink::ParquetSink::new(
+                    self.file_path.clone(),
+                    window,
+                ))
+            }
+        };
+
+        let out: Box<dyn Backend + 'static + Send + Sync> =
+            Box::new(FileBackend {
+                buffer: Vec::new(),
+                sink: std::sync::Mutex::new(Some(sink)),
+            });
+        out
+    }
+}
+
+fn open_append(
+    path: &Path,
+) -> std::io::Result<std::io::BufWriter<std::fs::File>> {
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    Ok(std::io::BufWriter::new(file))
+}
+
+/// Open the file ahead of the first write, so it exists as soon as the
+/// backend does. On error, opening is retried on write.
+fn try_open_append(path: &Path) -> Option<std::io::BufWriter<std::fs::File>> {
+    match open_append(path) {
+        Ok(writer) => Some(writer),
+        Err(err) => {
+            tracing::warn!(?err, ?path, "open metrics file error");
+            None
+        }
+    }
+}
+
+fn try_create_dir(path: &Path) -> bool {
+    match std::fs::create_dir_all(path) {
+        Ok(()) => true,
+        Err(err) => {
+            tracing::warn!(?err, ?path, "create metrics directory error");
+            false
+        }
+    }
+}
+
+fn sync(writer: &mut std::io::BufWriter<std::fs::File>) -> std::io::Result<()> {
+    writer.flush()?;
+    writer.get_ref().sync_data()
+}
+
+/// Makes a measurement name safe to use as a file name.
+fn file_stem(name: &str) -> String {
+    let stem: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    match stem.trim_start_matches('.') {
+        "" => "_".to_string(),
+        s => s.to_string(),
+    }
+}
+
+fn time_nanos(timestamp: std::time::SystemTime) -> u128 {
+    timestamp
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0)
+}
+
+/// Appends one encoded line per metric to a single file.
+struct LineSink {
+    path: PathBuf,
+    writer: Option<std::io::BufWriter<std::fs::File>>,
+    encode: fn(&Metric, &mut String),
+}
+
+impl FileSink for LineSink {
+    fn write(&mut self, metrics: &[Metric]) -> std::io::Result<()> {
+        let mut out = String::new();
+        for metric in metrics {
+            (self.encode)(metric, &mut out);
+        }
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => self.writer.insert(open_append(&self.path)?),
+        };
+        writer.write_all(out.as_bytes())?;
+        writer.flush()
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.writer {
+            Some(writer) => sync(writer),
+            None => Ok(()),
+        }
+    }
+
+    fn close(&mut self) -> std::io::Result<()> {
+        self.flush()?;
+        self.writer = None;
+        Ok(())
+    }
+}
+
+pub(crate) fn json_str(out: &mut String, s: &str) {
+    use std::fmt::Write;
+
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn json_value(out: &mut String, value: &DataType) {
+    match value {
+        DataType::F64(f) if !f.is_finite() => out.push_str("null"),
+        DataType::String(s) => json_str(out, s.as_str()),
+        value => out.push_str(&value.to_string()),
+    }
+}
+
+fn json_object(out: &mut String, entries: &[(StringType, DataType)]) {
+    out.push('{');
+    for (i, (k, v)) in entries.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        json_str(out, k.as_str());
+        out.push(':');
+        json_value(out, v);
+    }
+    out.push('}');
+}
+
+/// Renders a metric as a newline-terminated JSON object.
+pub(crate) fn metric_to_json(metric: &Metric, out: &mut String) {
+    let Metric {
+        timestamp,
+        name,
+        fields,
+        tags,
+    } = metric;
+
+    out.push_str("{\"measurement\":");
+    json_str(out, name.as_str());
+    out.push_str(",\"time\":");
+    out.push_str(&time_nanos(*timestamp).to_string());
+    out.push_str(",\"tags\":");
+    json_object(out, tags);
+    out.push_str(",\"fields\":");
+    json_object(out, fields);
+    out.push_str("}\n");
+}
+
+fn csv_cell(out: &mut String, value: &str) {
+    if value.contains([',', '"', '\n', '\r']) {
+        out.push('"');
+        out.push_str(&value.replace('"', "\"\""));
+        out.push('"');
+    } else {
+        out.push_str(value);
+    }
+}
+
+fn csv_row<'a>(out: &mut String, cells: impl Iterator<Item = &'a str>) {
+    for (i, cell) in cells.enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        csv_cell(out, cell);
+    }
+    out.push('\n');
+}
+
+/// The cells of a metric by column, tags then fields each sorted.
+/// A tag with the same key as `time` or an earlier tag gets a `_tag`
+/// suffix, and a field with the same key as any column before it a
+/// `_field` suffix, so no cell overwrites or drops another.
+fn csv_cells(metric: &Metric) -> Vec<(String, String)> {
+    let mut tags = metric
+        .tags
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect::<Vec<_>>();
+    tags.sort();
+    let mut fields = metric
+        .fields
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect::<Vec<_>>();
+    fields.sort();
+
+    let mut cells =
+        vec![("time".to_string(), time_nanos(metric.timestamp).to_string())];
+    for (mut k, v) in tags {
+        while cells.iter().any(|(c, _)| *c == k) {
+            k.push_str("_tag");
+        }
+        cells.push((k, v));
+    }
+    for (mut k, v) in fields {
+        while cells.iter().any(|(c, _)| *c == k) {
+            k.push_str("_field");
+        }
+        cells.push((k, v));
+    }
+    cells
+}
+
+struct CsvFile {
+    columns: Vec<String>,
+    writer: std::io::BufWriter<std::fs::File>,
+}
+
+impl CsvFile {
+    /// Opens the first `{stem}.csv` / `{stem}.{n}.csv` that is either
+    /// missing, or whose header covers `columns`, appending to it.
+    fn open(
+        dir: &Path,
+        stem: &str,
+        columns: Vec<String>,
+    ) -> std::io::Result<Self> {
+        for n in 0.. {
+            let path = if n == 0 {
+                dir.join(format!("{stem}.csv"))
+            } else {
+                dir.join(format!("{stem}.{n}.csv"))
+            };
+
+            let header = match std::fs::File::open(&path) {
+                Ok(file) => {
+                    let mut header = String::new();
+                    std::io::BufReader::new(file).read_line(&mut header)?;
+                    header
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    String::new()
+                }
+                Err(err) => return Err(err),
+            };
+
+            let mut writer = open_append(&path)?;
+
+            if header.is_empty() {
+                let mut out = String::new();
+                csv_row(&mut out, columns.iter().map(String::as_str));
+                writer.write_all(out.as_bytes())?;
+                return Ok(Self { columns, writer });
+            }
+
+            // headers we write never need quoting
+            let existing = header
+                .trim_end_matches(['\r', '\n'])
+                .split(',')
+                .map(String::from)
+                .collect::<Vec<_>>();
+            if columns.iter().all(|c| existing.contains(c)) {
+                return Ok(Self {
+                    columns: existing,
+                    writer,
+                });
+            }
+        }
+        unreachable!()
+    }
+}
+
+struct CsvSink {
+    dir: PathBuf,
+    dir_created: bool,
+    files: HashMap<String, CsvFile>,
+}
+
+impl FileSink for CsvSink {
+    fn write(&mut self, metrics: &[Metric]) -> std::io::Result<()> {
+        let mut touched = Vec::new();
+
+        if !self.dir_created {
+            std::fs::create_dir_all(&self.dir)?;
+            self.dir_created = true;
+        }
+
+        for metric in metrics {
+            let stem = file_stem(metric.name.as_str());
+            let cells = csv_cells(metric);
+            let columns =
+                cells.iter().map(|(c, _)| c.clone()).collect::<Vec<_>>();
+
+            let needs_open = match self.files.get(&stem) {
+                None => true,
+                Some(file) => !columns.iter().all(|c| file.columns.contains(c)),
+            };
+
+            if needs_open {
+                // widen the existing header so rows of the old shape
+                // keep landing in the same file
+                let mut widened = match self.files.remove(&stem) {
+                    Some(mut file) => {
+                        file.writer.flush()?;
+                        file.columns
+                    }
+                    None => Vec::new(),
+                };
+                for column in columns {
+                    if !widened.contains(&column) {
+                        widened.push(column);
+                    }
+                }
+                self.files.insert(
+                    stem.clone(),
+                    CsvFile::open(&self.dir, &stem, widened)?,
+                );
+            }
+
+            let file = self.files.get_mut(&stem).unwrap();
+
+            let mut out = String::new();
+            csv_row(
+                &mut out,
+                file.columns.iter().map(|c| {
+                    cells
+                        .iter()
+                        .find(|(k, _)| k == c)
+                        .map(|(_, v)| v.as_str())
+                        .unwrap_or("")
+                }),
+            );
+            file.writer.write_all(out.as_bytes())?;
+
+            if !touched.contains(&stem) {
+                touched.push(stem);
+            }
+        }
+
+        for stem in touched {
+            if let Some(file) = self.files.get_mut(&stem) {
+                file.writer.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        for file in self.files.values_mut() {
+            sync(&mut file.writer)?;
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) -> std::io::Result<()> {
+        self.flush()?;
+        self.files.clear();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "parquet")]
+pub(crate) mod parquet_sink {
+    use super::*;
+    use arrow_array::{
+        ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch,
+        StringArray, TimestampNanosecondArray, UInt64Array,
+    };
+    use arrow_schema::{
+        DataType as ArrowType, Field, Schema, SchemaRef, TimeUnit,
+    };
+
+    fn arrow_type(value: &DataType) -> ArrowType {
+        match value {
+            DataType::Bool(_) => ArrowType::Boolean,
+            DataType::F64(_) => ArrowType::Float64,
+            DataType::I64(_) => ArrowType::Int64,
+            DataType::U64(_) => ArrowType::UInt64,
+            DataType::String(_) => ArrowType::Utf8,
+        }
+    }
+
+    /// Column layout of one file: the time, then tags as strings, then
+    /// fields typed by the first value seen, each sorted.
+    #[derive(Clone, PartialEq)]
+    struct Layout {
+        tags: Vec<String>,
+        fields: Vec<(String, ArrowType)>,
+    }
+
+    impl Layout {
+        fn of(metrics: &[&Metric]) -> Self {
+            let mut tags = Vec::new();
+            let mut fields: Vec<(String, ArrowType)> = Vec::new();
+            for metric in metrics {
+                for (k, _) in metric.tags.iter() {
+                    if !tags.iter().any(|t| t == k.as_str()) {
+                        tags.push(k.to_string());
+                    }
+                }
+                for (k, v) in metric.fields.iter() {
+                    if !fields.iter().any(|(f, _)| f == k.as_str()) {
+                        fields.push((k.to_string(), arrow_type(v)));
+                    }
+                }
+            }
+            tags.sort();
+            fields.sort_by(|a, b| a.0.cmp(&b.0));
+            Self { tags, fields }
+        }
+
+        fn covers(&self, other: &Layout) -> bool {
+            other.tags.iter().all(|t| self.tags.contains(t))
+                && other
+                    .fields
+                    .iter()
+                    .all(|(f, _)| self.fields.iter().any(|(g, _)| g == f))
+        }
+
+        fn widen(&mut self, other: Layout) {
+            for t in other.tags {
+                if !self.tags.contains(&t) {
+                    self.tags.push(t);
+                }
+            }
+            for (f, ty) in other.fields {
+                if !self.fields.iter().any(|(g, _)| *g == f) {
+                    self.fields.push((f, ty));
+                }
+            }
+            self.tags.sort();
+            self.fields.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+
+        fn schema(&self) -> SchemaRef {
+            let mut columns = vec![Field::new(
+                "time",
+                ArrowType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            )];
+            for t in self.tags.iter() {
+                columns.push(Field::new(t, ArrowType::Utf8, true));
+            }
+            for (f, ty) in self.fields.iter() {
+                columns.push(Field::new(f, ty.clone(), true));
+            }
+            std::sync::Arc::new(Schema::new(columns))
+        }
+
+        /// Builds a record batch, values that don't match the column
+        /// type are written as null.
+        fn batch(
+            &self,
+            schema: SchemaRef,
+            metrics: &[&Metric],
+        ) -> std::io::Result<RecordBatch> {
+            fn get<'a>(
+                entries: &'a [(StringType, DataType)],
+                key: &str,
+            ) -> Option<&'a DataType> {
+                entries
+                    .iter()
+                    .find(|(k, _)| k.as_str() == key)
+                    .map(|(_, v)| v)
+            }
+
+            let mut columns: Vec<ArrayRef> =
+                vec![std::sync::Arc::new(TimestampNanosecondArray::from(
+                    metrics
+                        .iter()
+                        .map(|m| time_nanos(m.timestamp) as i64)
+                        .collect::<Vec<_>>(),
+                ))];
+
+            for t in self.tags.iter() {
+                columns.push(std::sync::Arc::new(StringArray::from(
+                    metrics
+                        .iter()
+                        .map(|m| get(&m.tags, t).map(|v| v.to_string()))
+                        .collect::<Vec<_>>(),
+                )));
+            }
+
+            for (f, ty) in self.fields.iter() {
+                let values = metrics.iter().map(|m| get(&m.fields, f));
+                let array: ArrayRef = match ty {
+                    ArrowType::Boolean => {
+                        std::sync::Arc::new(BooleanArray::from(
+                            values
+                                .map(|v| match v {
+                                    Some(DataType::Bool(b)) => Some(*b),
+                                    _ => None,
+                                })
+                                .collect::<Vec<_>>(),
+                        ))
+                    }
+                    ArrowType::Float64 => {
+                        std::sync::Arc::new(Float64Array::from(
+                            values
+                                .map(|v| match v {
+                                    Some(DataType::F64(f)) => Some(*f),
+                                    _ => None,
+                                })
+                                .collect::<Vec<_>>(),
+                        ))
+                    }
+                    ArrowType::Int64 => std::sync::Arc::new(Int64Array::from(
+                        values
+                            .map(|v| match v {
+                                Some(DataType::I64(i)) => Some(*i),
+                                _ => None,
+                            })
+                            .collect::<Vec<_>>(),
+                    )),
+                    ArrowType::UInt64 => {
+                        std::sync::Arc::new(UInt64Array::from(
+                            values
+                                .map(|v| match v {
+                                    Some(DataType::U64(u)) => Some(*u),
+                                    _ => None,
+                                })
+                                .collect::<Vec<_>>(),
+                        ))
+                    }
+                    _ => std::sync::Arc::new(StringArray::from(
+                        values
+                            .map(|v| match v {
+                                Some(DataType::String(s)) => {
+                                    Some(s.as_str().to_string())
+                                }
+                                _ => None,
+                            })
+                            .collect::<Vec<_>>(),
+                    )),
+                };
+                columns.push(array);
+            }
+
+            RecordBatch::try_new(schema, columns).map_err(std::io::Error::other)
+        }
+    }
+
+    struct OpenFile {
+        window_start: u64,
+        layout: Layout,
+        schema: SchemaRef,
+        writer: parquet::arrow::ArrowWriter<std::fs::File>,
+    }
+
+    impl OpenFile {
+        /// Write the footer, which makes the file readable.
+        fn finish(self) -> std::io::Result<()> {
+            let file =
+                self.writer.into_inner().map_err(std::io::Error::other)?;
+            file.sync_data()
+        }
+
+        fn close(self) {
+            if let Err(err) = self.finish() {
+                tracing::warn!(?err, "close metrics file error");
+            }
+        }
+    }
+
+    pub(crate) struct ParquetSink {
+        dir: PathBuf,
+        window: u64,
+        files: HashMap<String, OpenFile>,
+    }
+
+    impl Drop for ParquetSink {
+        fn drop(&mut self) {
+            for (_, file) in self.files.drain() {
+                file.close();
+            }
+        }
+    }
+
+    impl ParquetSink {
+        pub(crate) fn new(dir: PathBuf, window: std::time::Duration) -> Self {
+            Self {
+                dir,
+                window: window.as_secs().max(1),
+                files: HashMap::new(),
+            }
+        }
+
+        fn open(
+            &self,
+            stem: &str,
+            window_start: u64,
+            layout: Layout,
+        ) -> std::io::Result<OpenFile> {
+            let dir = self.dir.join(stem);
+            std::fs::create_dir_all(&dir)?;
+
+            let mut n = 0;
+            let file = loop {
+                let path = if n == 0 {
+                    dir.join(format!("{window_start}.parquet"))
+                } else {
+                    dir.join(format!("{window_start}-{n}.parquet"))
+                };
+                match std::fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&path)
+                {
+                    Ok(file) => break file,
+                    Err(err)
+                        if err.kind() == std::io::ErrorKind::AlreadyExists =>
+                    {
+                        n += 1
+                    }
+                    Err(err) => return Err(err),
+                }
+            };
+
+            let schema = layout.schema();
+            let writer = parquet::arrow::ArrowWriter::try_new(
+                file,
+                schema.clone(),
+                None,
+            )
+            .map_err(std::io::Error::other)?;
+
+            Ok(OpenFile {
+                window_start,
+                layout,
+                schema,
+                writer,
+            })
+        }
+    }
+
+    impl FileSink for ParquetSink {
+        fn write(&mut self, metrics: &[Metric]) -> std::io::Result<()> {
+            let mut order = Vec::new();
+            let mut groups: HashMap<(String, u64), Vec<&Metric>> =
+                HashMap::new();
+            for metric in metrics {
+                let secs =
+                    (time_nanos(metric.timestamp) / 1_000_000_000) as u64;
+                let key = (
+                    file_stem(metric.name.as_str()),
+                    secs - secs % self.window,
+                );
+                groups
+                    .entry(key.clone())
+                    .or_insert_with(|| {
+                        order.push(key);
+                        Vec::new()
+                    })
+                    .push(metric);
+            }
+
+            for key in order {
+                let metrics = groups.remove(&key).unwrap_or_default();
+                let (stem, window_start) = key;
+                let layout = Layout::of(&metrics);
+
+                let mut file = match self.files.remove(&stem) {
+                    Some(file)
+                        if file.window_start == window_start
+                            && file.layout.covers(&layout) =>
+                    {
+                        file
+                    }
+                    Some(file) => {
+                        let mut widened = layout;
+                        if file.window_start == window_start {
+                            let mut old = file.layout.clone();
+                            old.widen(widened);
+                            widened = old;
+                        }
+                        file.close();
+                        self.open(&stem, window_start, widened)?
+                    }
+                    None => self.open(&stem, window_start, layout)?,
+                };
+
+                let batch = file.layout.batch(file.schema.clone(), &metrics)?;
+                let res = file
+                    .writer
+                    .write(&batch)
+                    .and_then(|_| file.writer.flush())
+                    .map_err(std::io::Error::other);
+                self.files.insert(stem, file);
+                res?;
+            }
+
+            // close files whose window has been over for a full window
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            let expired = self
+                .files
+                .iter()
+                .filter(|(_, f)| f.window_start + 2 * self.window <= now)
+                .map(|(stem, _)| stem.clone())
+                .collect::<Vec<_>>();
+            for stem in expired {
+                if let Some(file) = self.files.remove(&stem) {
+                    file.close();
+                }
+            }
+
+            Ok(())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            // a parquet file is only readable once its footer is written
+            let mut res = Ok(());
+            for (_, file) in self.files.drain() {
+                if let Err(err) = file.finish() {
+                    res = Err(err);
+                }
+            }
+            res
+        }
+
+        fn close(&mut self) -> std::io::Result<()> {
+            self.flush()
+        }
+    }
+}
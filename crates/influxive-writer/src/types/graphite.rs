@@ -0,0 +1,183 @@
+use super::*;
+
+/// How metric tags are represented in Graphite paths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum GraphiteTagMode {
+    /// Graphite 1.1+ tagged series, e.g. `my.metric;host=a 3.14 1700000000`.
+    Tagged,
+
+    /// Tag values are prepended to the path in tag order,
+    /// e.g. `a.my.metric 3.14 1700000000` (like Telegraf's default template).
+    Path,
+
+    /// Tags are discarded.
+    Drop,
+}
+
+/// Characters that would break a path segment or tag.
+fn sanitize(s: &str, allow_dot: bool) -> String {
+    s.chars()
+        .map(|c| match c {
+            '.' if allow_dot => c,
+            ' ' | '\t' | '\n' | '\r' | ';' | '!' | '^' | '=' | '~' | '.' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Renders a metric as Graphite plaintext lines, one per numeric field.
+pub(crate) fn metric_to_graphite(
+    prefix: Option<&str>,
+    tag_mode: GraphiteTagMode,
+    metric: Metric,
+    lines: &mut Vec<String>,
+) {
+    let Metric {
+        timestamp,
+        name,
+        fields,
+        tags,
+    } = metric;
+
+    let ts = timestamp
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let mut base = String::new();
+    if let Some(prefix) = prefix {
+        base.push_str(prefix);
+        base.push('.');
+    }
+    if tag_mode == GraphiteTagMode::Path {
+        for (_, v) in tags.iter() {
+            base.push_str(&sanitize(&v.to_string(), false));
+            base.push('.');
+        }
+    }
+    base.push_str(&sanitize(name.as_str(), true));
+
+    let mut suffix = String::new();
+    if tag_mode == GraphiteTagMode::Tagged {
+        for (k, v) in tags.iter() {
+            suffix.push(';');
+            suffix.push_str(&sanitize(k.as_str(), true));
+            suffix.push('=');
+            suffix.push_str(&sanitize(&v.to_string(), true));
+        }
+    }
+
+    for (field, value) in fields {
+        let value = match value {
+            DataType::Bool(b) => (b as u8).to_string(),
+            DataType::F64(f) if f.is_finite() => f.to_string(),
+            DataType::F64(f) => {
+                tracing::debug!(
+                    metric = name.as_str(),
+                    field = field.as_str(),
+                    value = f,
+                    "skipping non-finite graphite value"
+                );
+                continue;
+            }
+            DataType::I64(i) => i.to_string(),
+            DataType::U64(u) => u.to_string(),
+            DataType::String(_) => continue,
+        };
+
+        let path = if field.as_str() == "value" {
+            base.clone()
+        } else {
+            format!("{base}.{}", sanitize(field.as_str(), false))
+        };
+
+        lines.push(format!("{path}{suffix} {value} {ts}\n"));
+    }
+}
+
+/// Write metrics to a Graphite (carbon) plaintext listener over TCP,
+/// as `path value timestamp` lines.
+///
+/// The path is `{prefix}.{name}` for the field `value`, or
+/// `{prefix}.{name}.{field}` otherwise. Tags are mapped according to
+/// [GraphiteTagMode]. String fields and non-finite values (NaN, ±inf)
+/// are skipped. Connection handling is the same as
+/// [LineProtocolSocketBackendFactory].
+#[derive(Debug, Clone)]
+pub struct GraphiteBackendFactory {
+    socket: LineProtocolSocketBackendFactory,
+    prefix: Option<String>,
+    tag_mode: GraphiteTagMode,
+}
+
+impl GraphiteBackendFactory {
+    /// Creates a new instance targeting a carbon plaintext TCP address,
+    /// e.g. `127.0.0.1:2003`.
+    pub fn new<A: Into<String>>(addr: A) -> Self {
+        Self {
+            socket: LineProtocolSocketBackendFactory::new(
+                LineProtocolSocketAddr::Tcp(addr.into()),
+            ),
+            prefix: None,
+            tag_mode: GraphiteTagMode::Tagged,
+        }
+    }
+
+    /// Prefix prepended to every path.
+    /// Defaults to `None`.
+    pub fn with_prefix<P: Into<String>>(mut self, prefix: P) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// How tags are represented.
+    /// Defaults to [GraphiteTagMode::Tagged].
+    pub fn with_tag_mode(mut self, tag_mode: GraphiteTagMode) -> Self {
+        self.tag_mode = tag_mode;
+        self
+    }
+
+    /// See [LineProtocolSocketBackendFactory::with_write_timeout].
+    pub fn with_write_timeout(
+        mut self,
+        write_timeout: std::time::Duration,
+    ) -> Self {
+        self.socket = self.socket.with_write_timeout(write_timeout);
+        self
+    }
+
+    /// See [LineProtocolSocketBackendFactory::with_reconnect_interval].
+    pub fn with_reconnect_interval(
+        mut self,
+        reconnect_interval: std::time::Duration,
+    ) -> Self {
+        self.socket = self.socket.with_reconnect_interval(reconnect_interval);
+        self
+    }
+
+    /// See [LineProtocolSocketBackendFactory::with_max_buffer_bytes].
+    pub fn with_max_buffer_bytes(mut self, max_buffer_bytes: usize) -> Self {
+        self.socket = self.socket.with_max_buffer_bytes(max_buffer_bytes);
+        self
+    }
+}
+
+impl BackendFactory for GraphiteBackendFactory {
+    fn with_token_auth(
+        &self,
+        _host: String,
+        _bucket: String,
+        _token: String,
+    ) -> Box<dyn Backend + 'static + Send + Sync> {
+        let prefix = self.prefix.clone();
+        let tag_mode = self.tag_mode;
+        self.socket.create_backend(Arc::new(move |metric, lines| {
+            metric_to_graphite(prefix.as_deref(), tag_mode, metric, lines)
+        }))
+    }
+}
@@ -0,0 +1,116 @@
+use super::*;
+
+/// Minimal InfluxDB HTTP client reporting write failures with the
+/// response status.
+#[derive(Clone)]
+pub(crate) struct InfluxHttp {
+    client: reqwest::Client,
+    host: String,
+    bucket: String,
+    token: String,
+    org: Option<String>,
+    precision: Precision,
+}
+
+impl InfluxHttp {
+    pub(crate) fn new(host: String, bucket: String, token: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            host: host.trim_end_matches('/').to_string(),
+            bucket,
+            token,
+            org: None,
+            precision: Precision::Nanoseconds,
+        }
+    }
+
+    /// Write to the v2 API (`/api/v2/write`) of this organization,
+    /// rather than the v1 compatibility API (`/write`).
+    pub(crate) fn with_org(mut self, org: Option<String>) -> Self {
+        self.org = org;
+        self
+    }
+
+    /// Authenticate following requests with this token.
+    pub(crate) fn set_token(&mut self, token: String) {
+        self.token = token;
+    }
+
+    /// Send following requests to this host and bucket.
+    pub(crate) fn set_endpoint(&mut self, host: String, bucket: String) {
+        self.host = host.trim_end_matches('/').to_string();
+        self.bucket = bucket;
+    }
+
+    /// Send requests with this client, sharing its connection pool.
+    pub(crate) fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Timestamp precision of the written lines.
+    pub(crate) fn with_precision(mut self, precision: Precision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Write newline-separated Line Protocol with the configured precision.
+    pub(crate) async fn write(&self, body: String) -> BackendResult<()> {
+        let req = match &self.org {
+            Some(org) => self
+                .client
+                .post(format!("{}/api/v2/write", self.host))
+                .query(&[
+                    ("org", org.as_str()),
+                    ("bucket", self.bucket.as_str()),
+                    ("precision", self.precision.as_v2_str()),
+                ]),
+            None => self.client.post(format!("{}/write", self.host)).query(&[
+                ("db", self.bucket.as_str()),
+                ("precision", self.precision.as_v1_str()),
+            ]),
+        };
+
+        let res = req
+            .header("Authorization", format!("Token {}", self.token))
+            .body(body)
+            .send()
+            .await?;
+
+        let status = res.status();
+        if status.is_success() {
+            Ok(())
+        } else {
+            Err(BackendError::Http {
+                status: status.as_u16(),
+                message: res.text().await.unwrap_or_default(),
+            })
+        }
+    }
+
+    /// Check that the server is reachable and answering.
+    pub(crate) async fn ping(&self) -> BackendResult<BackendHealth> {
+        let res = self
+            .client
+            .get(format!("{}/ping", self.host))
+            .send()
+            .await?;
+
+        let status = res.status();
+        if status.is_success() {
+            Ok(BackendHealth {
+                version: res
+                    .headers()
+                    .get("X-Influxdb-Version")
+                    .and_then(|v| v.to_str().ok())
+                    .map(String::from),
+                degraded: false,
+            })
+        } else {
+            Err(BackendError::Http {
+                status: status.as_u16(),
+                message: res.text().await.unwrap_or_default(),
+            })
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use super::*;
+
+/// Timestamp precision of Line Protocol written to InfluxDB over HTTP.
+/// Coarser precisions make for smaller writes, but timestamps are
+/// truncated, so metrics of the same series written within the same
+/// precision interval overwrite each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Precision {
+    /// Nanoseconds, `ns`.
+    #[default]
+    #[cfg_attr(feature = "serde", serde(rename = "ns"))]
+    Nanoseconds,
+
+    /// Microseconds, `us`.
+    #[cfg_attr(feature = "serde", serde(rename = "us"))]
+    Microseconds,
+
+    /// Milliseconds, `ms`.
+    #[cfg_attr(feature = "serde", serde(rename = "ms"))]
+    Milliseconds,
+
+    /// Seconds, `s`.
+    #[cfg_attr(feature = "serde", serde(rename = "s"))]
+    Seconds,
+}
+
+impl Precision {
+    /// The `precision` query parameter of the v1 `/write` API.
+    pub(crate) fn as_v1_str(&self) -> &'static str {
+        match self {
+            Precision::Nanoseconds => "ns",
+            Precision::Microseconds => "u",
+            Precision::Milliseconds => "ms",
+            Precision::Seconds => "s",
+        }
+    }
+
+    /// Nanoseconds per timestamp unit.
+    pub(crate) fn as_nanos(&self) -> u64 {
+        match self {
+            Precision::Nanoseconds => 1,
+            Precision::Microseconds => 1_000,
+            Precision::Milliseconds => 1_000_000,
+            Precision::Seconds => 1_000_000_000,
+        }
+    }
+
+    /// The `precision` query parameter of the v2 `/api/v2/write` API.
+    pub(crate) fn as_v2_str(&self) -> &'static str {
+        match self {
+            Precision::Nanoseconds => "ns",
+            Precision::Microseconds => "us",
+            Precision::Milliseconds => "ms",
+            Precision::Seconds => "s",
+        }
+    }
+}
+
+impl std::str::FromStr for Precision {
+    type Err = std::io::Error;
+
+    /// Parses `ns`, `us` (or `u`), `ms` or `s`.
+    fn from_str(s: &str) -> std::io::Result<Self> {
+        match s {
+            "ns" => Ok(Precision::Nanoseconds),
+            "us" | "u" => Ok(Precision::Microseconds),
+            "ms" => Ok(Precision::Milliseconds),
+            "s" => Ok(Precision::Seconds),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid precision: {s}"),
+            )),
+        }
+    }
+}
+
+struct InfluxHttpBackend {
+    buffer: Vec<String>,
+    precision: Precision,
+    client: http::InfluxHttp,
+}
+
+impl Backend for InfluxHttpBackend {
+    fn buffer_metric(&mut self, metric: Metric) {
+        if let Some(line) = query_to_line(metric_to_query_with_precision(
+            metric,
+            self.precision,
+        )) {
+            self.buffer.push(line);
+        }
+    }
+
+    fn buffer_count(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn send(&mut self) -> BackendFuture<'_, ()> {
+        Box::pin(async move {
+            self.client.write(self.buffer.concat()).await?;
+            self.buffer.clear();
+            Ok(())
+        })
+    }
+
+    fn clear(&mut self) {
+        self.buffer.clear();
+    }
+
+    fn health(&mut self) -> BackendFuture<'_, BackendHealth> {
+        Box::pin(async move { self.client.ping().await })
+    }
+
+    fn set_token(&mut self, token: String) {
+        self.client.set_token(token);
+    }
+
+    fn set_endpoint(&mut self, host: String, bucket: String) {
+        self.client.set_endpoint(host, bucket);
+    }
+}
+
+/// Writes Line Protocol to a running InfluxDB instance over HTTP,
+/// with a configurable organization and timestamp precision.
+///
+/// Without an organization, writes go to the v1 compatibility API
+/// (`/write?db={bucket}`), which InfluxDB 1.x and 2.x both serve.
+/// With an organization, writes go to the v2 API
+/// (`/api/v2/write?org={org}&bucket={bucket}`).
+#[derive(Clone, Default)]
+pub struct InfluxHttpBackendFactory {
+    endpoint: Option<(String, String, String)>,
+    org: Option<String>,
+    precision: Precision,
+    client: Option<reqwest::Client>,
+}
+
+impl std::fmt::Debug for InfluxHttpBackendFactory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // don't leak the token into logs
+        f.debug_struct("InfluxHttpBackendFactory")
+            .field(
+                "endpoint",
+                &self
+                    .endpoint
+                    .as_ref()
+                    .map(|(host, bucket, _)| (host, bucket)),
+            )
+            .field("org", &self.org)
+            .field("precision", &self.precision)
+            .finish()
+    }
+}
+
+impl InfluxHttpBackendFactory {
+    /// Creates a new instance writing to the host, bucket and token
+    /// the writer is created with.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write to this host, bucket and token, ignoring the ones the
+    /// writer is created with.
+    pub fn with_endpoint<H, B, T>(
+        mut self,
+        host: H,
+        bucket: B,
+        token: T,
+    ) -> Self
+    where
+        H: Into<String>,
+        B: Into<String>,
+        T: Into<String>,
+    {
+        self.endpoint = Some((host.into(), bucket.into(), token.into()));
+        self
+    }
+
+    /// Write to the v2 API of this organization.
+    /// Defaults to `None`, writing to the v1 compatibility API.
+    pub fn with_org<O: Into<String>>(mut self, org: O) -> Self {
+        self.org = Some(org.into());
+        self
+    }
+
+    /// Timestamp precision of the written lines.
+    /// Defaults to [Precision::Nanoseconds].
+    pub fn with_precision(mut self, precision: Precision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Send with this client, sharing its connection pool,
+    /// see [InfluxiveWriterPool].
+    pub(crate) fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+}
+
+impl BackendFactory for InfluxHttpBackendFactory {
+    fn with_token_auth(
+        &self,
+        host: String,
+        bucket: String,
+        token: String,
+    ) -> Box<dyn Backend + 'static + Send + Sync> {
+        let (host, bucket, token) =
+            self.endpoint.clone().unwrap_or((host, bucket, token));
+        let mut client = http::InfluxHttp::new(host, bucket, token)
+            .with_org(self.org.clone())
+            .with_precision(self.precision);
+        if let Some(shared) = &self.client {
+            client = client.with_client(shared.clone());
+        }
+        let out: Box<dyn Backend + 'static + Send + Sync> =
+            Box::new(InfluxHttpBackend {
+                buffer: Vec::new(),
+                precision: self.precision,
+                client,
+            });
+        out
+    }
+}
@@ -0,0 +1,349 @@
+use super::*;
+use std::collections::HashMap;
+
+/// The subset of the OpenTelemetry protocol (`opentelemetry/proto`, v1)
+/// needed to export gauge metrics. Declared by hand to avoid a protoc
+/// build dependency, field numbers must match the upstream `.proto` files.
+pub(crate) mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ExportMetricsServiceRequest {
+        #[prost(message, repeated, tag = "1")]
+        pub resource_metrics: Vec<ResourceMetrics>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ResourceMetrics {
+        #[prost(message, optional, tag = "1")]
+        pub resource: Option<Resource>,
+        #[prost(message, repeated, tag = "2")]
+        pub scope_metrics: Vec<ScopeMetrics>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Resource {
+        #[prost(message, repeated, tag = "1")]
+        pub attributes: Vec<KeyValue>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ScopeMetrics {
+        #[prost(message, optional, tag = "1")]
+        pub scope: Option<InstrumentationScope>,
+        #[prost(message, repeated, tag = "2")]
+        pub metrics: Vec<Metric>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct InstrumentationScope {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(string, tag = "2")]
+        pub version: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Metric {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(string, tag = "2")]
+        pub description: String,
+        #[prost(string, tag = "3")]
+        pub unit: String,
+        #[prost(message, optional, tag = "5")]
+        pub gauge: Option<Gauge>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Gauge {
+        #[prost(message, repeated, tag = "1")]
+        pub data_points: Vec<NumberDataPoint>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct NumberDataPoint {
+        #[prost(message, repeated, tag = "7")]
+        pub attributes: Vec<KeyValue>,
+        #[prost(fixed64, tag = "3")]
+        pub time_unix_nano: u64,
+        #[prost(oneof = "number_data_point::Value", tags = "4, 6")]
+        pub value: Option<number_data_point::Value>,
+    }
+
+    pub mod number_data_point {
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum Value {
+            #[prost(double, tag = "4")]
+            AsDouble(f64),
+            #[prost(sfixed64, tag = "6")]
+            AsInt(i64),
+        }
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct KeyValue {
+        #[prost(string, tag = "1")]
+        pub key: String,
+        #[prost(message, optional, tag = "2")]
+        pub value: Option<AnyValue>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct AnyValue {
+        #[prost(oneof = "any_value::Value", tags = "1, 2, 3, 4")]
+        pub value: Option<any_value::Value>,
+    }
+
+    pub mod any_value {
+        // variant names mirror the upstream proto
+        #[allow(clippy::enum_variant_names)]
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum Value {
+            #[prost(string, tag = "1")]
+            StringValue(String),
+            #[prost(bool, tag = "2")]
+            BoolValue(bool),
+            #[prost(int64, tag = "3")]
+            IntValue(i64),
+            #[prost(double, tag = "4")]
+            DoubleValue(f64),
+        }
+    }
+}
+
+fn any_value(value: DataType) -> proto::AnyValue {
+    use proto::any_value::Value;
+
+    proto::AnyValue {
+        value: Some(match value {
+            DataType::Bool(b) => Value::BoolValue(b),
+            DataType::F64(f) => Value::DoubleValue(f),
+            DataType::I64(i) => Value::IntValue(i),
+            DataType::U64(u) => {
+                Value::IntValue(i64::try_from(u).unwrap_or(i64::MAX))
+            }
+            DataType::String(s) => Value::StringValue(s.into_string()),
+        }),
+    }
+}
+
+fn key_value(key: StringType, value: DataType) -> proto::KeyValue {
+    proto::KeyValue {
+        key: key.into_string(),
+        value: Some(any_value(value)),
+    }
+}
+
+/// Converts a batch of Metrics into an OTLP export request. Each field
+/// becomes a gauge named `{name}` for the field `value`, or
+/// `{name}.{field}` otherwise. Tags become data point attributes.
+/// String fields cannot be represented as a number and are skipped.
+pub(crate) fn metrics_to_request(
+    resource: &[(StringType, DataType)],
+    metrics: Vec<Metric>,
+) -> proto::ExportMetricsServiceRequest {
+    use proto::number_data_point::Value;
+
+    let mut order = Vec::new();
+    let mut by_name: HashMap<String, Vec<proto::NumberDataPoint>> =
+        HashMap::new();
+
+    for metric in metrics {
+        let Metric {
+            timestamp,
+            name,
+            fields,
+            tags,
+        } = metric;
+
+        let time_unix_nano = timestamp
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+
+        let attributes = tags
+            .into_iter()
+            .map(|(k, v)| key_value(k, v))
+            .collect::<Vec<_>>();
+
+        for (field, value) in fields {
+            let value = match value {
+                DataType::Bool(b) => Value::AsInt(b as i64),
+                DataType::F64(f) => Value::AsDouble(f),
+                DataType::I64(i) => Value::AsInt(i),
+                DataType::U64(u) => {
+                    Value::AsInt(i64::try_from(u).unwrap_or(i64::MAX))
+                }
+                DataType::String(_) => continue,
+            };
+
+            let metric_name = if field.as_str() == "value" {
+                name.to_string()
+            } else {
+                format!("{name}.{field}")
+            };
+
+            let points =
+                by_name.entry(metric_name.clone()).or_insert_with(|| {
+                    order.push(metric_name);
+                    Vec::new()
+                });
+
+            points.push(proto::NumberDataPoint {
+                attributes: attributes.clone(),
+                time_unix_nano,
+                value: Some(value),
+            });
+        }
+    }
+
+    let metrics = order
+        .into_iter()
+        .map(|name| {
+            let data_points = by_name.remove(&name).unwrap_or_default();
+            proto::Metric {
+                name,
+                description: String::new(),
+                unit: String::new(),
+                gauge: Some(proto::Gauge { data_points }),
+            }
+        })
+        .collect();
+
+    proto::ExportMetricsServiceRequest {
+        resource_metrics: vec![proto::ResourceMetrics {
+            resource: Some(proto::Resource {
+                attributes: resource
+                    .iter()
+                    .map(|(k, v)| key_value(k.clone(), v.clone()))
+                    .collect(),
+            }),
+            scope_metrics: vec![proto::ScopeMetrics {
+                scope: Some(proto::InstrumentationScope {
+                    name: "influxive".to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                }),
+                metrics,
+            }],
+        }],
+    }
+}
+
+struct OtlpHttpBackend {
+    factory: OtlpHttpBackendFactory,
+    buffer: Vec<Metric>,
+    client: reqwest::Client,
+}
+
+impl Backend for OtlpHttpBackend {
+    fn buffer_metric(&mut self, metric: Metric) {
+        self.buffer.push(metric);
+    }
+
+    fn buffer_count(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn send(&mut self) -> BackendFuture<'_, ()> {
+        Box::pin(async move {
+            use prost::Message;
+
+            let request =
+                metrics_to_request(&self.factory.resource, self.buffer.clone());
+
+            let mut req = self
+                .client
+                .post(&self.factory.endpoint)
+                .timeout(self.factory.timeout)
+                .header("Content-Type", "application/x-protobuf")
+                .body(request.encode_to_vec());
+            for (k, v) in self.factory.headers.iter() {
+                req = req.header(k, v);
+            }
+
+            let res = req.send().await?;
+            let status = res.status();
+            if !status.is_success() {
+                return Err(BackendError::Http {
+                    status: status.as_u16(),
+                    message: res.text().await.unwrap_or_default(),
+                });
+            }
+
+            self.buffer.clear();
+            Ok(())
+        })
+    }
+
+    fn clear(&mut self) {
+        self.buffer.clear();
+    }
+}
+
+/// Export metrics as OTLP protobuf over HTTP, e.g. to an
+/// OpenTelemetry Collector at `http://127.0.0.1:4318/v1/metrics`.
+///
+/// Each field is exported as a gauge data point. See
+/// [InfluxiveWriterConfig::create_with_otlp_http].
+#[derive(Debug, Clone)]
+pub struct OtlpHttpBackendFactory {
+    endpoint: String,
+    headers: Vec<(String, String)>,
+    resource: Vec<(StringType, DataType)>,
+    timeout: std::time::Duration,
+}
+
+impl OtlpHttpBackendFactory {
+    /// Creates a new instance posting to the full metrics endpoint url.
+    pub fn new<E: Into<String>>(endpoint: E) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            headers: Vec::new(),
+            resource: Vec::new(),
+            timeout: std::time::Duration::from_secs(10),
+        }
+    }
+
+    /// Add an HTTP header to every export request, e.g. for authorization.
+    pub fn with_header<K: Into<String>, V: Into<String>>(
+        mut self,
+        key: K,
+        value: V,
+    ) -> Self {
+        self.headers.push((key.into(), value.into()));
+        self
+    }
+
+    /// Add a resource attribute, e.g. `service.name`.
+    pub fn with_resource_attribute<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: Into<StringType>,
+        V: Into<DataType>,
+    {
+        self.resource.push((key.into(), value.into()));
+        self
+    }
+
+    /// Timeout for each export request.
+    /// Defaults to `10s`.
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl BackendFactory for OtlpHttpBackendFactory {
+    fn with_token_auth(
+        &self,
+        _host: String,
+        _bucket: String,
+        _token: String,
+    ) -> Box<dyn Backend + 'static + Send + Sync> {
+        let out: Box<dyn Backend + 'static + Send + Sync> =
+            Box::new(OtlpHttpBackend {
+                factory: self.clone(),
+                buffer: Vec::new(),
+                client: reqwest::Client::new(),
+            });
+        out
+    }
+}
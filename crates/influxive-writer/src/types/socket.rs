@@ -0,0 +1,310 @@
+use super::*;
+use std::collections::VecDeque;
+use tokio::io::AsyncWriteExt;
+
+/// Address of a stream socket accepting InfluxDB Line Protocol,
+/// such as a Telegraf `socket_listener` input.
+#[derive(Debug, Clone)]
+pub enum LineProtocolSocketAddr {
+    /// A TCP address, e.g. `127.0.0.1:8094`.
+    Tcp(String),
+
+    /// A unix domain socket path.
+    #[cfg(unix)]
+    Unix(std::path::PathBuf),
+}
+
+enum SocketStream {
+    Tcp(tokio::net::TcpStream),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixStream),
+}
+
+impl SocketStream {
+    async fn connect(addr: &LineProtocolSocketAddr) -> std::io::Result<Self> {
+        match addr {
+            LineProtocolSocketAddr::Tcp(addr) => {
+                let stream = tokio::net::TcpStream::connect(addr).await?;
+                stream.set_nodelay(true)?;
+                Ok(SocketStream::Tcp(stream))
+            }
+            #[cfg(unix)]
+            LineProtocolSocketAddr::Unix(path) => Ok(SocketStream::Unix(
+                tokio::net::UnixStream::connect(path).await?,
+            )),
+        }
+    }
+
+    /// The listener never writes to us, so a readable EOF means the
+    /// remote end has gone away. Without this check, the first write
+    /// after a remote close would "succeed" into the kernel buffer
+    /// and be silently lost.
+    fn is_closed(&self) -> bool {
+        let mut buf = [0; 1];
+        let res = match self {
+            SocketStream::Tcp(s) => s.try_read(&mut buf),
+            #[cfg(unix)]
+            SocketStream::Unix(s) => s.try_read(&mut buf),
+        };
+        match res {
+            Ok(0) => true,
+            Ok(_) => false,
+            Err(err) => err.kind() != std::io::ErrorKind::WouldBlock,
+        }
+    }
+
+    async fn write_all(&mut self, data: &[u8]) -> std::io::Result<()> {
+        match self {
+            SocketStream::Tcp(s) => {
+                s.write_all(data).await?;
+                s.flush().await
+            }
+            #[cfg(unix)]
+            SocketStream::Unix(s) => {
+                s.write_all(data).await?;
+                s.flush().await
+            }
+        }
+    }
+}
+
+/// Renders a metric as zero or more newline-terminated lines.
+pub(crate) type LineEncoder =
+    Arc<dyn Fn(Metric, &mut Vec<String>) + 'static + Send + Sync>;
+
+struct SocketBackend {
+    factory: LineProtocolSocketBackendFactory,
+    encode: LineEncoder,
+    buffer: Vec<Metric>,
+    pending: VecDeque<String>,
+    pending_bytes: usize,
+    stream: Option<SocketStream>,
+    last_connect: Option<std::time::Instant>,
+}
+
+impl SocketBackend {
+    fn push_pending(&mut self, line: String) {
+        self.pending_bytes += line.len();
+        self.pending.push_back(line);
+
+        let mut dropped = 0;
+        while self.pending_bytes > self.factory.max_buffer_bytes {
+            match self.pending.pop_front() {
+                Some(line) => {
+                    self.pending_bytes -= line.len();
+                    dropped += 1;
+                }
+                None => break,
+            }
+        }
+
+        if dropped > 0 {
+            tracing::warn!(
+                dropped,
+                "socket metrics buffer full, dropping oldest metrics"
+            );
+        }
+    }
+
+    async fn connect(&mut self) -> std::io::Result<()> {
+        if let Some(stream) = &self.stream {
+            if !stream.is_closed() {
+                return Ok(());
+            }
+            tracing::debug!("metrics socket closed by remote");
+            self.stream = None;
+        }
+
+        // the writer may retry sooner than we want to reconnect
+        if let Some(last_connect) = self.last_connect {
+            if let Some(wait) = self
+                .factory
+                .reconnect_interval
+                .checked_sub(last_connect.elapsed())
+            {
+                tokio::time::sleep(wait).await;
+            }
+        }
+        self.last_connect = Some(std::time::Instant::now());
+
+        let stream = tokio::time::timeout(
+            self.factory.write_timeout,
+            SocketStream::connect(&self.factory.addr),
+        )
+        .await
+        .map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "metrics socket connect timeout",
+            )
+        })??;
+        self.stream = Some(stream);
+        Ok(())
+    }
+}
+
+impl Backend for SocketBackend {
+    fn buffer_metric(&mut self, metric: Metric) {
+        self.buffer.push(metric);
+    }
+
+    fn buffer_count(&self) -> usize {
+        // include lines held while disconnected so the writer keeps
+        // triggering sends, which drives reconnect attempts
+        self.buffer.len() + self.pending.len()
+    }
+
+    fn send(&mut self) -> BackendFuture<'_, ()> {
+        Box::pin(async move {
+            let mut lines = Vec::new();
+            for metric in std::mem::take(&mut self.buffer) {
+                (self.encode)(metric, &mut lines);
+            }
+            for line in lines {
+                self.push_pending(line);
+            }
+
+            if self.pending.is_empty() {
+                return Ok(());
+            }
+
+            // pending lines are kept until a write succeeds,
+            // the writer retries the failed send
+            if let Err(err) = self.connect().await {
+                tracing::debug!(?err, "metrics socket connect error");
+                return Err(err.into());
+            }
+
+            let mut data = Vec::with_capacity(self.pending_bytes);
+            for line in self.pending.iter() {
+                data.extend_from_slice(line.as_bytes());
+            }
+
+            let stream = match &mut self.stream {
+                Some(stream) => stream,
+                None => return Err(err_other("metrics socket closed").into()),
+            };
+
+            let res = match tokio::time::timeout(
+                self.factory.write_timeout,
+                stream.write_all(&data),
+            )
+            .await
+            {
+                Ok(res) => res,
+                Err(_) => Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "write metrics timeout",
+                )),
+            };
+
+            match res {
+                Ok(()) => {
+                    self.pending.clear();
+                    self.pending_bytes = 0;
+                    Ok(())
+                }
+                Err(err) => {
+                    self.stream = None;
+                    Err(err.into())
+                }
+            }
+        })
+    }
+
+    fn clear(&mut self) {
+        self.buffer.clear();
+        self.pending.clear();
+        self.pending_bytes = 0;
+    }
+}
+
+/// Write InfluxDB Line Protocol to a stream socket (TCP or unix domain),
+/// such as a Telegraf `socket_listener` input.
+///
+/// If the socket is unavailable, sends fail with
+/// [BackendError::Io] and the writer retries them, see
+/// [InfluxiveWriterConfig::max_retries]. Lines are held in memory
+/// (up to [LineProtocolSocketBackendFactory::with_max_buffer_bytes])
+/// until a write succeeds or the writer gives up on them. A write that
+/// fails partway may be repeated after reconnecting, so delivery is
+/// at-least-once.
+#[derive(Debug, Clone)]
+pub struct LineProtocolSocketBackendFactory {
+    addr: LineProtocolSocketAddr,
+    write_timeout: std::time::Duration,
+    reconnect_interval: std::time::Duration,
+    max_buffer_bytes: usize,
+}
+
+impl LineProtocolSocketBackendFactory {
+    /// Creates a new instance targeting the provided socket address.
+    pub fn new(addr: LineProtocolSocketAddr) -> Self {
+        Self {
+            addr,
+            write_timeout: std::time::Duration::from_secs(5),
+            reconnect_interval: std::time::Duration::from_secs(1),
+            max_buffer_bytes: 4 * 1024 * 1024,
+        }
+    }
+
+    /// Timeout applied to connecting and to each batch write.
+    /// Defaults to `5s`.
+    pub fn with_write_timeout(
+        mut self,
+        write_timeout: std::time::Duration,
+    ) -> Self {
+        self.write_timeout = write_timeout;
+        self
+    }
+
+    /// Minimum time between connection attempts while disconnected.
+    /// Defaults to `1s`.
+    pub fn with_reconnect_interval(
+        mut self,
+        reconnect_interval: std::time::Duration,
+    ) -> Self {
+        self.reconnect_interval = reconnect_interval;
+        self
+    }
+
+    /// Max bytes of line protocol to hold while disconnected.
+    /// Beyond this, the oldest lines are dropped.
+    /// Defaults to `4MiB`.
+    pub fn with_max_buffer_bytes(mut self, max_buffer_bytes: usize) -> Self {
+        self.max_buffer_bytes = max_buffer_bytes;
+        self
+    }
+
+    /// Create a backend writing lines rendered by `encode`
+    /// using these connection settings.
+    pub(crate) fn create_backend(
+        &self,
+        encode: LineEncoder,
+    ) -> Box<dyn Backend + 'static + Send + Sync> {
+        Box::new(SocketBackend {
+            factory: self.clone(),
+            encode,
+            buffer: Vec::new(),
+            pending: VecDeque::new(),
+            pending_bytes: 0,
+            stream: None,
+            last_connect: None,
+        })
+    }
+}
+
+impl BackendFactory for LineProtocolSocketBackendFactory {
+    fn with_token_auth(
+        &self,
+        _host: String,
+        _bucket: String,
+        _token: String,
+    ) -> Box<dyn Backend + 'static + Send + Sync> {
+        self.create_backend(Arc::new(|metric, lines| {
+            if let Some(line) = query_to_line(metric_to_query(metric)) {
+                lines.push(line);
+            }
+        }))
+    }
+}
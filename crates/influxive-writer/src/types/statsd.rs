@@ -0,0 +1,205 @@
+use super::*;
+
+/// StatsD dialect to emit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum StatsdFlavor {
+    /// Plain (etsy) StatsD, tags are discarded, e.g. `my.metric:3.14|g`.
+    Statsd,
+
+    /// DogStatsD, tags are appended, e.g. `my.metric:3.14|g|#host:a`.
+    DogStatsd,
+}
+
+/// Characters that are part of the datagram syntax.
+fn sanitize(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            ':' | '|' | '@' | '#' | ',' | '\n' | ' ' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Renders a metric as StatsD gauge lines, one per numeric field.
+pub(crate) fn metric_to_statsd(
+    prefix: Option<&str>,
+    flavor: StatsdFlavor,
+    metric: Metric,
+    lines: &mut Vec<String>,
+) {
+    let Metric {
+        name, fields, tags, ..
+    } = metric;
+
+    let mut base = String::new();
+    if let Some(prefix) = prefix {
+        base.push_str(prefix);
+        base.push('.');
+    }
+    base.push_str(&sanitize(name.as_str()));
+
+    let mut suffix = String::new();
+    if flavor == StatsdFlavor::DogStatsd && !tags.is_empty() {
+        suffix.push_str("|#");
+        for (i, (k, v)) in tags.iter().enumerate() {
+            if i > 0 {
+                suffix.push(',');
+            }
+            suffix.push_str(&sanitize(k.as_str()));
+            suffix.push(':');
+            suffix.push_str(&sanitize(&v.to_string()));
+        }
+    }
+
+    for (field, value) in fields {
+        let value = match value {
+            DataType::Bool(b) => (b as u8).to_string(),
+            DataType::F64(f) if f.is_finite() => f.to_string(),
+            DataType::F64(f) => {
+                tracing::debug!(
+                    metric = name.as_str(),
+                    field = field.as_str(),
+                    value = f,
+                    "skipping non-finite statsd value"
+                );
+                continue;
+            }
+            DataType::I64(i) => i.to_string(),
+            DataType::U64(u) => u.to_string(),
+            DataType::String(_) => continue,
+        };
+
+        let name = if field.as_str() == "value" {
+            base.clone()
+        } else {
+            format!("{base}.{}", sanitize(field.as_str()))
+        };
+
+        // plain StatsD reads a signed gauge as a change of the value,
+        // so set it to zero first. as one entry, both lines are always
+        // sent in the same datagram
+        if flavor == StatsdFlavor::Statsd && value.starts_with('-') {
+            lines.push(format!("{name}:0|g\n{name}:{value}|g"));
+        } else {
+            lines.push(format!("{name}:{value}|g{suffix}"));
+        }
+    }
+}
+
+struct StatsdBackend {
+    factory: StatsdBackendFactory,
+    buffer: Vec<Metric>,
+    sender: UdpLineSender,
+}
+
+impl Backend for StatsdBackend {
+    fn buffer_metric(&mut self, metric: Metric) {
+        self.buffer.push(metric);
+    }
+
+    fn buffer_count(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn send(&mut self) -> BackendFuture<'_, ()> {
+        Box::pin(async move {
+            let mut lines = Vec::new();
+            for metric in self.buffer.iter().cloned() {
+                metric_to_statsd(
+                    self.factory.prefix.as_deref(),
+                    self.factory.flavor,
+                    metric,
+                    &mut lines,
+                );
+            }
+
+            self.sender.send(lines, "\n").await?;
+
+            self.buffer.clear();
+            Ok(())
+        })
+    }
+
+    fn clear(&mut self) {
+        self.buffer.clear();
+    }
+}
+
+/// Write metrics as StatsD or DogStatsD gauge datagrams over UDP.
+///
+/// The name is `{prefix}.{name}` for the field `value`, or
+/// `{prefix}.{name}.{field}` otherwise. String fields and non-finite
+/// values (NaN, ±inf) are skipped.
+/// Plain StatsD servers interpret signed gauge values as decrements, so
+/// for [StatsdFlavor::Statsd] a negative value is sent as `0|g`, then
+/// the value, e.g. `my.metric:0|g` and `my.metric:-5|g`.
+///
+/// As with [LineProtocolUdpBackendFactory], metrics that are lost in
+/// transit, or that nobody is listening for, are not reported as errors.
+#[derive(Debug, Clone)]
+pub struct StatsdBackendFactory {
+    addr: String,
+    prefix: Option<String>,
+    flavor: StatsdFlavor,
+    max_packet_size: usize,
+}
+
+impl StatsdBackendFactory {
+    /// Creates a new instance targeting a StatsD UDP address,
+    /// e.g. `127.0.0.1:8125`.
+    pub fn new<A: Into<String>>(addr: A) -> Self {
+        Self {
+            addr: addr.into(),
+            prefix: None,
+            flavor: StatsdFlavor::DogStatsd,
+            max_packet_size: 1432,
+        }
+    }
+
+    /// Prefix prepended to every metric name.
+    /// Defaults to `None`.
+    pub fn with_prefix<P: Into<String>>(mut self, prefix: P) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// StatsD dialect to emit.
+    /// Defaults to [StatsdFlavor::DogStatsd].
+    pub fn with_flavor(mut self, flavor: StatsdFlavor) -> Self {
+        self.flavor = flavor;
+        self
+    }
+
+    /// Max datagram payload size, multiple lines are packed into
+    /// each datagram up to this size.
+    /// Defaults to `1432` (fits a 1500 byte ethernet MTU).
+    pub fn with_max_packet_size(mut self, max_packet_size: usize) -> Self {
+        self.max_packet_size = max_packet_size;
+        self
+    }
+}
+
+impl BackendFactory for StatsdBackendFactory {
+    fn with_token_auth(
+        &self,
+        _host: String,
+        _bucket: String,
+        _token: String,
+    ) -> Box<dyn Backend + 'static + Send + Sync> {
+        let out: Box<dyn Backend + 'static + Send + Sync> =
+            Box::new(StatsdBackend {
+                factory: self.clone(),
+                buffer: Vec::new(),
+                sender: UdpLineSender::new(
+                    self.addr.clone(),
+                    self.max_packet_size,
+                ),
+            });
+        out
+    }
+}
@@ -0,0 +1,164 @@
+use super::*;
+
+/// A lazily bound UDP socket packing lines into datagrams.
+pub(crate) struct UdpLineSender {
+    addr: String,
+    max_packet_size: usize,
+    socket: Option<tokio::net::UdpSocket>,
+}
+
+impl UdpLineSender {
+    pub(crate) fn new(addr: String, max_packet_size: usize) -> Self {
+        Self {
+            addr,
+            max_packet_size,
+            socket: None,
+        }
+    }
+
+    async fn socket(&mut self) -> std::io::Result<&tokio::net::UdpSocket> {
+        if self.socket.is_none() {
+            let addr = tokio::net::lookup_host(&self.addr)
+                .await?
+                .next()
+                .ok_or_else(|| err_other("udp address did not resolve"))?;
+            let bind: std::net::SocketAddr = if addr.is_ipv4() {
+                ([0, 0, 0, 0], 0).into()
+            } else {
+                ([0, 0, 0, 0, 0, 0, 0, 0], 0).into()
+            };
+            let socket = tokio::net::UdpSocket::bind(bind).await?;
+            socket.connect(addr).await?;
+            self.socket = Some(socket);
+        }
+        Ok(self.socket.as_ref().unwrap())
+    }
+
+    /// Send the lines, joined by `separator`, packing as many lines
+    /// as fit into each datagram. A line larger than the max packet
+    /// size is sent in a datagram of its own.
+    pub(crate) async fn send(
+        &mut self,
+        lines: Vec<String>,
+        separator: &str,
+    ) -> std::io::Result<()> {
+        let max = self.max_packet_size;
+        let mut packets: Vec<String> = Vec::new();
+        for line in lines {
+            match packets.last_mut() {
+                Some(p) if p.len() + separator.len() + line.len() <= max => {
+                    p.push_str(separator);
+                    p.push_str(&line);
+                }
+                _ => packets.push(line),
+            }
+        }
+
+        if !packets.is_empty() {
+            let socket = self.socket().await?;
+            for packet in packets {
+                send_packet(socket, packet.as_bytes()).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Send a datagram on a connected socket. An ICMP port unreachable for
+/// an earlier datagram is reported by a following send as connection
+/// refused, which consumes the error without sending. Nobody listening
+/// is not an error for UDP, so the datagram is sent again.
+async fn send_packet(
+    socket: &tokio::net::UdpSocket,
+    packet: &[u8],
+) -> std::io::Result<()> {
+    for _ in 0..2 {
+        match socket.send(packet).await {
+            Ok(_) => return Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::ConnectionRefused => {
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+struct LineProtocolUdpBackend {
+    buffer: Vec<String>,
+    sender: UdpLineSender,
+}
+
+impl Backend for LineProtocolUdpBackend {
+    fn buffer_metric(&mut self, metric: Metric) {
+        if let Some(line) = query_to_line(metric_to_query(metric)) {
+            self.buffer.push(line);
+        }
+    }
+
+    fn buffer_count(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn send(&mut self) -> BackendFuture<'_, ()> {
+        Box::pin(async move {
+            // lines are already newline-terminated
+            self.sender.send(self.buffer.clone(), "").await?;
+            self.buffer.clear();
+            Ok(())
+        })
+    }
+
+    fn clear(&mut self) {
+        self.buffer.clear();
+    }
+}
+
+/// Write Line Protocol datagrams over UDP, e.g. to an InfluxDB 1.x
+/// UDP listener or a Telegraf `socket_listener` at `127.0.0.1:8089`.
+///
+/// UDP is fire-and-forget: metrics that are lost in transit, or that
+/// nobody is listening for, are not reported as errors.
+#[derive(Debug, Clone)]
+pub struct LineProtocolUdpBackendFactory {
+    addr: String,
+    max_packet_size: usize,
+}
+
+impl LineProtocolUdpBackendFactory {
+    /// Creates a new instance targeting a UDP address,
+    /// e.g. `127.0.0.1:8089`.
+    pub fn new<A: Into<String>>(addr: A) -> Self {
+        Self {
+            addr: addr.into(),
+            max_packet_size: 1432,
+        }
+    }
+
+    /// Max datagram payload size, multiple lines are packed into
+    /// each datagram up to this size.
+    /// Defaults to `1432` (fits a 1500 byte ethernet MTU).
+    pub fn with_max_packet_size(mut self, max_packet_size: usize) -> Self {
+        self.max_packet_size = max_packet_size;
+        self
+    }
+}
+
+impl BackendFactory for LineProtocolUdpBackendFactory {
+    fn with_token_auth(
+        &self,
+        _host: String,
+        _bucket: String,
+        _token: String,
+    ) -> Box<dyn Backend + 'static + Send + Sync> {
+        let out: Box<dyn Backend + 'static + Send + Sync> =
+            Box::new(LineProtocolUdpBackend {
+                buffer: Vec::new(),
+                sender: UdpLineSender::new(
+                    self.addr.clone(),
+                    self.max_packet_size,
+                ),
+            });
+        out
+    }
+}
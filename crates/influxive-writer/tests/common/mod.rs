@@ -1,3 +0,0 @@
-pub mod telegraf_binaries;
-pub mod telegraf_influx_file_conf;
-pub mod telegraf_svc;
@@ -1,39 +1,17 @@
-use crate::common::telegraf_influx_file_conf::TelegrafLineProtocolConfig;
-use crate::common::telegraf_svc::TelegrafSvc;
-use influxive_child_svc::{InfluxiveChildSvc, InfluxiveChildSvcConfig};
 use influxive_core::*;
+use influxive_mock::{InfluxiveMock, InfluxiveMockConfig};
+use influxive_writer::types::BackendFactory;
 use influxive_writer::*;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::time::Duration;
 
-mod common;
-
 /// Setup [`InfluxiveWriter`] to use [`LineProtocolFileBackendFactory`]
 pub fn create_influx_file_writer(test_path: &PathBuf) -> InfluxiveWriter {
-    let _ = std::fs::remove_file(&test_path);
+    let _ = std::fs::remove_file(test_path);
     let mut config =
         InfluxiveWriterConfig::create_with_influx_file(test_path.clone());
     config.batch_duration = std::time::Duration::from_millis(30);
-    let writer = InfluxiveWriter::with_token_auth(config.clone(), "", "", "");
-    writer
-}
-
-/// Spawn influxDB with the default config
-async fn spawn_influx(path: &Path) -> InfluxiveChildSvc {
-    let child = InfluxiveChildSvc::new(
-        InfluxiveChildSvcConfig::default()
-            .with_database_path(Some(path.into()))
-            .with_metric_write(
-                InfluxiveWriterConfig::default()
-                    .with_batch_duration(std::time::Duration::from_millis(5)),
-            ),
-    )
-    .await
-    .unwrap();
-
-    child.ping().await.unwrap();
-
-    child
+    InfluxiveWriter::with_token_auth(config.clone(), "", "", "")
 }
 
 async fn write_metrics_to_file(test_path: &PathBuf) {
@@ -69,7 +47,7 @@ async fn write_metrics_to_file(test_path: &PathBuf) {
     tokio::time::timeout(std::time::Duration::from_millis(1000), async {
         loop {
             // Make sure metrics have been written to disk
-            let file = std::fs::File::open(&test_path).unwrap();
+            let file = std::fs::File::open(test_path).unwrap();
             let reader = std::io::BufReader::new(file);
             let count = reader.lines().count();
             if count == 11 {
@@ -84,65 +62,47 @@ async fn write_metrics_to_file(test_path: &PathBuf) {
 
 #[tokio::test(flavor = "multi_thread")]
 async fn write_to_file_then_read() {
-    let test_dir = tempfile::tempdir().unwrap().path().to_owned();
-    std::fs::create_dir_all(&test_dir).unwrap();
-    let metrics_path = test_dir.join("test_metrics.influx");
-    let telegraf_config_path = test_dir.join("test_telegraf.conf");
+    let test_dir = tempfile::tempdir().unwrap();
+    let metrics_path = test_dir.path().join("test_metrics.influx");
+    let checkpoint_path = test_dir.path().join("test_metrics.checkpoint");
 
     // Write metrics to disk
     write_metrics_to_file(&metrics_path).await;
 
-    // Launch influxDB
-    let influx_process = spawn_influx(&test_dir).await;
+    // Launch an in-memory InfluxDB
+    let mock = InfluxiveMock::new(InfluxiveMockConfig::default())
+        .await
+        .unwrap();
 
-    // Generate Telegraf config
-    let config = TelegrafLineProtocolConfig::new(
-        influx_process.get_host(),
-        influx_process.get_token(),
-        "influxive",
-        "influxive",
-        metrics_path.to_str().unwrap(),
+    // Replay the file, twice to check the checkpoint prevents duplicates
+    let mut backend = types::InfluxHttpBackendFactory::new().with_token_auth(
+        mock.get_host(),
+        mock.get_bucket(),
+        String::new(),
+    );
+    let replay = LineProtocolReplay::new(metrics_path.clone())
+        .with_checkpoint_path(Some(checkpoint_path));
+    let progress = replay.to_backend(&mut *backend).await.unwrap();
+    assert_eq!(11, progress.metrics);
+    let progress = replay.to_backend(&mut *backend).await.unwrap();
+    assert_eq!(0, progress.metrics);
+    assert_eq!(
+        std::fs::metadata(&metrics_path).unwrap().len(),
+        progress.offset
     );
-    assert!(config.write_to_file(telegraf_config_path.as_path()).is_ok());
-
-    // Launch Telegraf
-    let _telegraf_process = TelegrafSvc::spawn(
-        telegraf_config_path.to_str().unwrap(),
-        test_dir.to_str().unwrap(),
-        true,
-    )
-    .await
-    .unwrap();
 
-    // Wait for telegraf to process by querying influxDB every second until we get the expected
-    // result or a timeout
-    let mut line_count = 0;
-    let res = tokio::time::timeout(std::time::Duration::from_secs(20), async {
-        loop {
-            let result = influx_process
-                .query(
-                    r#"from(bucket: "influxive")
+    let result = mock
+        .query(
+            r#"from(bucket: "influxive")
 |> range(start: -15m, stop: now())
 |> filter(fn: (r) => r["_measurement"] == "my-second-metric")
 |> filter(fn: (r) => r["_field"] == "val")"#,
-                )
-                .await
-                .unwrap();
-
-            line_count = result
-                .split('\n')
-                .filter(|l| l.contains("my-second-metric"))
-                .count();
-            if line_count == 10 {
-                break;
-            }
-            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-        }
-    })
-    .await
-    .unwrap_or_else(|_| {
-        panic!(
-            "Error: Test timed out. line_count = {line_count} ; Expected: 10"
         )
-    });
+        .unwrap();
+
+    let line_count = result
+        .split('\n')
+        .filter(|l| l.contains("my-second-metric"))
+        .count();
+    assert_eq!(10, line_count, "{result}");
 }
@@ -7,7 +7,7 @@
 //!
 //! ### Easy, zero-configuration InfluxDB as a child process
 //!
-//! ```
+//! ```rust,no_run
 //! # #[tokio::main(flavor = "multi_thread")]
 //! # async fn main() {
 //! let tmp = tempfile::tempdir().unwrap();
@@ -103,6 +103,18 @@ pub use influxive_writer::InfluxiveWriterConfig;
 #[doc(inline)]
 pub use influxive_otel::InfluxiveMeterProviderConfig;
 
+#[cfg(feature = "serde")]
+#[doc(inline)]
+pub use influxive_child_svc::InfluxiveChildSvcConfigModel;
+
+#[cfg(feature = "serde")]
+#[doc(inline)]
+pub use influxive_writer::InfluxiveWriterConfigModel;
+
+#[cfg(feature = "serde")]
+#[doc(inline)]
+pub use influxive_otel::InfluxiveMeterProviderConfigModel;
+
 /// Create an opentelemetry_api MeterProvider ready to provide metrics
 /// to a running child process instance of InfluxDB.
 pub async fn influxive_child_process_meter_provider(
@@ -23,7 +23,7 @@ async fn file_meter_provider_one_metric_one_value() {
         .init();
 
     // make a recording
-    m.record(3.14, &[]);
+    m.record(2.5, &[]);
 
     // Wait for the metric to be written
     tokio::time::sleep(std::time::Duration::from_millis(200)).await;
@@ -36,5 +36,5 @@ async fn file_meter_provider_one_metric_one_value() {
     let line = res.unwrap();
     let split = line.split(' ').collect::<Vec<&str>>();
     assert_eq!(split[0], "my.metric");
-    assert!(split[1].contains(&"3.14"));
+    assert!(split[1].contains("2.5"));
 }